@@ -1,7 +1,19 @@
 use anyhow::{Result, anyhow};
 use clap::{Parser, Subcommand};
 use pwsp_lib::{
-    types::socket::Request,
+    types::{
+        buses::BusInfo,
+        doctor::{Finding, diagnose},
+        effects::Effect,
+        limiter::LimiterMode,
+        pipewire::{DeviceEvent, DeviceEventKind, Port, PwGraph, PwLink, PwNode},
+        random::{RandomMode, RandomSource},
+        recorder::{RecordFormat, RecordSource, SilenceSplit},
+        socket::Request,
+        tts::TtsEngine,
+        voice_changer::VoicePreset,
+        voices::VoiceStealing,
+    },
     utils::daemon::{make_request, wait_for_daemon},
 };
 use std::path::PathBuf;
@@ -15,7 +27,7 @@ struct Cli {
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    /// Perform an action (ping, pause, resume, toggle-pause, stop, play)
+    /// Perform an action (ping, pause, resume, toggle-pause, stop, play, play-random)
     Action {
         #[clap(subcommand)]
         action: Actions,
@@ -30,6 +42,10 @@ enum Commands {
         #[clap(subcommand)]
         parameter: SetCommands,
     },
+    /// Print audio sources as they are added or removed, until interrupted
+    WatchDevices,
+    /// Check that the virtual mics, streams and links the daemon needs are all in place
+    Doctor,
 }
 
 #[derive(Subcommand, Debug)]
@@ -63,6 +79,45 @@ enum Actions {
         file_path: PathBuf,
         #[clap(short, long)]
         concurrent: bool,
+        /// Stop other sounds of this group when starting
+        #[clap(long)]
+        choke_group: Option<String>,
+        /// Buses to play on (comma separated), every bus when omitted. Only the default bus
+        /// is heard on the default output
+        #[clap(short, long, value_delimiter = ',')]
+        bus: Vec<String>,
+    },
+    /// Speak text through the virtual mic with the configured TTS engine
+    Say {
+        #[clap(required = true, num_args = 1..)]
+        text: Vec<String>,
+        #[clap(short, long)]
+        concurrent: bool,
+        /// Buses to play on (comma separated), every bus when omitted. Only the default bus
+        /// is heard on the default output
+        #[clap(short, long, value_delimiter = ',')]
+        bus: Vec<String>,
+    },
+    /// Play a random file from a directory, a list of files or a tag
+    PlayRandom {
+        #[clap(long, conflicts_with_all = ["files", "tag"])]
+        dir: Option<PathBuf>,
+        #[clap(long, num_args = 1.., conflicts_with = "tag")]
+        files: Vec<PathBuf>,
+        #[clap(long)]
+        tag: Option<String>,
+        /// uniform, shuffle or round-robin
+        #[clap(short, long, default_value = "uniform")]
+        mode: String,
+        #[clap(short, long)]
+        concurrent: bool,
+        /// Stop other sounds of this group when starting
+        #[clap(long)]
+        choke_group: Option<String>,
+        /// Buses to play on (comma separated), every bus when omitted. Only the default bus
+        /// is heard on the default output
+        #[clap(short, long, value_delimiter = ',')]
+        bus: Vec<String>,
     },
     /// Toggle loop
     ToggleLoop {
@@ -77,6 +132,44 @@ enum Actions {
     ClearHotkey { slot: String },
     /// Clear the key chord for a hotkey slot
     ClearHotkeyKey { slot: String },
+    /// Delete a saved effect preset
+    DeleteEffectPreset { name: String },
+    /// Turn the voice changer on the microphone on or off
+    ToggleVoiceChanger,
+    /// Delete a voice changer preset
+    DeleteVoicePreset { name: String },
+    /// Record to the PWSP audio dir until stop-recording
+    StartRecording {
+        /// mix (the virtual mic), mic (before the voice changer) or sounds
+        #[clap(short, long, default_value = "mix")]
+        source: String,
+        /// Bus to record the mix or sounds of, the default one when omitted
+        #[clap(short, long)]
+        bus: Option<String>,
+        /// wav, flac or ogg
+        #[clap(short, long, default_value = "wav")]
+        format: String,
+        /// Start a new clip after a stretch of silence
+        #[clap(long)]
+        split: bool,
+        /// Level below which the recording counts as silent, in dBFS
+        #[clap(long, requires = "split", allow_hyphen_values = true)]
+        split_threshold: Option<f32>,
+        /// How long the silence has to last, in milliseconds
+        #[clap(long, requires = "split")]
+        split_silence_ms: Option<u32>,
+    },
+    /// Stop recording and print the saved clips
+    StopRecording,
+    /// Save what the replay buffer holds to the library
+    SaveReplay {
+        /// Only the last seconds of the buffer
+        #[clap(short, long)]
+        seconds: Option<f32>,
+        /// Bind the clip to this hotkey slot
+        #[clap(long)]
+        slot: Option<String>,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -116,6 +209,35 @@ enum GetCommands {
     FullState,
     /// All hotkey slots
     Hotkeys,
+    /// Peak and RMS levels of the master bus, the microphone and every track
+    Levels,
+    /// Configured and negotiated sample rate, quantum and channels of the streams
+    AudioInfo,
+    /// Memory budget and contents of the decoded sample cache
+    SampleCache,
+    /// Virtual mics the sounds can be routed to
+    Buses,
+    /// Capture streams of apps the sounds can be linked into directly
+    AppStreams,
+    /// PipeWire nodes the daemon keeps track of
+    PwNodes,
+    /// Ports of those nodes
+    PwPorts,
+    /// Every link in the PipeWire graph
+    PwLinks,
+    /// Master limiter settings and current gain reduction
+    Limiter,
+    /// Settings of a sound file (tags, ...)
+    SoundSettings { file_path: PathBuf },
+    /// Effect chain of a track, or of the master bus without --id
+    Effects {
+        #[clap(short, long)]
+        id: Option<u32>,
+    },
+    /// Saved effect presets
+    EffectPresets,
+    /// Voice changer state and presets
+    VoiceChanger,
 }
 
 #[derive(Subcommand, Debug)]
@@ -134,11 +256,39 @@ enum SetCommands {
         #[clap(short, long)]
         id: Option<u32>,
     },
-    /// Audio input id (see pwsp-cli get inputs)
-    Input { name: String },
+    /// Playback speed, changes pitch along with tempo (0.25 - 4.0)
+    Speed {
+        speed: f32,
+        #[clap(short, long)]
+        id: Option<u32>,
+    },
+    /// Pitch shift in semitones, keeps tempo (-24 - 24)
+    Pitch {
+        #[clap(allow_hyphen_values = true)]
+        semitones: f32,
+        #[clap(short, long)]
+        id: Option<u32>,
+    },
+    /// Audio input by node name (see pwsp-cli get inputs), bus-path:<device.bus-path> or
+    /// serial:<object.serial>, which is kept as the node name it finds
+    Input {
+        name: String,
+        /// Match part of the name or description instead, failing when several devices match
+        #[clap(long)]
+        fuzzy: bool,
+    },
+    /// Channels of the input device used as the microphone, e.g. AUX2 or FL,FR
+    /// (none mixes all of them down)
+    InputChannels {
+        #[clap(value_delimiter = ',')]
+        channels: Vec<String>,
+    },
     /// Enable or disable loop (true or false)
     Loop {
         enabled: String,
+        /// Stop looping after the sound played this many times
+        #[clap(short, long)]
+        count: Option<u32>,
         #[clap(short, long)]
         id: Option<u32>,
     },
@@ -152,6 +302,112 @@ enum SetCommands {
         action: String,
         key_chord: String,
     },
+    /// Limit how many tracks started from a hotkey slot play at once (omit to remove the limit)
+    HotkeyMaxVoices {
+        slot: String,
+        max_voices: Option<usize>,
+    },
+    /// Limit how many tracks play at once (0 removes the limit, omit to keep it)
+    VoiceLimit {
+        max_voices: Option<usize>,
+        /// What to do when the limit is hit: oldest, quietest or refuse
+        #[clap(short, long)]
+        policy: Option<String>,
+    },
+    /// Configure the master limiter
+    Limiter {
+        #[clap(short, long)]
+        enabled: Option<bool>,
+        /// limiter or soft-clip
+        #[clap(short, long)]
+        mode: Option<String>,
+        /// Output ceiling in dBFS (e.g. -1.0)
+        #[clap(short, long, allow_hyphen_values = true)]
+        ceiling: Option<f32>,
+        /// Release time in milliseconds
+        #[clap(short, long)]
+        release: Option<f32>,
+    },
+    /// Configure leading/trailing silence trimming (sounds can override it with "trim_silence")
+    SilenceTrim {
+        #[clap(short, long)]
+        enabled: Option<bool>,
+        /// Anything quieter is silence, in dBFS (e.g. -50.0)
+        #[clap(short, long, allow_hyphen_values = true)]
+        threshold: Option<f32>,
+    },
+    /// Text-to-speech engine and voice used by say
+    Tts {
+        /// espeak-ng, piper or rhvoice
+        #[clap(short, long)]
+        engine: Option<String>,
+        /// Voice name, or the .onnx model for piper. Empty for the engine default
+        #[clap(short, long)]
+        voice: Option<String>,
+        /// Speaking rate, 1.0 is normal
+        #[clap(short, long)]
+        rate: Option<f32>,
+        /// Pitch shift in semitones
+        #[clap(short, long, allow_hyphen_values = true)]
+        pitch: Option<f32>,
+    },
+    /// Rolling buffer for save-replay
+    Replay {
+        #[clap(short, long)]
+        enabled: Option<bool>,
+        /// How much audio to keep
+        #[clap(short, long)]
+        seconds: Option<u32>,
+        /// Audio source to buffer, `mic` for the microphone
+        #[clap(long)]
+        source: Option<String>,
+    },
+    /// Volume of the sounds on a bus
+    BusVolume { bus: String, volume: f32 },
+    /// Mix the microphone into a bus or not (true or false)
+    BusMic {
+        bus: String,
+        #[clap(action = clap::ArgAction::Set)]
+        enabled: bool,
+    },
+    /// App capture streams to link the sounds into, by application name, binary or node name
+    /// (see pwsp-cli get app-streams, none stops routing into apps)
+    RouteTargets {
+        #[clap(value_delimiter = ',')]
+        targets: Vec<String>,
+    },
+    /// Memory budget in MB for decoded hotkey-bound and pinned sounds (0 disables the cache)
+    SampleCache { memory_budget_mb: usize },
+    /// Effect chain of a track, or of the master bus without --id
+    /// (JSON, e.g. '[{"type":"high_pass","frequency":300,"q":0.7},{"type":"distortion","drive":4,"mix":0.5}]')
+    Effects {
+        /// Chain as JSON, an empty list removes all effects
+        effects: Option<String>,
+        /// Use a saved preset instead
+        #[clap(short, long, conflicts_with = "effects")]
+        preset: Option<String>,
+        #[clap(short, long)]
+        id: Option<u32>,
+    },
+    /// Save an effect chain (JSON) as a named preset
+    EffectPreset { name: String, effects: String },
+    /// Voice changer on the microphone passthrough
+    VoiceChanger {
+        #[clap(short, long)]
+        enabled: Option<bool>,
+        /// Preset to use (e.g. chipmunk, deep, robot, cave)
+        #[clap(short, long)]
+        preset: Option<String>,
+    },
+    /// Save a voice changer preset
+    /// (JSON, e.g. '{"pitch":-4,"formant":-2,"ring_mod_hz":0,"effects":[]}')
+    VoicePreset { name: String, preset: String },
+    /// Change settings of a sound file, fields left out keep their values
+    /// (JSON, e.g. '{"tags":["applause"],"choke_group":"horns"}')
+    SoundSettings {
+        file_path: PathBuf,
+        settings: String,
+    },
 }
 
 #[tokio::main]
@@ -161,6 +417,8 @@ async fn main() -> Result<()> {
     wait_for_daemon().await?;
 
     let request = match cli.command {
+        Commands::WatchDevices => return watch_devices().await,
+        Commands::Doctor => return doctor().await,
         Commands::Action { action } => match action {
             Actions::Ping => Request::ping(),
             Actions::Kill => Request::kill(),
@@ -171,12 +429,86 @@ async fn main() -> Result<()> {
             Actions::Play {
                 file_path,
                 concurrent,
-            } => Request::play(&file_path.to_string_lossy(), concurrent),
+                choke_group,
+                bus,
+            } => {
+                let mut request = Request::play(&file_path.to_string_lossy(), concurrent);
+                if let Some(group) = choke_group {
+                    request.args.insert("choke_group".to_string(), group);
+                }
+                if !bus.is_empty() {
+                    request.args.insert("bus".to_string(), bus.join(","));
+                }
+                request
+            }
+            Actions::Say {
+                text,
+                concurrent,
+                bus,
+            } => {
+                let mut request = Request::say(&text.join(" "), concurrent);
+                if !bus.is_empty() {
+                    request.args.insert("bus".to_string(), bus.join(","));
+                }
+                request
+            }
+            Actions::PlayRandom {
+                dir,
+                files,
+                tag,
+                mode,
+                concurrent,
+                choke_group,
+                bus,
+            } => {
+                let source = if let Some(dir) = dir {
+                    RandomSource::Dir(dir)
+                } else if !files.is_empty() {
+                    RandomSource::Files(files)
+                } else if let Some(tag) = tag {
+                    RandomSource::Tag(tag)
+                } else {
+                    return Err(anyhow!("One of --dir, --files or --tag is required"));
+                };
+                let mode = mode.parse::<RandomMode>().map_err(|e| anyhow!(e))?;
+                let mut request = Request::play_random(&source, mode, concurrent);
+                if let Some(group) = choke_group {
+                    request.args.insert("choke_group".to_string(), group);
+                }
+                if !bus.is_empty() {
+                    request.args.insert("bus".to_string(), bus.join(","));
+                }
+                request
+            }
             Actions::ToggleLoop { id } => Request::toggle_loop(id),
             Actions::SaveDaemonConfig => Request::save_daemon_config(),
             Actions::PlayHotkey { slot } => Request::play_hotkey(&slot),
             Actions::ClearHotkey { slot } => Request::clear_hotkey(&slot),
             Actions::ClearHotkeyKey { slot } => Request::clear_hotkey_key(&slot),
+            Actions::DeleteEffectPreset { name } => Request::delete_effect_preset(&name),
+            Actions::ToggleVoiceChanger => Request::toggle_voice_changer(),
+            Actions::DeleteVoicePreset { name } => Request::delete_voice_preset(&name),
+            Actions::StartRecording {
+                source,
+                bus,
+                format,
+                split,
+                split_threshold,
+                split_silence_ms,
+            } => {
+                let source = source.parse::<RecordSource>().map_err(|e| anyhow!(e))?;
+                let format = format.parse::<RecordFormat>().map_err(|e| anyhow!(e))?;
+                let split = split.then(|| {
+                    let default = SilenceSplit::default();
+                    SilenceSplit {
+                        threshold_db: split_threshold.unwrap_or(default.threshold_db),
+                        min_silence_ms: split_silence_ms.unwrap_or(default.min_silence_ms),
+                    }
+                });
+                Request::start_recording(source, bus.as_deref(), format, split)
+            }
+            Actions::StopRecording => Request::stop_recording(),
+            Actions::SaveReplay { seconds, slot } => Request::save_replay(seconds, slot.as_deref()),
         },
         Commands::Get { parameter } => match parameter {
             GetCommands::IsPaused => Request::get_is_paused(),
@@ -192,13 +524,34 @@ async fn main() -> Result<()> {
             GetCommands::DaemonConfig => Request::get_daemon_config(),
             GetCommands::FullState => Request::get_full_state(),
             GetCommands::Hotkeys => Request::get_hotkeys(),
+            GetCommands::Levels => Request::get_levels(),
+            GetCommands::AudioInfo => Request::get_audio_info(),
+            GetCommands::SampleCache => Request::get_sample_cache(),
+            GetCommands::Buses => Request::get_buses(),
+            GetCommands::AppStreams => Request::get_app_streams(),
+            GetCommands::PwNodes => Request::get_pw_nodes(),
+            GetCommands::PwPorts => Request::get_pw_ports(),
+            GetCommands::PwLinks => Request::get_pw_links(),
+            GetCommands::Limiter => Request::get_limiter(),
+            GetCommands::SoundSettings { file_path } => {
+                Request::get_sound_settings(&file_path.to_string_lossy())
+            }
+            GetCommands::Effects { id } => Request::get_effects(id),
+            GetCommands::EffectPresets => Request::get_effect_presets(),
+            GetCommands::VoiceChanger => Request::get_voice_changer(),
         },
         Commands::Set { parameter } => match parameter {
             SetCommands::Volume { volume, id } => Request::set_volume(volume, id),
             SetCommands::VolumeMultiplier { volume } => Request::set_volume_multiplier(volume),
             SetCommands::Position { position, id } => Request::seek(position, id),
-            SetCommands::Input { name } => Request::set_input(&name),
-            SetCommands::Loop { enabled, id } => Request::set_loop(&enabled, id),
+            SetCommands::Speed { speed, id } => Request::set_speed(speed, id),
+            SetCommands::Pitch { semitones, id } => Request::set_pitch(semitones, id),
+            SetCommands::Input { name, fuzzy } if fuzzy => {
+                Request::set_input(&format!("fuzzy:{}", name))
+            }
+            SetCommands::Input { name, .. } => Request::set_input(&name),
+            SetCommands::InputChannels { channels } => Request::set_input_channels(&channels),
+            SetCommands::Loop { enabled, count, id } => Request::set_loop(&enabled, count, id),
             SetCommands::Hotkey { slot, file_path } => {
                 Request::set_hotkey(&slot, &file_path.to_string_lossy())
             }
@@ -214,6 +567,81 @@ async fn main() -> Result<()> {
                 &serde_json::from_str::<Request>(&action)?,
                 &key_chord,
             ),
+            SetCommands::HotkeyMaxVoices { slot, max_voices } => {
+                Request::set_hotkey_max_voices(&slot, max_voices)
+            }
+            SetCommands::VoiceLimit { max_voices, policy } => {
+                let policy = policy
+                    .map(|p| p.parse::<VoiceStealing>())
+                    .transpose()
+                    .map_err(|e| anyhow!(e))?;
+                Request::set_voice_limit(max_voices, policy)
+            }
+            SetCommands::Limiter {
+                enabled,
+                mode,
+                ceiling,
+                release,
+            } => {
+                let mode = mode
+                    .map(|m| m.parse::<LimiterMode>())
+                    .transpose()
+                    .map_err(|e| anyhow!(e))?;
+                Request::set_limiter(enabled, mode, ceiling, release)
+            }
+            SetCommands::SilenceTrim { enabled, threshold } => {
+                Request::set_silence_trim(enabled, threshold)
+            }
+            SetCommands::Tts {
+                engine,
+                voice,
+                rate,
+                pitch,
+            } => {
+                let engine = engine
+                    .map(|e| e.parse::<TtsEngine>())
+                    .transpose()
+                    .map_err(|e| anyhow!(e))?;
+                Request::set_tts(engine, voice.as_deref(), rate, pitch)
+            }
+            SetCommands::Replay {
+                enabled,
+                seconds,
+                source,
+            } => Request::set_replay(enabled, seconds, source.as_deref()),
+            SetCommands::RouteTargets { targets } => Request::set_route_targets(&targets),
+            SetCommands::BusVolume { bus, volume } => Request::set_bus_volume(&bus, volume),
+            SetCommands::BusMic { bus, enabled } => Request::set_bus_mic(&bus, enabled),
+            SetCommands::SampleCache { memory_budget_mb } => {
+                Request::set_sample_cache(memory_budget_mb)
+            }
+            SetCommands::Effects {
+                effects,
+                preset,
+                id,
+            } => match (effects, preset) {
+                (Some(effects), _) => {
+                    Request::set_effects(&serde_json::from_str::<Vec<Effect>>(&effects)?, id)
+                }
+                (None, Some(preset)) => Request::set_effects_preset(&preset, id),
+                (None, None) => return Err(anyhow!("Either effects or --preset is required")),
+            },
+            SetCommands::EffectPreset { name, effects } => {
+                Request::save_effect_preset(&name, &serde_json::from_str::<Vec<Effect>>(&effects)?)
+            }
+            SetCommands::VoiceChanger { enabled, preset } => {
+                Request::set_voice_changer(enabled, preset.as_deref())
+            }
+            SetCommands::VoicePreset { name, preset } => {
+                Request::save_voice_preset(&name, &serde_json::from_str::<VoicePreset>(&preset)?)
+            }
+            SetCommands::SoundSettings {
+                file_path,
+                settings,
+            } => Request::update_sound_settings(
+                &file_path.to_string_lossy(),
+                &serde_json::from_str::<serde_json::Map<_, _>>(&settings)?,
+            ),
         },
     };
 
@@ -222,3 +650,70 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+async fn watch_devices() -> Result<()> {
+    let mut since = None;
+    loop {
+        let events: Vec<DeviceEvent> =
+            request_json(Request::wait_device_events(since, None)).await?;
+        for event in events {
+            let kind = match event.kind {
+                DeviceEventKind::Added => "added",
+                DeviceEventKind::Removed => "removed",
+            };
+            println!("{} {} - {}", kind, event.name, event.nick);
+            since = Some(event.seq);
+        }
+    }
+}
+
+/// Parses the JSON reply of a request, failing with the daemon's message.
+async fn request_json<T: serde::de::DeserializeOwned>(request: Request) -> Result<T> {
+    let response = make_request(request).await.map_err(|e| anyhow!(e))?;
+    if !response.status {
+        return Err(anyhow!(response.message));
+    }
+    Ok(serde_json::from_str(&response.message)?)
+}
+
+async fn doctor() -> Result<()> {
+    let graph = PwGraph {
+        nodes: request_json::<Vec<PwNode>>(Request::get_pw_nodes()).await?,
+        ports: request_json::<Vec<Port>>(Request::get_pw_ports()).await?,
+        links: request_json::<Vec<PwLink>>(Request::get_pw_links()).await?,
+    };
+    let buses = request_json::<Vec<BusInfo>>(Request::get_buses()).await?;
+
+    // Replies "<node.name> - <description>" once the selection resolves to a device
+    let response = make_request(Request::get_input())
+        .await
+        .map_err(|e| anyhow!(e))?;
+    let input = response
+        .status
+        .then(|| response.message.split(" - ").next().unwrap_or_default());
+
+    let mut findings = diagnose(&graph, &buses, input);
+    if !response.status && response.message != "No input device selected" {
+        findings.push(Finding {
+            ok: false,
+            message: format!(
+                "{}, the selected input device is not available",
+                response.message
+            ),
+        });
+    }
+    for finding in &findings {
+        println!(
+            "[{}] {}",
+            if finding.ok { "ok" } else { "!!" },
+            finding.message
+        );
+    }
+
+    let problems = findings.iter().filter(|finding| !finding.ok).count();
+    if problems > 0 {
+        return Err(anyhow!("Found {} problems", problems));
+    }
+    println!("Everything is in place");
+    Ok(())
+}
@@ -1,14 +1,17 @@
 use anyhow::{Result, anyhow};
 use pwsp_lib::{
-    types::socket::{MAX_MESSAGE_SIZE, Request, Response},
+    types::{
+        buses::configured_buses,
+        socket::{MAX_MESSAGE_SIZE, Request, Response},
+    },
     utils::{
         commands::parse_command,
         daemon::{
             create_runtime_dir, get_audio_player, get_daemon_config, get_runtime_dir,
-            is_daemon_running,
+            is_daemon_running, publish_device_event, with_daemon_config,
         },
         global_hotkeys::start_global_hotkey_listener,
-        pipewire::create_virtual_mic,
+        pipewire::{PwTerminator, create_virtual_mic, subscribe_connection, subscribe_devices},
     },
 };
 use std::os::unix::fs::PermissionsExt;
@@ -16,9 +19,16 @@ use std::{fs, time::Duration};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::{UnixListener, UnixStream},
-    time::sleep,
+    sync::broadcast::error::{RecvError, TryRecvError},
+    time::{sleep, timeout},
 };
 
+/// Time for the ports of a new node to show up before relinking
+const DEVICE_SETTLE_TIME: Duration = Duration::from_millis(200);
+/// First and longest wait before relinking again while something is still not linked
+const DEVICE_RETRY_MIN: Duration = Duration::from_millis(500);
+const DEVICE_RETRY_MAX: Duration = Duration::from_secs(10);
+
 #[tokio::main]
 async fn main() -> Result<()> {
     create_runtime_dir()?;
@@ -29,17 +39,19 @@ async fn main() -> Result<()> {
 
     get_daemon_config(); // Initialize daemon config
 
-    // Virtual mic object must be kept alive by some variable until daemon exits
-    let _virtual_mic = create_virtual_mic().await?;
-
-    if let Err(err) = get_audio_player().await {
-        eprintln!("Failed to initialize audio player: {}", err);
-    } // Initialize audio player
+    // Creates the virtual mics and the audio player once PipeWire is reachable
+    tokio::spawn(async {
+        connection_loop().await;
+    });
 
     tokio::spawn(async {
         start_global_hotkey_listener().await;
     });
 
+    tokio::spawn(async {
+        device_loop().await;
+    });
+
     let runtime_dir = get_runtime_dir();
 
     let lock_file = fs::OpenOptions::new()
@@ -176,25 +188,102 @@ async fn handle_connection(mut stream: UnixStream) {
 }
 
 async fn player_loop() {
-    let mut device_check_counter: u32 = 0;
     loop {
         let is_idle = match get_audio_player().await {
             Ok(player_mutex) => {
                 let mut audio_player = player_mutex.lock().await;
-                let check_devices = device_check_counter == 0;
-                audio_player.update(check_devices).await;
+                audio_player.update().await;
                 audio_player.tracks.is_empty()
             }
             Err(_err) => true,
         };
 
         if is_idle {
-            device_check_counter = 0;
             sleep(Duration::from_secs(2)).await;
         } else {
-            // Check devices every ~5 seconds (50 * 100ms) while playing
-            device_check_counter = (device_check_counter + 1) % 50;
             sleep(Duration::from_millis(100)).await;
         }
     }
 }
+
+async fn create_virtual_mics() -> Result<Vec<PwTerminator>> {
+    let (buses, audio) = with_daemon_config(|c| (configured_buses(&c.buses), c.audio));
+    let mut virtual_mics = vec![];
+    for bus in &buses {
+        virtual_mics.push(create_virtual_mic(bus, audio).await?);
+    }
+    Ok(virtual_mics)
+}
+
+/// Creates the virtual mics and the audio player on the first connection to PipeWire, and
+/// recreates the mics, streams and links whenever it comes back.
+async fn connection_loop() {
+    // Subscribed before anything is created, so a connection made meanwhile is not missed
+    let mut connection = subscribe_connection();
+    // Virtual mics live as long as this loop, recreated on reconnects
+    let mut virtual_mics: Vec<PwTerminator> = vec![];
+    let mut connected_before = false;
+    loop {
+        if *connection.borrow_and_update() > 0 {
+            if connected_before {
+                println!("Reconnected to PipeWire, recreating virtual mics");
+            }
+            virtual_mics.clear();
+            match create_virtual_mics().await {
+                Ok(mics) => virtual_mics = mics,
+                Err(err) => eprintln!("Failed to create virtual mics: {}", err),
+            }
+
+            match get_audio_player().await {
+                Ok(player_mutex) if connected_before => player_mutex.lock().await.reconnect().await,
+                Ok(_) => {}
+                Err(err) => eprintln!("Failed to initialize audio player: {}", err),
+            }
+            connected_before = true;
+        }
+
+        if connection.changed().await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn device_loop() {
+    let mut devices = subscribe_devices();
+    // Wait before the next relink while something is still not linked, doubled every time
+    let mut retry: Option<Duration> = None;
+    loop {
+        let event = match retry {
+            Some(delay) => timeout(delay, devices.recv()).await.ok(),
+            None => Some(devices.recv().await),
+        };
+        match &event {
+            Some(Ok((kind, device))) => publish_device_event(*kind, device),
+            Some(Err(RecvError::Lagged(_))) | None => {}
+            Some(Err(RecvError::Closed)) => break,
+        }
+
+        if event.is_some() {
+            // A device coming back often brings several nodes at once, relink after all of them
+            sleep(DEVICE_SETTLE_TIME).await;
+            loop {
+                match devices.try_recv() {
+                    Ok((kind, device)) => publish_device_event(kind, &device),
+                    Err(TryRecvError::Lagged(_)) => {}
+                    Err(_) => break,
+                }
+            }
+        }
+
+        let linked = match get_audio_player().await {
+            Ok(player_mutex) => player_mutex.lock().await.check_devices().await,
+            Err(_) => true,
+        };
+        // The ports of a node can show up well after the node, so don't wait for another event
+        retry = match (linked, retry) {
+            (true, _) => None,
+            (false, Some(delay)) if event.is_none() => Some((delay * 2).min(DEVICE_RETRY_MAX)),
+            (false, _) => Some(DEVICE_RETRY_MIN),
+        };
+    }
+}
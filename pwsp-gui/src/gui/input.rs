@@ -1,6 +1,9 @@
 use crate::gui::SoundpadGui;
 use egui::{Context, Id, Key, Modifiers};
-use pwsp_lib::types::socket::Request;
+use pwsp_lib::types::{
+    random::{RandomMode, RandomSource},
+    socket::Request,
+};
 use pwsp_lib::utils::gui::make_request_async;
 
 /// Convert an egui Key + Modifiers to a normalized chord string like "Ctrl+Shift+A".
@@ -103,6 +106,10 @@ impl SoundpadGui {
                 false
             }
         };
+        let say_focused = self
+            .get_focused(ctx)
+            .is_some_and(|focused_id| self.app_state.say_field_id == Some(focused_id));
+        let typing = search_focused || say_focused;
 
         // Handle hotkey capture mode: listen for a key chord to assign
         if self.app_state.hotkey_capture_active {
@@ -110,6 +117,7 @@ impl SoundpadGui {
                 self.app_state.hotkey_capture_active = false;
                 self.app_state.assigning_hotkey_slot = None;
                 self.app_state.assigning_hotkey_for_file = None;
+                self.app_state.assigning_hotkey_for_dir = None;
                 return;
             }
 
@@ -149,6 +157,28 @@ impl SoundpadGui {
                         &slot_name, &action, &chord,
                     ));
 
+                    self.app_state
+                        .hotkey_config
+                        .set_slot(slot_name.clone(), action);
+                    self.app_state
+                        .hotkey_config
+                        .set_key_chord(&slot_name, Some(chord.clone()));
+                } else if let Some(dir) = self.app_state.assigning_hotkey_for_dir.take() {
+                    // Auto-create a random-sound slot from the directory name
+                    let slot_name = format!(
+                        "random_{}",
+                        dir.file_name().unwrap_or_default().to_string_lossy()
+                    );
+                    let action = Request::play_random(
+                        &RandomSource::Dir(dir.clone()),
+                        RandomMode::Shuffle,
+                        true,
+                    );
+
+                    make_request_async(Request::set_hotkey_action_and_key(
+                        &slot_name, &action, &chord,
+                    ));
+
                     self.app_state
                         .hotkey_config
                         .set_slot(slot_name.clone(), action);
@@ -159,33 +189,34 @@ impl SoundpadGui {
                 self.app_state.hotkey_capture_active = false;
                 self.app_state.assigning_hotkey_slot = None;
                 self.app_state.assigning_hotkey_for_file = None;
+                self.app_state.assigning_hotkey_for_dir = None;
             }
             return;
         }
 
         // Open/close settings
-        if !search_focused && self.key_pressed(ctx, Key::I) {
+        if !typing && self.key_pressed(ctx, Key::I) {
             self.app_state.show_settings = !self.app_state.show_settings;
         }
 
         // Toggle hotkeys view
-        if !search_focused && self.key_pressed(ctx, Key::H) {
+        if !typing && self.key_pressed(ctx, Key::H) {
             self.app_state.show_hotkeys = !self.app_state.show_hotkeys;
         }
 
         if !self.app_state.show_settings && !self.app_state.show_hotkeys {
             // Pause / resume audio on space
-            if !search_focused && self.key_pressed(ctx, Key::Space) {
+            if !typing && self.key_pressed(ctx, Key::Space) {
                 self.play_toggle();
             }
 
             // Stop all audio tracks on backspace
-            if !search_focused && self.key_pressed(ctx, Key::Backspace) {
+            if !typing && self.key_pressed(ctx, Key::Backspace) {
                 self.stop(None);
             }
 
             // Focus search field
-            if self.key_pressed(ctx, Key::Slash) {
+            if !say_focused && self.key_pressed(ctx, Key::Slash) {
                 if search_focused {
                     ctx.memory_mut(|m| {
                         m.request_focus(Id::NULL);
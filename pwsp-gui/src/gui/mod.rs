@@ -8,10 +8,11 @@ use egui::{Context, FontData, FontDefinitions, FontFamily, FontTweak, Vec2, View
 use itertools::Itertools;
 use pwsp_lib::{
     types::{
-        audio_player::PlayerState,
+        audio_player::{PlayerState, SUPPORTED_EXTENSIONS},
         config::{GuiConfig, HotkeyConfig},
-        gui::{AppState, AudioPlayerState},
+        gui::{AppState, AudioPlayerState, WaveformEntry, WaveformState, WaveformStore},
         socket::Request,
+        waveform::Waveform,
     },
     utils::gui::{
         get_daemon_config, get_gui_config, make_request_async, make_request_sync,
@@ -27,9 +28,8 @@ use std::{
 };
 use system_fonts::{FontStyle, FoundFontSource, find_for_locale};
 
-const SUPPORTED_EXTENSIONS: [&str; 13] = [
-    "mp3", "wav", "ogg", "flac", "mp4", "m4a", "aac", "mov", "mkv", "mka", "webm", "avi", "opus",
-];
+/// Waveforms kept in memory before the finished ones are dropped
+const MAX_WAVEFORMS: usize = 64;
 
 struct SoundpadGui {
     pub app_state: AppState,
@@ -153,6 +153,60 @@ impl SoundpadGui {
         make_request_async(Request::stop(id));
     }
 
+    pub fn set_speed(&mut self, speed: f32, id: Option<u32>) {
+        make_request_async(Request::set_speed(speed, id));
+    }
+
+    pub fn set_pitch(&mut self, semitones: f32, id: Option<u32>) {
+        make_request_async(Request::set_pitch(semitones, id));
+    }
+
+    /// Returns the waveform of `path` once it is ready, starting to compute it on first use
+    /// and again once the file changed.
+    pub(crate) fn get_waveform(waveforms: &WaveformStore, path: &Path) -> Option<Arc<Waveform>> {
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        let mut guard = waveforms.lock().unwrap_or_else(|e| e.into_inner());
+        match guard.get(path) {
+            Some(entry) if entry.modified == modified => {
+                return match &entry.state {
+                    WaveformState::Ready(waveform) => Some(waveform.clone()),
+                    _ => None,
+                };
+            }
+            // Still computing the old version, wait for it before starting over
+            Some(entry) if matches!(entry.state, WaveformState::Loading) => return None,
+            _ => {}
+        }
+
+        if guard.len() >= MAX_WAVEFORMS {
+            guard.retain(|_, entry| matches!(entry.state, WaveformState::Loading));
+        }
+        guard.insert(
+            path.to_path_buf(),
+            WaveformEntry {
+                modified,
+                state: WaveformState::Loading,
+            },
+        );
+        let waveforms = waveforms.clone();
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            let state = match Waveform::load(&path) {
+                Ok(waveform) => WaveformState::Ready(Arc::new(waveform)),
+                Err(e) => {
+                    eprintln!("Failed to compute waveform for {:?}: {}", path, e);
+                    WaveformState::Failed
+                }
+            };
+            waveforms
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(path, WaveformEntry { modified, state });
+        });
+
+        None
+    }
+
     pub fn play_hotkey_slot(&mut self, slot: &str) {
         make_request_async(Request::play_hotkey(slot));
     }
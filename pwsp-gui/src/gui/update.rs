@@ -120,7 +120,16 @@ impl App for SoundpadGui {
             if let Some(config) = guard.hotkey_config.take() {
                 self.app_state.hotkey_config = config;
             }
+            let clips_saved = guard.clips_saved != self.audio_player_state.clips_saved;
             self.audio_player_state = guard.clone();
+
+            // New recordings show up in the library right away
+            if clips_saved {
+                self.app_state.dir_cache.clear();
+                if let Some(current_dir) = self.app_state.current_dir.clone() {
+                    self.open_dir(&current_dir);
+                }
+            }
         }
 
         // Handle scale factor changes
@@ -151,6 +160,11 @@ impl App for SoundpadGui {
                 return;
             }
 
+            if self.app_state.trim_editor.is_some() {
+                self.draw_trim_editor(ui);
+                return;
+            }
+
             if self.app_state.show_settings {
                 self.draw_settings(ui);
                 return;
@@ -1,13 +1,17 @@
 use crate::gui::SoundpadGui;
 use egui::{
-    Align, AtomExt, Button, CollapsingHeader, Color32, CursorIcon, Layout, RichText, ScrollArea,
-    Sense, TextEdit, Ui, Vec2,
+    Align, AtomExt, Button, CollapsingHeader, Color32, CursorIcon, Key, Layout, RichText,
+    ScrollArea, Sense, TextEdit, Ui, Vec2,
 };
 use egui_dnd::dnd;
 use egui_material_icons::icons::*;
-use pwsp_lib::types::{
-    config::{GuiConfig, SortOrder},
-    gui::{AppState, AudioPlayerState},
+use pwsp_lib::{
+    types::{
+        config::{GuiConfig, SortOrder},
+        gui::{AppState, AudioPlayerState},
+        socket::Request,
+    },
+    utils::gui::make_request_async,
 };
 use rust_i18n::t;
 use std::{cmp::Ordering, path::Path, path::PathBuf};
@@ -16,6 +20,7 @@ pub(crate) enum FileAction {
     Play(PathBuf, bool),
     StopAndPlay(u32, PathBuf, bool),
     AssignHotkey(PathBuf),
+    EditTrim(PathBuf),
 }
 
 impl SoundpadGui {
@@ -114,6 +119,19 @@ impl SoundpadGui {
                                 dir_to_open = Some(path.clone());
                             }
 
+                            if ui
+                                .button(format!(
+                                    "{} {}",
+                                    ICON_SHUFFLE.codepoint,
+                                    t!("gui.context.dirs.assign_random_hotkey")
+                                ))
+                                .clicked()
+                            {
+                                self.app_state.assigning_hotkey_for_dir = Some(path.clone());
+                                self.app_state.hotkey_capture_active = true;
+                                ui.close();
+                            }
+
                             if ui
                                 .button(format!(
                                     "{} {}",
@@ -220,11 +238,35 @@ impl SoundpadGui {
                     if play_file_button_response.clicked() {
                         self.open_file();
                     }
+
+                    self.draw_say_field(ui);
                 });
             });
         });
     }
 
+    fn draw_say_field(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            let say_button = Button::new(ICON_RECORD_VOICE_OVER).frame(false);
+            let say_button_response = ui
+                .add_sized([18.0, 18.0], say_button)
+                .on_hover_text(t!("gui.say.button_hint"));
+            let say_field_response = ui.add_sized(
+                [ui.available_width(), 22.0],
+                TextEdit::singleline(&mut self.app_state.say_text)
+                    .hint_text(t!("gui.say.placeholder")),
+            );
+            self.app_state.say_field_id = Some(say_field_response.id);
+
+            let submitted =
+                say_field_response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter));
+            let text = self.app_state.say_text.trim();
+            if (submitted || say_button_response.clicked()) && !text.is_empty() {
+                make_request_async(Request::say(text, true));
+            }
+        });
+    }
+
     fn draw_files_search_field(&mut self, ui: &mut Ui) {
         ui.horizontal(|ui| {
             let search_field_response = ui.add_sized(
@@ -272,6 +314,7 @@ impl SoundpadGui {
                             self.app_state.assigning_hotkey_for_file = Some(path);
                             self.app_state.hotkey_capture_active = true;
                         }
+                        FileAction::EditTrim(path) => self.open_trim_editor(path),
                     }
                 }
             });
@@ -479,6 +522,18 @@ impl SoundpadGui {
                     ui.close();
                 }
 
+                if ui
+                    .button(format!(
+                        "{} {}",
+                        ICON_CONTENT_CUT.codepoint,
+                        t!("gui.context.files.edit_trim")
+                    ))
+                    .clicked()
+                {
+                    actions.push(FileAction::EditTrim(path.clone()));
+                    ui.close();
+                }
+
                 ui.separator();
 
                 if ui
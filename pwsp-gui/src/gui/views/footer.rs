@@ -1,6 +1,13 @@
 use crate::gui::SoundpadGui;
 use egui::{AtomExt, Button, ComboBox, Label, RichText, Slider, Ui, Vec2};
 use egui_material_icons::icons::*;
+use pwsp_lib::{
+    types::{
+        recorder::{RecordFormat, RecordSource},
+        socket::Request,
+    },
+    utils::gui::make_request_async,
+};
 use rust_i18n::t;
 use std::time::Instant;
 
@@ -11,8 +18,9 @@ impl SoundpadGui {
             self.draw_mic_selection(ui);
             self.draw_master_volume(ui);
 
-            ui.add_space(ui.available_width() - 18.0 * 2.0 - ui.spacing().item_spacing.x * 2.0);
+            ui.add_space(ui.available_width() - 18.0 * 3.0 - ui.spacing().item_spacing.x * 3.0);
 
+            self.draw_record_button(ui);
             self.draw_hotkeys_button(ui);
             self.draw_settings_button(ui);
         });
@@ -40,6 +48,10 @@ impl SoundpadGui {
         if selected_input != prev_input {
             self.set_input(selected_input);
         }
+
+        if let Some(level) = self.audio_player_state.levels.input {
+            Self::draw_level_meter(ui, level, Vec2::new(60.0, 8.0));
+        }
     }
 
     fn draw_master_volume(&mut self, ui: &mut Ui) {
@@ -69,6 +81,40 @@ impl SoundpadGui {
         if volume_slider_response.drag_stopped() {
             self.app_state.volume_dragged = true;
         }
+
+        Self::draw_level_meter(
+            ui,
+            self.audio_player_state.levels.master,
+            Vec2::new(80.0, 8.0),
+        );
+    }
+
+    fn draw_record_button(&mut self, ui: &mut Ui) {
+        let recording = self.audio_player_state.recording;
+        let icon = if recording {
+            ICON_STOP_CIRCLE
+        } else {
+            ICON_FIBER_MANUAL_RECORD
+        };
+        let record_button = Button::new(icon.atom_size(Vec2::new(18.0, 18.0))).frame(false);
+        let record_button_response = ui.add_sized([18.0, 18.0], record_button);
+        if record_button_response.clicked() {
+            if recording {
+                make_request_async(Request::stop_recording());
+            } else {
+                make_request_async(Request::start_recording(
+                    RecordSource::Mix,
+                    None,
+                    RecordFormat::Wav,
+                    None,
+                ));
+            }
+        }
+        record_button_response.on_hover_text(if recording {
+            t!("gui.record.stop_hint")
+        } else {
+            t!("gui.record.start_hint")
+        });
     }
 
     fn draw_hotkeys_button(&mut self, ui: &mut Ui) {
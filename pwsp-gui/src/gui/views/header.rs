@@ -1,15 +1,26 @@
 use crate::gui::SoundpadGui;
-use egui::{Button, CollapsingHeader, FontFamily, Label, RichText, Slider, Ui};
+use egui::{Button, CollapsingHeader, FontFamily, Label, RichText, Slider, Ui, Vec2};
 use egui_material_icons::icons::*;
-use pwsp_lib::types::{audio_player::TrackInfo, gui::AppState};
+use pwsp_lib::types::{
+    audio_player::{MAX_SPEED, MIN_SPEED, TrackInfo},
+    gui::AppState,
+    meter::Level,
+    pitch::MAX_PITCH_SEMITONES,
+    waveform::Waveform,
+};
 use pwsp_lib::utils::gui::format_time_pair;
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+const TRACK_METER_WIDTH: f32 = 6.0;
 
 pub(crate) enum TrackAction {
     Pause(u32),
     Resume(u32),
     ToggleLoop(u32),
     Stop(u32),
+    SetSpeed(u32, f32),
+    SetPitch(u32, f32),
+    ResetRate(u32),
 }
 
 impl SoundpadGui {
@@ -36,7 +47,16 @@ impl SoundpadGui {
                 )
                 .default_open(true)
                 .show(ui, |ui| {
-                    if let Some(act) = Self::draw_track_control(ui, &mut self.app_state, track) {
+                    let level = self
+                        .audio_player_state
+                        .levels
+                        .tracks
+                        .get(&track.id)
+                        .copied()
+                        .unwrap_or_default();
+                    if let Some(act) =
+                        Self::draw_track_control(ui, &mut self.app_state, track, level)
+                    {
                         action = Some(act);
                     }
                 });
@@ -49,6 +69,12 @@ impl SoundpadGui {
                     TrackAction::Resume(id) => self.resume(Some(id)),
                     TrackAction::ToggleLoop(id) => self.toggle_loop(Some(id)),
                     TrackAction::Stop(id) => self.stop(Some(id)),
+                    TrackAction::SetSpeed(id, speed) => self.set_speed(speed, Some(id)),
+                    TrackAction::SetPitch(id, semitones) => self.set_pitch(semitones, Some(id)),
+                    TrackAction::ResetRate(id) => {
+                        self.set_speed(1.0, Some(id));
+                        self.set_pitch(0.0, Some(id));
+                    }
                 }
             }
         });
@@ -89,25 +115,125 @@ impl SoundpadGui {
         action
     }
 
+    /// Speed and pitch sliders in a drop-down, the button is highlighted while either is changed.
+    fn draw_rate_control(
+        ui: &mut Ui,
+        ui_state: &mut pwsp_lib::types::gui::TrackUiState,
+        track: &TrackInfo,
+    ) -> Option<TrackAction> {
+        let mut action = None;
+
+        let modified = track.speed != 1.0 || track.pitch != 0.0;
+        let icon = RichText::new(ICON_SPEED).size(18.0);
+        let icon = if modified {
+            icon.color(ui.visuals().selection.bg_fill)
+        } else {
+            icon
+        };
+
+        let response = ui
+            .menu_button(icon, |ui| {
+                let mut interacted = false;
+
+                let speed_response = ui.add(
+                    Slider::new(&mut ui_state.speed_slider_value, MIN_SPEED..=MAX_SPEED)
+                        .logarithmic(true)
+                        .step_by(0.05)
+                        .text("Speed"),
+                );
+                if speed_response.changed() || speed_response.dragged() {
+                    interacted = true;
+                }
+                if Self::slider_committed(&speed_response) {
+                    action = Some(TrackAction::SetSpeed(track.id, ui_state.speed_slider_value));
+                }
+
+                let pitch_response = ui.add(
+                    Slider::new(
+                        &mut ui_state.pitch_slider_value,
+                        -MAX_PITCH_SEMITONES..=MAX_PITCH_SEMITONES,
+                    )
+                    .step_by(0.5)
+                    .suffix(" st")
+                    .text("Pitch"),
+                );
+                if pitch_response.changed() || pitch_response.dragged() {
+                    interacted = true;
+                }
+                if Self::slider_committed(&pitch_response) {
+                    action = Some(TrackAction::SetPitch(track.id, ui_state.pitch_slider_value));
+                }
+
+                if ui.button("Reset").clicked() {
+                    ui_state.speed_slider_value = 1.0;
+                    ui_state.pitch_slider_value = 0.0;
+                    action = Some(TrackAction::ResetRate(track.id));
+                    interacted = true;
+                }
+
+                if interacted {
+                    // Keep the daemon values from overwriting the sliders
+                    ui_state.ignore_rate_update_until =
+                        Some(Instant::now() + Duration::from_millis(300));
+                }
+            })
+            .response;
+        response.on_hover_text(format!(
+            "Speed: {:.2}x, pitch: {:+.1} st",
+            track.speed, track.pitch
+        ));
+
+        action
+    }
+
+    /// Like the volume multiplier slider, a value is sent when dragging ends or it is typed in.
+    fn slider_committed(response: &egui::Response) -> bool {
+        response.drag_stopped() || (response.changed() && !response.dragged())
+    }
+
     fn draw_position_control(
         ui: &mut Ui,
         ui_state: &mut pwsp_lib::types::gui::TrackUiState,
         track: &TrackInfo,
+        waveform: Option<&Waveform>,
         default_slider_width: f32,
     ) {
         let duration = track.duration.unwrap_or(1.0);
-        let position_slider = Slider::new(&mut ui_state.position_slider_value, 0.0..=duration)
-            .show_value(false)
-            .step_by(0.01);
 
         let position_slider_width = ui.available_width()
-            - (30.0 * 3.0)
+            - (30.0 * 4.0)
+            - TRACK_METER_WIDTH
             - default_slider_width
-            - (ui.spacing().item_spacing.x * 6.0);
+            - (ui.spacing().item_spacing.x * 8.0);
+
+        if let Some(waveform) = waveform {
+            let progress = ui_state.position_slider_value / duration.max(f32::EPSILON);
+            let response = Self::draw_waveform(
+                ui,
+                waveform,
+                Vec2::new(position_slider_width.max(30.0), 30.0),
+                progress,
+            );
 
-        ui.spacing_mut().slider_width = position_slider_width;
-        if ui.add_sized([30.0, 30.0], position_slider).drag_stopped() {
-            ui_state.position_dragged = true;
+            if let Some(pointer) = response.interact_pointer_pos() {
+                ui_state.position_slider_value =
+                    Self::waveform_fraction(response.rect, pointer.x) * duration;
+                // Keep the daemon position from overwriting the value while dragging
+                ui_state.ignore_position_update_until =
+                    Some(Instant::now() + Duration::from_millis(300));
+            }
+            if response.clicked() || response.drag_stopped() {
+                ui_state.position_dragged = true;
+            }
+        } else {
+            let position_slider = Slider::new(&mut ui_state.position_slider_value, 0.0..=duration)
+                .show_value(false)
+                .step_by(0.01);
+
+            ui.spacing_mut().slider_width = position_slider_width;
+            if ui.add_sized([30.0, 30.0], position_slider).drag_stopped() {
+                ui_state.position_dragged = true;
+            }
         }
 
         let time_label =
@@ -151,7 +277,9 @@ impl SoundpadGui {
         ui: &mut Ui,
         app_state: &mut AppState,
         track: &TrackInfo,
+        level: Level,
     ) -> Option<TrackAction> {
+        let waveform = Self::get_waveform(&app_state.waveforms, &track.path);
         let ui_state = app_state.track_ui_states.entry(track.id).or_default();
 
         let should_update_position = !ui_state.position_dragged
@@ -174,6 +302,16 @@ impl SoundpadGui {
             ui_state.volume_slider_value = track.volume;
         }
 
+        let should_update_rate = ui_state
+            .ignore_rate_update_until
+            .map(|t| Instant::now() > t)
+            .unwrap_or(true);
+
+        if should_update_rate {
+            ui_state.speed_slider_value = track.speed;
+            ui_state.pitch_slider_value = track.pitch;
+        }
+
         let mut action = None;
 
         ui.horizontal_top(|ui| {
@@ -181,10 +319,23 @@ impl SoundpadGui {
                 action = Some(act);
             }
 
+            if let Some(act) = Self::draw_rate_control(ui, ui_state, track) {
+                action = Some(act);
+            }
+
             let default_slider_width = ui.spacing().slider_width;
-            Self::draw_position_control(ui, ui_state, track, default_slider_width);
+            Self::draw_position_control(
+                ui,
+                ui_state,
+                track,
+                waveform.as_deref(),
+                default_slider_width,
+            );
             Self::draw_volume_control(ui, ui_state, track, default_slider_width);
 
+            ui.add_space(ui.spacing().item_spacing.x);
+            Self::draw_level_meter(ui, level, Vec2::new(TRACK_METER_WIDTH, 30.0));
+
             if let Some(act) = Self::draw_stop_control(ui, track) {
                 action = Some(act);
             }
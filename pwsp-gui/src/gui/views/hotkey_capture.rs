@@ -21,6 +21,12 @@ impl SoundpadGui {
                     t!("gui.hotkeys.capture.for"),
                     path.file_name().unwrap_or_default().to_string_lossy()
                 )
+            } else if let Some(path) = &self.app_state.assigning_hotkey_for_dir {
+                format!(
+                    "{} '{}/*'",
+                    t!("gui.hotkeys.capture.for"),
+                    path.file_name().unwrap_or_default().to_string_lossy()
+                )
             } else {
                 String::new()
             };
@@ -60,6 +60,8 @@ impl SoundpadGui {
                     t!("gui.hotkeys.add_command_select")
                 ),
                 |ui| {
+                    let say_text = self.app_state.say_text.trim().to_string();
+                    let say_slot = format!("say: {}", say_text);
                     let mut selected_cmd = None;
                     if ui.button(t!("gui.hotkeys.toggle_pause_command")).clicked() {
                         selected_cmd = Some(("cmd_toggle_pause", Request::toggle_pause(None)));
@@ -82,6 +84,23 @@ impl SoundpadGui {
                     if ui.button(t!("gui.hotkeys.toggle_loop_command")).clicked() {
                         selected_cmd = Some(("cmd_toggle_loop", Request::toggle_loop(None)));
                     }
+                    if ui
+                        .button(t!("gui.hotkeys.toggle_voice_changer_command"))
+                        .clicked()
+                    {
+                        selected_cmd =
+                            Some(("cmd_toggle_voice_changer", Request::toggle_voice_changer()));
+                    }
+                    if ui.button(t!("gui.hotkeys.save_replay_command")).clicked() {
+                        selected_cmd = Some(("cmd_save_replay", Request::save_replay(None, None)));
+                    }
+                    if !say_text.is_empty()
+                        && ui
+                            .button(t!("gui.hotkeys.say_command", text = say_text))
+                            .clicked()
+                    {
+                        selected_cmd = Some((say_slot.as_str(), Request::say(&say_text, true)));
+                    }
 
                     if let Some((slot_name, req)) = selected_cmd {
                         make_request_async(Request::set_hotkey_action(slot_name, &req));
@@ -216,6 +235,20 @@ impl SoundpadGui {
                                         "Play".to_string()
                                     }
                                 }
+                                "play_random" => {
+                                    let source = if let Some(dir) = slot.action.args.get("dir") {
+                                        Path::new(dir)
+                                            .file_name()
+                                            .unwrap_or_default()
+                                            .to_string_lossy()
+                                            .to_string()
+                                    } else if let Some(tag) = slot.action.args.get("tag") {
+                                        format!("#{}", tag)
+                                    } else {
+                                        "list".to_string()
+                                    };
+                                    format!("Random: {}", source)
+                                }
                                 "toggle_pause" => "Toggle Pause".to_string(),
                                 "pause" => "Pause Playback".to_string(),
                                 "resume" => "Resume Playback".to_string(),
@@ -0,0 +1,98 @@
+use crate::gui::SoundpadGui;
+use egui::{Color32, CornerRadius, Rect, Sense, Stroke, StrokeKind, Ui, Vec2, pos2};
+use pwsp_lib::types::meter::Level;
+use rust_i18n::t;
+
+const METER_FLOOR_DB: f32 = -60.0;
+
+impl SoundpadGui {
+    /// Maps a dBFS value onto 0..1 of the meter length.
+    pub(crate) fn meter_fraction(db: f32) -> f32 {
+        ((db - METER_FLOOR_DB) / -METER_FLOOR_DB).clamp(0.0, 1.0)
+    }
+
+    fn meter_color(db: f32) -> Color32 {
+        if db >= -0.1 {
+            Color32::from_rgb(220, 50, 50)
+        } else if db >= -9.0 {
+            Color32::from_rgb(230, 190, 40)
+        } else {
+            Color32::from_rgb(60, 180, 80)
+        }
+    }
+
+    /// Draws a VU meter, vertical when `size` is taller than it is wide.
+    /// The bar shows RMS, the thin line shows the peak.
+    pub(crate) fn draw_level_meter(ui: &mut Ui, level: Level, size: Vec2) {
+        let (rect, response) = ui.allocate_exact_size(size, Sense::hover());
+        if !ui.is_rect_visible(rect) {
+            return;
+        }
+
+        let vertical = size.y > size.x;
+        let painter = ui.painter();
+        painter.rect_filled(rect, CornerRadius::same(2), ui.visuals().extreme_bg_color);
+
+        let sub_rect = |fraction: f32| {
+            if vertical {
+                Rect::from_min_max(
+                    pos2(rect.left(), rect.bottom() - rect.height() * fraction),
+                    rect.right_bottom(),
+                )
+            } else {
+                Rect::from_min_max(
+                    rect.left_top(),
+                    pos2(rect.left() + rect.width() * fraction, rect.bottom()),
+                )
+            }
+        };
+
+        let rms_db = level.rms_db();
+        let peak_db = level.peak_db();
+        painter.rect_filled(
+            sub_rect(Self::meter_fraction(rms_db)),
+            CornerRadius::same(2),
+            Self::meter_color(rms_db),
+        );
+
+        let peak_fraction = Self::meter_fraction(peak_db);
+        if peak_fraction > 0.0 {
+            let peak_stroke = Stroke::new(2.0, Self::meter_color(peak_db));
+            if vertical {
+                let y = rect.bottom() - rect.height() * peak_fraction;
+                painter.hline(rect.x_range(), y, peak_stroke);
+            } else {
+                let x = rect.left() + rect.width() * peak_fraction;
+                painter.vline(x, rect.y_range(), peak_stroke);
+            }
+        }
+
+        if level.is_clipping() {
+            painter.rect_stroke(
+                rect,
+                CornerRadius::same(2),
+                Stroke::new(1.0, Self::meter_color(0.0)),
+                StrokeKind::Inside,
+            );
+        }
+
+        response.on_hover_text(t!(
+            "gui.meter.hover",
+            peak = format!("{:.1}", peak_db),
+            rms = format!("{:.1}", rms_db)
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meter_fraction() {
+        assert_eq!(SoundpadGui::meter_fraction(0.0), 1.0);
+        assert_eq!(SoundpadGui::meter_fraction(-30.0), 0.5);
+        assert_eq!(SoundpadGui::meter_fraction(-120.0), 0.0);
+        assert_eq!(SoundpadGui::meter_fraction(6.0), 1.0);
+    }
+}
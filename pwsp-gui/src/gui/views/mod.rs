@@ -7,8 +7,11 @@ mod footer;
 mod header;
 mod hotkey_capture;
 mod hotkeys;
+mod meter;
 mod settings;
+mod trim_editor;
 mod waiting_for_daemon;
+mod waveform;
 
 impl SoundpadGui {
     pub(crate) fn get_volume_icon(volume: f32) -> &'static str {
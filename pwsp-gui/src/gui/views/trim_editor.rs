@@ -0,0 +1,411 @@
+use crate::gui::SoundpadGui;
+use egui::{
+    Align2, Button, Color32, ComboBox, CornerRadius, DragValue, FontId, Rect, RichText, Stroke, Ui,
+    Vec2, pos2,
+};
+use egui_material_icons::icons::{ICON_ARROW_BACK, ICON_PLAY_ARROW, ICON_SAVE, ICON_STOP};
+use pwsp_lib::{
+    types::{
+        config::SoundSettings,
+        gui::{TrimEditorState, TrimHandle},
+        socket::Request,
+        trim::Preview,
+    },
+    utils::gui::make_request_sync,
+};
+use rust_i18n::t;
+use std::path::PathBuf;
+
+/// Shortest part of a sound the handles can be squeezed to, in seconds
+const MIN_TRIM_LENGTH: f32 = 0.05;
+
+impl SoundpadGui {
+    pub(crate) fn open_trim_editor(&mut self, path: PathBuf) {
+        let settings = make_request_sync(Request::get_sound_settings(&path.to_string_lossy()))
+            .ok()
+            .filter(|r| r.status)
+            .and_then(|r| serde_json::from_str::<SoundSettings>(&r.message).ok())
+            .unwrap_or_default();
+
+        self.app_state.trim_editor = Some(TrimEditorState {
+            path,
+            settings,
+            dragging: None,
+            preview: None,
+            error: None,
+        });
+    }
+
+    pub fn draw_trim_editor(&mut self, ui: &mut Ui) {
+        let waveform = match &self.app_state.trim_editor {
+            Some(editor) => Self::get_waveform(&self.app_state.waveforms, &editor.path),
+            None => return,
+        };
+        let mut close = false;
+
+        let Some(editor) = self.app_state.trim_editor.as_mut() else {
+            return;
+        };
+        if editor.preview.as_ref().is_some_and(|p| p.is_finished()) {
+            editor.preview = None;
+        }
+
+        ui.vertical(|ui| {
+            ui.spacing_mut().item_spacing.y = 5.0;
+            // --------- Back Button and Title ----------
+            ui.horizontal_top(|ui| {
+                let back_button = Button::new(ICON_ARROW_BACK).frame(false);
+                if ui.add(back_button).clicked() {
+                    close = true;
+                }
+
+                ui.add_space(ui.available_width() / 2.0 - 40.0);
+
+                ui.label(
+                    RichText::new(t!("gui.trim.header"))
+                        .color(Color32::WHITE)
+                        .monospace(),
+                );
+            });
+            // --------------------------------
+
+            ui.separator();
+            ui.label(
+                editor
+                    .path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+            );
+            ui.add_space(10.0);
+
+            let Some(waveform) = waveform else {
+                ui.label(t!("gui.trim.loading"));
+                return;
+            };
+            let duration = waveform.duration.max(MIN_TRIM_LENGTH);
+            Self::clamp_trim(&mut editor.settings, duration);
+
+            // --------- Waveform ----------
+            let start = editor.settings.start;
+            let end = editor.settings.end.unwrap_or(duration);
+            let position = editor
+                .preview
+                .as_ref()
+                .map(|p| start + p.position().as_secs_f32())
+                .unwrap_or(start);
+
+            let response = Self::draw_waveform(
+                ui,
+                &waveform,
+                Vec2::new(ui.available_width(), 140.0),
+                position / duration,
+            );
+            Self::draw_trim_overlay(ui, response.rect, &editor.settings, duration);
+
+            if let Some(pointer) = response.interact_pointer_pos() {
+                let time = Self::waveform_fraction(response.rect, pointer.x) * duration;
+                let handle = *editor
+                    .dragging
+                    .get_or_insert_with(|| Self::nearest_trim_handle(start, end, time));
+                match handle {
+                    TrimHandle::Start => editor.settings.start = time,
+                    TrimHandle::End => editor.settings.end = Some(time),
+                }
+                Self::clamp_trim(&mut editor.settings, duration);
+            }
+            if !response.is_pointer_button_down_on() {
+                editor.dragging = None;
+            }
+            // --------------------------------
+
+            ui.add_space(10.0);
+
+            // --------- Numeric Values ----------
+            let mut end = editor.settings.end.unwrap_or(duration);
+            ui.horizontal(|ui| {
+                ui.label(t!("gui.trim.start"));
+                ui.add(
+                    DragValue::new(&mut editor.settings.start)
+                        .range(0.0..=duration)
+                        .speed(0.01)
+                        .suffix(" s"),
+                );
+
+                ui.label(t!("gui.trim.end"));
+                if ui
+                    .add(
+                        DragValue::new(&mut end)
+                            .range(0.0..=duration)
+                            .speed(0.01)
+                            .suffix(" s"),
+                    )
+                    .changed()
+                {
+                    editor.settings.end = Some(end);
+                }
+
+                ui.separator();
+
+                ui.label(t!("gui.trim.fade_in"));
+                ui.add(
+                    DragValue::new(&mut editor.settings.fade_in)
+                        .range(0.0..=duration)
+                        .speed(0.01)
+                        .suffix(" s"),
+                );
+
+                ui.label(t!("gui.trim.fade_out"));
+                ui.add(
+                    DragValue::new(&mut editor.settings.fade_out)
+                        .range(0.0..=duration)
+                        .speed(0.01)
+                        .suffix(" s"),
+                );
+            });
+
+            let mut loop_end = editor.settings.loop_end.unwrap_or(end);
+            ui.horizontal(|ui| {
+                ui.label(t!("gui.trim.loop_start"));
+                ui.add(
+                    DragValue::new(&mut editor.settings.loop_start)
+                        .range(0.0..=duration)
+                        .speed(0.01)
+                        .suffix(" s"),
+                )
+                .on_hover_text(t!("gui.trim.loop_hint"));
+
+                ui.label(t!("gui.trim.loop_end"));
+                if ui
+                    .add(
+                        DragValue::new(&mut loop_end)
+                            .range(0.0..=duration)
+                            .speed(0.01)
+                            .suffix(" s"),
+                    )
+                    .on_hover_text(t!("gui.trim.loop_hint"))
+                    .changed()
+                {
+                    editor.settings.loop_end = Some(loop_end);
+                }
+            });
+            Self::clamp_trim(&mut editor.settings, duration);
+
+            let silence_text = |value: Option<bool>| match value {
+                None => t!("gui.trim.silence_default"),
+                Some(true) => t!("gui.trim.silence_on"),
+                Some(false) => t!("gui.trim.silence_off"),
+            };
+            ComboBox::from_label(t!("gui.trim.trim_silence"))
+                .selected_text(silence_text(editor.settings.trim_silence))
+                .show_ui(ui, |ui| {
+                    for value in [None, Some(true), Some(false)] {
+                        ui.selectable_value(
+                            &mut editor.settings.trim_silence,
+                            value,
+                            silence_text(value),
+                        );
+                    }
+                });
+            ui.checkbox(&mut editor.settings.pinned, t!("gui.trim.pinned"))
+                .on_hover_text(t!("gui.trim.pinned_hint"));
+            // --------------------------------
+
+            ui.add_space(10.0);
+
+            // --------- Buttons ----------
+            ui.horizontal(|ui| {
+                if editor.preview.is_some() {
+                    if ui
+                        .button(format!("{} {}", ICON_STOP.codepoint, t!("gui.trim.stop")))
+                        .clicked()
+                    {
+                        editor.preview = None;
+                    }
+                } else if ui
+                    .button(format!(
+                        "{} {}",
+                        ICON_PLAY_ARROW.codepoint,
+                        t!("gui.trim.preview")
+                    ))
+                    .on_hover_text(t!("gui.trim.preview_hint"))
+                    .clicked()
+                {
+                    match Preview::start(&editor.path, &editor.settings) {
+                        Ok(preview) => {
+                            editor.preview = Some(preview);
+                            editor.error = None;
+                        }
+                        Err(e) => editor.error = Some(e.to_string()),
+                    }
+                }
+
+                if ui.button(t!("gui.trim.reset")).clicked() {
+                    editor.settings = SoundSettings {
+                        start: 0.0,
+                        end: None,
+                        fade_in: 0.0,
+                        fade_out: 0.0,
+                        loop_start: 0.0,
+                        loop_end: None,
+                        ..editor.settings.clone()
+                    };
+                }
+
+                if ui
+                    .button(format!("{} {}", ICON_SAVE.codepoint, t!("gui.trim.save")))
+                    .clicked()
+                {
+                    let request = Request::set_sound_settings(
+                        &editor.path.to_string_lossy(),
+                        &editor.settings,
+                    );
+                    match make_request_sync(request) {
+                        Ok(response) if response.status => close = true,
+                        Ok(response) => editor.error = Some(response.message),
+                        Err(e) => editor.error = Some(e.to_string()),
+                    }
+                }
+            });
+            // --------------------------------
+
+            if let Some(error) = &editor.error {
+                ui.colored_label(Color32::from_rgb(220, 50, 50), error);
+            }
+        });
+
+        if close {
+            self.app_state.trim_editor = None;
+        }
+    }
+
+    /// Keeps the trimmed part inside the file and the fades inside the trimmed part.
+    /// An end at the very end of the file is stored as unset.
+    pub(crate) fn clamp_trim(settings: &mut SoundSettings, duration: f32) {
+        let end = settings
+            .end
+            .unwrap_or(duration)
+            .clamp(MIN_TRIM_LENGTH, duration);
+        settings.start = settings.start.clamp(0.0, end - MIN_TRIM_LENGTH);
+        settings.end = (end < duration).then_some(end);
+
+        let length = end - settings.start;
+        settings.fade_in = settings.fade_in.clamp(0.0, length);
+        settings.fade_out = settings.fade_out.clamp(0.0, length - settings.fade_in);
+
+        // A loop start before the trim start loops from the trim start, a loop end at the trim end
+        // is no end at all
+        settings.loop_start = settings.loop_start.min(end);
+        let loop_start = settings.loop_start.max(settings.start);
+        settings.loop_end = settings
+            .loop_end
+            .map(|loop_end| loop_end.clamp(loop_start, end))
+            .filter(|loop_end| *loop_end < end);
+    }
+
+    pub(crate) fn nearest_trim_handle(start: f32, end: f32, time: f32) -> TrimHandle {
+        if (time - start).abs() <= (time - end).abs() {
+            TrimHandle::Start
+        } else {
+            TrimHandle::End
+        }
+    }
+
+    /// Shades the cut off parts and draws the in/out handles and fade ramps over a waveform.
+    fn draw_trim_overlay(ui: &Ui, rect: Rect, settings: &SoundSettings, duration: f32) {
+        let x_at = |time: f32| rect.left() + rect.width() * (time / duration).clamp(0.0, 1.0);
+        let start_x = x_at(settings.start);
+        let end_x = x_at(settings.end.unwrap_or(duration));
+
+        let painter = ui.painter();
+        let shade = Color32::from_black_alpha(150);
+        painter.rect_filled(
+            Rect::from_min_max(rect.left_top(), pos2(start_x, rect.bottom())),
+            CornerRadius::ZERO,
+            shade,
+        );
+        painter.rect_filled(
+            Rect::from_min_max(pos2(end_x, rect.top()), rect.right_bottom()),
+            CornerRadius::ZERO,
+            shade,
+        );
+
+        let handle_color = Color32::from_rgb(230, 190, 40);
+        let handle_stroke = Stroke::new(2.0, handle_color);
+        for (x, label) in [(start_x, "["), (end_x, "]")] {
+            painter.vline(x, rect.y_range(), handle_stroke);
+            painter.text(
+                pos2(x, rect.top()),
+                Align2::CENTER_BOTTOM,
+                label,
+                FontId::monospace(12.0),
+                handle_color,
+            );
+        }
+
+        let fade_stroke = Stroke::new(1.5, Color32::WHITE.gamma_multiply(0.7));
+        if settings.fade_in > 0.0 {
+            painter.line_segment(
+                [
+                    pos2(start_x, rect.bottom()),
+                    pos2(x_at(settings.start + settings.fade_in), rect.top()),
+                ],
+                fade_stroke,
+            );
+        }
+        if settings.fade_out > 0.0 {
+            let end = settings.end.unwrap_or(duration);
+            painter.line_segment(
+                [
+                    pos2(x_at(end - settings.fade_out), rect.top()),
+                    pos2(end_x, rect.bottom()),
+                ],
+                fade_stroke,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamp_trim() {
+        let mut settings = SoundSettings {
+            start: 8.0,
+            end: Some(20.0),
+            fade_in: 5.0,
+            fade_out: 5.0,
+            ..Default::default()
+        };
+        SoundpadGui::clamp_trim(&mut settings, 10.0);
+        assert_eq!(settings.end, None);
+        assert_eq!(settings.start, 8.0);
+        assert_eq!(settings.fade_in, 2.0);
+        assert_eq!(settings.fade_out, 0.0);
+
+        let mut settings = SoundSettings {
+            start: 2.0,
+            loop_start: 4.0,
+            loop_end: Some(12.0),
+            ..Default::default()
+        };
+        SoundpadGui::clamp_trim(&mut settings, 10.0);
+        assert_eq!(settings.loop_start, 4.0);
+        assert_eq!(settings.loop_end, None);
+    }
+
+    #[test]
+    fn test_nearest_trim_handle() {
+        assert_eq!(
+            SoundpadGui::nearest_trim_handle(1.0, 5.0, 2.0),
+            TrimHandle::Start
+        );
+        assert_eq!(
+            SoundpadGui::nearest_trim_handle(1.0, 5.0, 4.0),
+            TrimHandle::End
+        );
+    }
+}
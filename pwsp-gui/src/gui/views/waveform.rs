@@ -0,0 +1,78 @@
+use crate::gui::SoundpadGui;
+use egui::{CornerRadius, Rect, Response, Sense, Stroke, Ui, Vec2};
+use pwsp_lib::types::waveform::Waveform;
+
+impl SoundpadGui {
+    /// Draws a min/max envelope, highlighting everything left of `progress` (0..1).
+    ///
+    /// The returned response senses clicks and drags so callers can map them to a time.
+    pub(crate) fn draw_waveform(
+        ui: &mut Ui,
+        waveform: &Waveform,
+        size: Vec2,
+        progress: f32,
+    ) -> Response {
+        let (rect, response) = ui.allocate_exact_size(size, Sense::click_and_drag());
+        if !ui.is_rect_visible(rect) {
+            return response;
+        }
+
+        let visuals = ui.visuals();
+        let played_color = visuals.selection.bg_fill;
+        let unplayed_color = visuals.widgets.inactive.fg_stroke.color.gamma_multiply(0.6);
+        let painter = ui.painter();
+
+        painter.rect_filled(rect, CornerRadius::same(2), visuals.extreme_bg_color);
+
+        let center_y = rect.center().y;
+        let half_height = rect.height() / 2.0;
+        let progress_x = rect.left() + rect.width() * progress.clamp(0.0, 1.0);
+
+        for (i, (min, max)) in waveform
+            .columns(rect.width().max(0.0) as usize)
+            .into_iter()
+            .enumerate()
+        {
+            let x = rect.left() + i as f32 + 0.5;
+            let color = if x <= progress_x {
+                played_color
+            } else {
+                unplayed_color
+            };
+            // Keep silent parts visible as a thin line
+            let top = center_y - (max.clamp(-1.0, 1.0) * half_height).max(0.5);
+            let bottom = center_y - (min.clamp(-1.0, 1.0) * half_height).min(-0.5);
+            painter.vline(x, top..=bottom, Stroke::new(1.0, color));
+        }
+
+        painter.vline(
+            progress_x,
+            rect.y_range(),
+            Stroke::new(1.5, visuals.strong_text_color()),
+        );
+
+        response
+    }
+
+    /// Converts a pointer position over a waveform rect into a 0..1 fraction.
+    pub(crate) fn waveform_fraction(rect: Rect, x: f32) -> f32 {
+        if rect.width() <= 0.0 {
+            return 0.0;
+        }
+        ((x - rect.left()) / rect.width()).clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::pos2;
+
+    #[test]
+    fn test_waveform_fraction() {
+        let rect = Rect::from_min_size(pos2(10.0, 0.0), Vec2::new(100.0, 30.0));
+        assert_eq!(SoundpadGui::waveform_fraction(rect, 10.0), 0.0);
+        assert_eq!(SoundpadGui::waveform_fraction(rect, 60.0), 0.5);
+        assert_eq!(SoundpadGui::waveform_fraction(rect, 500.0), 1.0);
+    }
+}
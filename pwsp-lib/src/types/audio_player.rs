@@ -1,21 +1,58 @@
 use crate::{
-    types::pipewire::DeviceType,
+    types::{
+        buses::{
+            BusControl, BusInfo, BusInput, BusRouter, BusSettings, configured_buses, main_bus,
+        },
+        config::{HotkeyConfig, SoundConfig, SoundSettings},
+        effects::{Effect, EffectControl, Effected},
+        limiter::{Limiter, LimiterControl},
+        looping::{LoopControl, LoopRegion, Looped},
+        meter::{LevelAccumulator, LevelMeter, Levels, Metered},
+        pipewire::{
+            AppStream, AudioDevice, AudioInfo, DeviceSelector, DeviceType, Port, StreamInfo,
+            map_ports, route_matches,
+        },
+        pitch::{PitchControl, PitchShifted},
+        random::RandomPicker,
+        recorder::{RecordOptions, RecordSource, RecordTap, Recording},
+        replay::{ReplayBuffer, ReplaySettings},
+        sample_cache::{SampleCache, SampleCacheInfo, load_into, preload_targets},
+        trim::{cached_playback_settings, open_sound, playback_settings, trimmed},
+        voice_changer::{VoiceChanger, VoiceChangerControl, VoicePreset},
+        voices::{Voice, pick_victims},
+    },
     utils::{
         daemon::with_daemon_config,
-        pipewire::{PwTerminator, create_link, get_device, link_player_to_virtual_mic},
+        gui::ensure_pwsp_audio_dir,
+        pipewire::{
+            PASSTHROUGH_NODE, PlaybackStream, PwTerminator, RECORD_NODE, REPLAY_NODE,
+            create_bus_stream, create_capture_stream, create_channel_capture, create_link,
+            create_player_stream, get_app_streams, get_device, link_ports_to, link_stream_to,
+        },
     },
 };
 use anyhow::{Result, anyhow};
-use rodio::{Decoder, DeviceSinkBuilder, MixerDeviceSink, Player, Source};
+use rodio::{ChannelCount, Player, SampleRate, Source};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     error::Error,
-    fs,
     path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
     time::Duration,
 };
 
+/// Playback speed range accepted by `set_speed`
+pub const MIN_SPEED: f32 = 0.25;
+pub const MAX_SPEED: f32 = 4.0;
+
+pub const SUPPORTED_EXTENSIONS: [&str; 13] = [
+    "mp3", "wav", "ogg", "flac", "mp4", "m4a", "aac", "mov", "mkv", "mka", "webm", "avi", "opus",
+];
+
 #[derive(Debug, Eq, PartialEq, Default, Clone, Serialize, Deserialize)]
 pub enum PlayerState {
     #[default]
@@ -32,7 +69,25 @@ pub struct TrackInfo {
     pub position: f32,
     pub volume: f32,
     pub looped: bool,
+    /// Passes left after the current one when the loop has a count
+    #[serde(default)]
+    pub loops_remaining: Option<u32>,
     pub paused: bool,
+    /// Varispeed factor, changes pitch along with tempo
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    /// Pitch shift in semitones, tempo is kept
+    #[serde(default)]
+    pub pitch: f32,
+    #[serde(default)]
+    pub effects: Vec<Effect>,
+    /// Names of the buses the track plays on
+    #[serde(default)]
+    pub buses: Vec<String>,
+}
+
+fn default_speed() -> f32 {
+    1.0
 }
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
@@ -43,6 +98,11 @@ pub struct FullState {
     pub volume_multiplier: f32,
     pub current_input: String,
     pub all_inputs: HashMap<String, String>,
+    #[serde(default)]
+    pub recording: bool,
+    /// Counts the clips saved to the library, the library is reread when it changes
+    #[serde(default)]
+    pub clips_saved: u64,
 }
 
 pub struct PlayingSound {
@@ -50,17 +110,166 @@ pub struct PlayingSound {
     pub sink: Player,
     pub path: PathBuf,
     pub duration: Option<f32>,
-    pub looped: bool,
     pub volume: f32,
+    pub slot: Option<String>,
+    pub choke_group: Option<String>,
+    pub meter: Arc<LevelMeter>,
+    pub pitch: Arc<PitchControl>,
+    pub effects: Arc<EffectControl>,
+    pub loop_control: Arc<LoopControl>,
+    pub buses: Vec<String>,
+    /// Bit mask over the buses, picks the mixer of the router
+    pub route: u64,
+}
+
+impl PlayingSound {
+    pub fn looped(&self) -> bool {
+        self.loop_control.enabled()
+    }
+
+    /// `count` limits how many more times the loop plays, including the current pass
+    pub fn set_looped(&self, enabled: bool, count: Option<u32>) {
+        self.loop_control.set(enabled, count);
+    }
+
+    /// Position within the sound, the player's clock keeps running across loop restarts
+    pub fn position(&self) -> f32 {
+        self.loop_control
+            .position()
+            .unwrap_or_else(|| self.sink.get_pos().as_secs_f32())
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct PlayOptions {
+    pub concurrent: bool,
+    /// Hotkey slot the sound was started from
+    pub slot: Option<String>,
+    /// Voice limit for the slot, only used together with `slot`
+    pub max_voices: Option<usize>,
+    /// Overrides the choke group from the sound settings
+    pub choke_group: Option<String>,
+    /// Names of the buses to play on, every bus when empty
+    pub buses: Vec<String>,
+    /// Pitch shift in semitones, applied from the first sample
+    pub pitch: f32,
+}
+
+enum LimitScope {
+    Slot,
+    Sound,
+    Global,
+}
+
+/// A virtual mic and the daemon stream feeding it.
+struct Bus {
+    settings: BusSettings,
+    control: Arc<BusControl>,
+    link: Option<PwTerminator>,
+    stream: Option<PlaybackStream>,
+}
+
+impl Bus {
+    /// Starts the bus stream if it is not running and links it to the virtual mic.
+    ///
+    /// The stream of the `main` bus only carries the voice changer, its sounds are linked in
+    /// straight from the player stream. `index` is the position of the bus, which the recorder
+    /// asks for.
+    async fn start(&mut self, index: usize, main: bool, tap: &Arc<RecordTap>) -> Result<()> {
+        if self.stream.is_none() {
+            let format = with_daemon_config(|c| self.settings.format(&c.audio));
+            let channels: ChannelCount = (format.channels as u16).try_into()?;
+            let sample_rate: SampleRate = format.rate.try_into()?;
+            let mut sounds =
+                (!main).then(|| BusInput::new(self.control.clone(), channels, sample_rate));
+            let control = self.control.clone();
+            let tap = tap.clone();
+            let mut mic = VecDeque::with_capacity(control.max_samples());
+            let mut sounds_only = Vec::with_capacity(control.max_samples());
+
+            let stream = create_bus_stream(
+                &self.settings.name,
+                format,
+                Box::new(move |samples, _channels| {
+                    control.take_mic(&mut mic);
+                    let volume = control.volume();
+                    let record_sounds = !main && tap.wants(RecordSource::Sounds, index);
+                    sounds_only.clear();
+                    for sample in samples.iter_mut() {
+                        let sound = sounds.as_mut().and_then(|s| s.next()).unwrap_or(0.0);
+                        let sound = sound * volume;
+                        if record_sounds {
+                            sounds_only.push(sound);
+                        }
+                        *sample = sound + mic.pop_front().unwrap_or(0.0);
+                    }
+
+                    if record_sounds {
+                        tap.push(&sounds_only);
+                    }
+                }),
+            )
+            .await?;
+            self.stream = Some(stream);
+        }
+
+        if self.link.is_none()
+            && let Some(stream) = &self.stream
+        {
+            self.link = Some(link_stream_to(stream, &self.settings.node_name()).await?);
+        }
+        Ok(())
+    }
+
+    fn info(&self) -> BusInfo {
+        BusInfo {
+            name: self.settings.name.clone(),
+            description: self.settings.description.clone(),
+            node: self.settings.node_name(),
+            mic: self.control.mic_enabled(),
+            volume: self.control.volume(),
+            linked: self.link.is_some(),
+        }
+    }
 }
 
 pub struct AudioPlayer {
-    stream_handle: Option<MixerDeviceSink>,
+    stream_handle: Option<PlaybackStream>,
+    /// Link from the player stream into the virtual mic of the main bus
+    player_link: Option<PwTerminator>,
+    /// Every track is mixed into the buses it is routed to here
+    router: Option<BusRouter>,
+    buses: Vec<Bus>,
+    /// Index of the bus the player stream is linked into
+    main_bus: usize,
+    /// Links from the player stream into app capture streams, by their node id
+    route_links: HashMap<u32, PwTerminator>,
+    pub limiter: Arc<LimiterControl>,
+    pub master_effects: Arc<EffectControl>,
+    master_meter: Arc<LevelMeter>,
+    input_meter: Arc<LevelMeter>,
+    pub voice_changer: Arc<VoiceChangerControl>,
+    sample_cache: Arc<Mutex<SampleCache>>,
+    record_tap: Arc<RecordTap>,
+    recording: Option<Recording>,
+    /// Capture of the virtual mic while its mix is recorded
+    record_capture: Option<PwTerminator>,
+    clips_saved: Arc<AtomicU64>,
+    replay: Arc<ReplayBuffer>,
+    /// Capture of the replay source, unused while the microphone is buffered
+    replay_capture: Option<PwTerminator>,
+    replay_info: Arc<StreamInfo>,
     pub tracks: HashMap<u32, PlayingSound>,
     pub next_id: u32,
+    pub random_picker: RandomPicker,
 
+    /// Microphone capture feeding the meter, recorder, replay buffer and voice changer
     input_link_sender: Option<PwTerminator>,
-    player_link_sender: Option<PwTerminator>,
+    /// Ports of the microphone, in the order they are captured
+    input_ports: Vec<Port>,
+    /// Links from the microphone straight into the virtual mics while the voice changer is off
+    mic_links: Vec<PwTerminator>,
+    input_info: Arc<StreamInfo>,
     pub input_device_name: Option<String>,
 
     pub volume: f32, // Master volume
@@ -69,50 +278,164 @@ pub struct AudioPlayer {
 
 impl AudioPlayer {
     pub async fn new() -> Result<Self> {
-        let (default_input_name, default_volume, default_volume_multiplier) =
-            with_daemon_config(|c| {
-                (
-                    c.default_input_name.clone(),
-                    c.default_volume.unwrap_or(1.0),
-                    c.default_volume_multiplier.unwrap_or(1.0),
-                )
-            });
+        let (
+            default_input_name,
+            default_volume,
+            default_volume_multiplier,
+            limiter,
+            master_effects,
+            voice_preset,
+            cache_budget,
+            buses,
+        ) = with_daemon_config(|c| {
+            (
+                c.default_input_name.clone(),
+                c.default_volume.unwrap_or(1.0),
+                c.default_volume_multiplier.unwrap_or(1.0),
+                c.limiter,
+                c.master_effects.clone(),
+                c.voice_changer.active(),
+                c.sample_cache.budget_bytes(),
+                (configured_buses(&c.buses), c.audio),
+            )
+        });
+        let (buses, audio) = buses;
+        let main_bus = main_bus(&buses);
 
         let mut audio_player = AudioPlayer {
             stream_handle: None,
+            player_link: None,
+            router: None,
+            route_links: HashMap::new(),
+            buses: buses
+                .into_iter()
+                .map(|settings| Bus {
+                    control: Arc::new(BusControl::new(&settings, settings.format(&audio))),
+                    settings,
+                    link: None,
+                    stream: None,
+                })
+                .collect(),
+            main_bus,
+            limiter: Arc::new(LimiterControl::new(limiter)),
+            master_effects: Arc::new(EffectControl::new(master_effects)),
+            master_meter: Arc::new(LevelMeter::default()),
+            input_meter: Arc::new(LevelMeter::default()),
+            voice_changer: Arc::new(VoiceChangerControl::new(voice_preset)),
+            sample_cache: Arc::new(Mutex::new(SampleCache::new(cache_budget))),
+            record_tap: Arc::new(RecordTap::default()),
+            recording: None,
+            record_capture: None,
+            clips_saved: Arc::new(AtomicU64::new(0)),
+            replay: Arc::new(ReplayBuffer::default()),
+            replay_capture: None,
+            replay_info: Arc::new(StreamInfo::default()),
             tracks: HashMap::new(),
             next_id: 1,
+            random_picker: RandomPicker::default(),
 
             input_link_sender: None,
-            player_link_sender: None,
+            input_ports: vec![],
+            mic_links: vec![],
+            input_info: Arc::new(StreamInfo::default()),
             input_device_name: default_input_name,
 
             volume: default_volume,
             volume_multiplier: default_volume_multiplier,
         };
 
+        audio_player.start_buses().await;
         if audio_player.input_device_name.is_some() {
             audio_player.link_devices().await?;
         }
+        audio_player.start_replay().await;
+        audio_player.preload_sounds();
 
         Ok(audio_player)
     }
 
-    fn ensure_stream(&mut self) -> Result<&MixerDeviceSink> {
-        if self.stream_handle.is_none() {
-            let mut sink = DeviceSinkBuilder::open_default_sink()?;
-            sink.log_on_drop(false);
-            self.stream_handle = Some(sink);
+    async fn ensure_stream(&mut self) -> Result<()> {
+        if self.stream_handle.is_some() {
+            return Ok(());
         }
-        self.stream_handle
-            .as_ref()
-            .ok_or_else(|| anyhow!("Failed to initialize stream_handle"))
+
+        let (format, audio) = with_daemon_config(|c| (c.audio.player_format(), c.audio));
+        let channels: ChannelCount = (format.channels as u16).try_into()?;
+        let sample_rate: SampleRate = format.rate.try_into()?;
+        let main = self.main_bus;
+        let (router, main_mix) = BusRouter::new(
+            channels,
+            sample_rate,
+            main,
+            self.buses
+                .iter()
+                .map(|bus| {
+                    let channels = bus.settings.format(&audio).channels as usize;
+                    (bus.control.clone(), channels)
+                })
+                .collect(),
+        );
+        let mut output = Metered::new(
+            Limiter::new(
+                Effected::new(main_mix, self.master_effects.clone()),
+                self.limiter.clone(),
+            ),
+            self.master_meter.clone(),
+        );
+
+        // Pulling the main bus is what feeds the others, so it runs for as long as tracks play
+        let tap = self.record_tap.clone();
+        let stream = create_player_stream(
+            format,
+            Box::new(move |samples, _channels| {
+                for sample in samples.iter_mut() {
+                    *sample = output.next().unwrap_or(0.0);
+                }
+                if tap.wants(RecordSource::Sounds, main) {
+                    tap.push(samples);
+                }
+            }),
+        )
+        .await?;
+
+        self.stream_handle = Some(stream);
+        self.router = Some(router);
+        self.link_player().await;
+        self.update_routes().await;
+        Ok(())
     }
 
     fn drop_stream(&mut self) {
         if self.stream_handle.is_some() {
+            self.route_links.clear();
+            self.player_link = None;
+            self.router = None;
             self.stream_handle = None;
-            self.abort_player_link_thread();
+        }
+    }
+
+    /// Links the player stream straight into the virtual mic of the main bus.
+    async fn link_player(&mut self) {
+        let Some(stream) = &self.stream_handle else {
+            return;
+        };
+        if self.player_link.is_some() {
+            return;
+        }
+
+        let target = self.buses[self.main_bus].settings.node_name();
+        match link_stream_to(stream, &target).await {
+            Ok(link) => self.player_link = Some(link),
+            Err(err) => eprintln!("Failed to link the player to {}: {}", target, err),
+        }
+    }
+
+    async fn start_buses(&mut self) {
+        let main = self.main_bus;
+        for (index, bus) in self.buses.iter_mut().enumerate() {
+            if let Err(err) = bus.start(index, index == main, &self.record_tap).await {
+                eprintln!("Failed to start bus {}: {}", bus.settings.name, err);
+            }
         }
     }
 
@@ -121,26 +444,35 @@ impl AudioPlayer {
             println!("Sent terminate signal to input link thread");
             self.input_link_sender = None;
         }
+        self.input_ports.clear();
+        self.mic_links.clear();
     }
 
-    fn abort_player_link_thread(&mut self) {
-        if self.player_link_sender.is_some() {
-            println!("Sent terminate signal to player link thread");
-            self.player_link_sender = None;
+    /// Links the microphone straight into the virtual mics of the buses that take it. While
+    /// the voice changer is on the capture stream feeds them the processed voice instead.
+    async fn link_mic(&mut self) {
+        self.mic_links.clear();
+        if self.voice_changer.is_active() || self.input_ports.is_empty() {
+            return;
         }
-    }
 
-    async fn link_player(&mut self) -> Result<()> {
-        if self.player_link_sender.is_some() {
-            return Ok(());
+        for bus in self.buses.iter().filter(|bus| bus.control.mic_enabled()) {
+            match link_ports_to(&self.input_ports, &bus.settings.node_name()).await {
+                Ok(links) => self.mic_links.push(links),
+                Err(err) => eprintln!(
+                    "Failed to link the microphone to bus {}: {}",
+                    bus.settings.name, err
+                ),
+            }
         }
+    }
 
-        match link_player_to_virtual_mic().await {
-            Ok(sender) => {
-                self.player_link_sender = Some(sender);
-                Ok(())
-            }
-            Err(_) => Ok(()),
+    /// Switches the voice changer preset, `None` links the microphone straight through again.
+    pub async fn set_voice_preset(&mut self, preset: Option<VoicePreset>) {
+        let was_active = self.voice_changer.is_active();
+        self.voice_changer.set_preset(preset);
+        if was_active != self.voice_changer.is_active() {
+            self.link_mic().await;
         }
     }
 
@@ -163,36 +495,107 @@ impl AudioPlayer {
             return Ok(());
         }
 
-        let daemon_input;
-        if let Ok(device) = get_device("pwsp-virtual-mic").await {
-            daemon_input = device;
+        // Metered before the voice changer so the level shows the microphone itself
+        let format = with_daemon_config(|c| c.audio.virtual_mic_format());
+        let mut levels = LevelAccumulator::new(
+            self.input_meter.clone(),
+            format.rate,
+            format.channels as u16,
+        );
+        let mut voice_changer =
+            VoiceChanger::new(self.voice_changer.clone(), format.rate, format.channels);
+
+        let buses: Vec<Arc<BusControl>> =
+            self.buses.iter().map(|bus| bus.control.clone()).collect();
+        let tap = self.record_tap.clone();
+        let replay = self.replay.clone();
+        let info = self.input_info.clone();
+        let mut buffer = vec![];
+        let on_samples = Box::new(move |samples: &[f32], channels: u32| {
+            if let Some(format) = info.format() {
+                levels.set_format(format.rate, format.channels as u16);
+            }
+            levels.push_all(samples);
+            if tap.wants(RecordSource::Mic, 0) {
+                tap.push(samples);
+            }
+            replay.push_mic(samples, channels);
+            buffer.clear();
+            buffer.extend_from_slice(samples);
+            // Without a preset the microphone reaches the virtual mics through direct links
+            if voice_changer.process(&mut buffer) {
+                for bus in &buses {
+                    bus.push_mic(&buffer, channels as usize);
+                }
+            }
+        });
+
+        let input_channels = with_daemon_config(|c| c.input_channels.clone());
+        let ports = input_device.capture_ports(&input_channels);
+        let capture = if input_channels.is_empty() {
+            create_capture_stream(
+                PASSTHROUGH_NODE,
+                &input_device.name,
+                false,
+                format,
+                self.input_info.clone(),
+                on_samples,
+            )
+            .await
         } else {
-            eprintln!("Could not find pwsp-virtual-mic device, skipping device linking");
-            return Ok(());
-        }
-
-        let Some(output_fl) = input_device.output_fl.clone() else {
-            eprintln!("Failed to get pwsp-daemon output_fl");
-            return Ok(());
-        };
-        let Some(output_fr) = input_device.output_fr.clone() else {
-            eprintln!("Failed to get pwsp-daemon output_fr");
-            return Ok(());
-        };
-        let Some(input_fl) = daemon_input.input_fl.clone() else {
-            eprintln!("Failed to get pwsp-daemon input_fl");
-            return Ok(());
-        };
-        let Some(input_fr) = daemon_input.input_fr.clone() else {
-            eprintln!("Failed to get pwsp-daemon input_fr");
-            return Ok(());
+            match ports.clone() {
+                Ok(ports) => {
+                    create_channel_capture(
+                        PASSTHROUGH_NODE,
+                        ports,
+                        format,
+                        self.input_info.clone(),
+                        on_samples,
+                    )
+                    .await
+                }
+                Err(err) => Err(anyhow!(err)),
+            }
         };
 
-        self.input_link_sender = Some(create_link(output_fl, output_fr, input_fl, input_fr).await?);
+        match capture {
+            Ok(capture) => self.input_link_sender = Some(capture),
+            Err(err) => eprintln!("{}, skipping device linking", err),
+        }
+        if self.input_link_sender.is_some() {
+            self.input_ports = ports.unwrap_or_default();
+            self.link_mic().await;
+        }
 
         Ok(())
     }
 
+    /// Decodes hotkey-bound and pinned sounds into the sample cache in the background and drops
+    /// the ones that are neither anymore. Called whenever hotkeys or sound settings change.
+    pub fn preload_sounds(&self) {
+        let cache = self.sample_cache.clone();
+        tokio::task::spawn_blocking(move || {
+            let targets = preload_targets(
+                &HotkeyConfig::load().unwrap_or_default(),
+                &SoundConfig::load().unwrap_or_default(),
+            );
+            let budget = with_daemon_config(|c| c.sample_cache.budget_bytes());
+
+            let mut paths: Vec<PathBuf> = targets.iter().cloned().collect();
+            paths.sort();
+            {
+                let mut cache = cache.lock().unwrap();
+                cache.set_budget(budget);
+                cache.set_targets(targets);
+            }
+            load_into(&cache, &paths, false);
+        });
+    }
+
+    pub fn get_sample_cache(&self) -> SampleCacheInfo {
+        self.sample_cache.lock().unwrap().info()
+    }
+
     pub fn pause(&mut self, id: Option<u32>) {
         if let Some(id) = id {
             if let Some(sound) = self.tracks.get_mut(&id) {
@@ -285,14 +688,65 @@ impl AudioPlayer {
         }
     }
 
+    /// Varispeed for one track, or every track when `id` is unset.
+    pub fn set_speed(&mut self, speed: f32, id: Option<u32>) -> Result<()> {
+        let speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        for sound in self.tracks_mut(id)? {
+            sound.sink.set_speed(speed);
+        }
+        Ok(())
+    }
+
+    /// Tempo-preserving pitch shift for one track, or every track when `id` is unset.
+    pub fn set_pitch(&mut self, semitones: f32, id: Option<u32>) -> Result<()> {
+        for sound in self.tracks_mut(id)? {
+            sound.pitch.set_semitones(semitones);
+        }
+        Ok(())
+    }
+
+    /// Replaces the effect chain of a track, or of the master bus when `id` is unset.
+    pub fn set_effects(&mut self, effects: Vec<Effect>, id: Option<u32>) -> Result<()> {
+        match id {
+            Some(_) => {
+                for sound in self.tracks_mut(id)? {
+                    sound.effects.set_chain(effects.clone());
+                }
+            }
+            None => self.master_effects.set_chain(effects),
+        }
+        Ok(())
+    }
+
+    pub fn get_effects(&self, id: Option<u32>) -> Result<Vec<Effect>> {
+        match id {
+            Some(id) => self
+                .tracks
+                .get(&id)
+                .map(|sound| sound.effects.chain())
+                .ok_or_else(|| anyhow!("Track {} is not playing", id)),
+            None => Ok(self.master_effects.chain()),
+        }
+    }
+
+    fn tracks_mut(&mut self, id: Option<u32>) -> Result<Vec<&mut PlayingSound>> {
+        match id {
+            Some(id) => match self.tracks.get_mut(&id) {
+                Some(sound) => Ok(vec![sound]),
+                None => Err(anyhow!("Track {} is not playing", id)),
+            },
+            None => Ok(self.tracks.values_mut().collect()),
+        }
+    }
+
     pub fn get_position(&self, id: Option<u32>) -> f32 {
         if let Some(id) = id {
             if let Some(sound) = self.tracks.get(&id) {
-                return sound.sink.get_pos().as_secs_f32();
+                return sound.position();
             }
         } else if let Some(sound) = self.tracks.values().last() {
             // Fallback to last added track if no ID
-            return sound.sink.get_pos().as_secs_f32();
+            return sound.position();
         }
         0.0
     }
@@ -324,73 +778,261 @@ impl AudioPlayer {
         Err(anyhow!("No track playing"))
     }
 
+    /// Works out which tracks to stop before a new one can start, honoring choke groups,
+    /// per-slot, per-sound and global voice limits.
+    fn make_room(
+        &self,
+        file_path: &Path,
+        settings: &SoundSettings,
+        options: &PlayOptions,
+    ) -> Result<Vec<u32>> {
+        let (max_voices, policy) = with_daemon_config(|c| (c.max_voices, c.voice_stealing));
+
+        let mut victims: Vec<u32> = vec![];
+        if let Some(group) = &options.choke_group {
+            victims.extend(
+                self.tracks
+                    .values()
+                    .filter(|s| s.choke_group.as_ref() == Some(group))
+                    .map(|s| s.id),
+            );
+        }
+
+        let slot_max_voices = options.slot.as_ref().and(options.max_voices);
+        let limits = [
+            (slot_max_voices, LimitScope::Slot),
+            (settings.max_voices, LimitScope::Sound),
+            (max_voices, LimitScope::Global),
+        ];
+
+        for (limit, scope) in limits {
+            let Some(limit) = limit else {
+                continue;
+            };
+
+            let voices: Vec<Voice> = self
+                .tracks
+                .values()
+                .filter(|s| !victims.contains(&s.id))
+                .filter(|s| match scope {
+                    LimitScope::Slot => s.slot.is_some() && s.slot == options.slot,
+                    LimitScope::Sound => s.path == file_path,
+                    LimitScope::Global => true,
+                })
+                // What the track measures right now, at its volume
+                .map(|s| Voice {
+                    id: s.id,
+                    loudness: s.meter.level().scaled(s.sink.volume()).rms,
+                })
+                .collect();
+
+            match pick_victims(&voices, limit, policy) {
+                Some(ids) => victims.extend(ids),
+                None => return Err(anyhow!("Voice limit reached ({} voices)", limit)),
+            }
+        }
+
+        Ok(victims)
+    }
+
     pub async fn play(&mut self, file_path: &Path, concurrent: bool) -> Result<u32> {
-        let path_buf = file_path.to_path_buf();
+        self.play_with_options(
+            file_path,
+            PlayOptions {
+                concurrent,
+                ..Default::default()
+            },
+        )
+        .await
+    }
 
-        let decoder_result =
-            tokio::task::spawn_blocking(move || -> Result<_, Box<dyn Error + Send + Sync>> {
-                if !path_buf.exists() {
-                    return Err(format!("File does not exist: {}", path_buf.display()).into());
-                }
+    pub async fn play_with_options(
+        &mut self,
+        file_path: &Path,
+        mut options: PlayOptions,
+    ) -> Result<u32> {
+        // Read once, the settings are used all through starting the sound
+        let settings = SoundConfig::load()
+            .map(|c| c.get(file_path))
+            .unwrap_or_default();
+        if options.choke_group.is_none() {
+            options.choke_group = settings.choke_group.clone();
+        }
+        let effects = Arc::new(EffectControl::new(settings.effects.clone()));
 
-                let file = fs::File::open(&path_buf)?;
-                let decoder = Decoder::try_from(file)
-                    .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
-                Ok(decoder)
-            })
-            .await?;
+        let mask = self.bus_mask(&options.buses)?;
+        let victims = if options.concurrent {
+            self.make_room(file_path, &settings, &options)?
+        } else {
+            vec![]
+        };
 
-        match decoder_result {
-            Ok(source) => {
-                if !concurrent {
-                    self.tracks.clear();
+        let cached = self.sample_cache.lock().unwrap().get(file_path);
+        let loop_control = Arc::new(LoopControl::default());
+        let source: Box<dyn Source + Send> = match cached {
+            // Straight from memory, no disk access or decoding before the first sample
+            Some(sound) => {
+                let silence = with_daemon_config(|c| c.silence_trim);
+                let settings = cached_playback_settings(&sound, &settings, &silence);
+                Box::new(Looped::new(
+                    trimmed(sound.source(), &settings),
+                    LoopRegion::from_settings(&settings),
+                    loop_control.clone(),
+                ))
+            }
+            None => {
+                let path_buf = file_path.to_path_buf();
+                let control = loop_control.clone();
+                let decoder_result = tokio::task::spawn_blocking(
+                    move || -> Result<_, Box<dyn Error + Send + Sync>> {
+                        if !path_buf.exists() {
+                            return Err(
+                                format!("File does not exist: {}", path_buf.display()).into()
+                            );
+                        }
+
+                        let silence = with_daemon_config(|c| c.silence_trim);
+                        let settings = playback_settings(&path_buf, &settings, &silence);
+                        let source = open_sound(&path_buf, &settings)
+                            .map_err(|e| Box::<dyn Error + Send + Sync>::from(e.to_string()))?;
+                        Ok(Looped::new(
+                            source,
+                            LoopRegion::from_settings(&settings),
+                            control,
+                        ))
+                    },
+                )
+                .await?;
+                let source = decoder_result.map_err(|err| anyhow!(err))?;
+
+                // Evicted earlier, bring it back for the next trigger
+                if self.sample_cache.lock().unwrap().is_target(file_path) {
+                    let cache = self.sample_cache.clone();
+                    let paths = vec![file_path.to_path_buf()];
+                    tokio::task::spawn_blocking(move || load_into(&cache, &paths, true));
                 }
 
-                self.ensure_stream()?;
-                self.link_player().await.ok();
-
-                let id = self.next_id;
-                self.next_id += 1;
+                Box::new(source)
+            }
+        };
 
-                let duration = source.total_duration().map(|d| d.as_secs_f32());
+        if !options.concurrent {
+            self.tracks.clear();
+        }
+        for id in victims {
+            self.tracks.remove(&id);
+        }
 
-                let mixer = self
-                    .stream_handle
-                    .as_ref()
-                    .ok_or_else(|| anyhow::anyhow!("stream_handle is unexpectedly missing"))?
-                    .mixer();
-                let sink = Player::connect_new(mixer);
-                sink.set_volume(self.volume * self.volume_multiplier); // Default volume is 1.0 * master
-                sink.append(source);
-                sink.play();
+        self.ensure_stream().await?;
+
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let duration = source.total_duration().map(|d| d.as_secs_f32());
+        let meter = Arc::new(LevelMeter::default());
+
+        let mixer = self
+            .router
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("router is unexpectedly missing"))?
+            .mixer(mask);
+        let sink = Player::connect_new(&mixer);
+        sink.set_volume(self.volume * self.volume_multiplier); // Default volume is 1.0 * master
+        let pitch = Arc::new(PitchControl::default());
+        pitch.set_semitones(options.pitch);
+        sink.append(Metered::new(
+            Effected::new(PitchShifted::new(source, pitch.clone()), effects.clone()),
+            meter.clone(),
+        ));
+        sink.play();
+
+        let sound = PlayingSound {
+            id,
+            sink,
+            path: file_path.to_path_buf(),
+            duration,
+            volume: 1.0,
+            slot: options.slot,
+            choke_group: options.choke_group,
+            meter,
+            pitch,
+            effects,
+            loop_control,
+            buses: self
+                .buses
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| mask & (1 << index) != 0)
+                .map(|(_, bus)| bus.settings.name.clone())
+                .collect(),
+            route: mask,
+        };
 
-                let sound = PlayingSound {
-                    id,
-                    sink,
-                    path: file_path.to_path_buf(),
-                    duration,
-                    looped: false,
-                    volume: 1.0,
-                };
+        self.tracks.insert(id, sound);
 
-                self.tracks.insert(id, sound);
+        Ok(id)
+    }
 
-                Ok(id)
-            }
-            Err(err) => Err(anyhow!(err)),
+    /// Bit mask over the buses with the given names, every bus when none are given.
+    fn bus_mask(&self, names: &[String]) -> Result<u64> {
+        if names.is_empty() {
+            return Ok((0..self.buses.len()).fold(0, |mask, index| mask | 1 << index));
         }
+
+        names.iter().try_fold(0, |mask, name| {
+            let index = self
+                .buses
+                .iter()
+                .position(|bus| bus.settings.name == *name)
+                .ok_or_else(|| anyhow!("Unknown bus '{}'", name))?;
+            Ok(mask | 1 << index)
+        })
+    }
+
+    fn bus_mut(&mut self, name: &str) -> Result<&mut Bus> {
+        self.buses
+            .iter_mut()
+            .find(|bus| bus.settings.name == name)
+            .ok_or_else(|| anyhow!("Unknown bus '{}'", name))
+    }
+
+    pub fn get_buses(&self) -> Vec<BusInfo> {
+        self.buses.iter().map(Bus::info).collect()
+    }
+
+    /// Settings of every bus as they are now, to store in the daemon config
+    pub fn bus_settings(&self) -> Vec<BusSettings> {
+        self.buses.iter().map(|bus| bus.settings.clone()).collect()
+    }
+
+    /// Volume of the sounds on one bus
+    pub fn set_bus_volume(&mut self, name: &str, volume: f32) -> Result<()> {
+        let bus = self.bus_mut(name)?;
+        bus.control.set_volume(volume);
+        bus.settings.volume = bus.control.volume();
+        Ok(())
+    }
+
+    /// Turns the microphone passthrough into one bus on or off
+    pub async fn set_bus_mic(&mut self, name: &str, enabled: bool) -> Result<()> {
+        let bus = self.bus_mut(name)?;
+        bus.control.set_mic(enabled);
+        bus.settings.mic = enabled;
+        self.link_mic().await;
+        Ok(())
     }
 
-    pub fn set_loop(&mut self, enabled: bool, id: Option<u32>) {
+    /// `count` limits how many more times the loop plays, including the current pass.
+    pub fn set_loop(&mut self, enabled: bool, count: Option<u32>, id: Option<u32>) {
         if let Some(id) = id {
             if let Some(sound) = self.tracks.get_mut(&id) {
-                sound.looped = enabled;
+                sound.set_looped(enabled, count);
             }
         } else {
             // Set loop for all? Or just last?
             // Let's set for all.
             for sound in self.tracks.values_mut() {
-                sound.looped = enabled;
+                sound.set_looped(enabled, count);
             }
         }
     }
@@ -403,44 +1045,332 @@ impl AudioPlayer {
                 id: sound.id,
                 path: sound.path.clone(),
                 duration: sound.duration,
-                position: sound.sink.get_pos().as_secs_f32(),
+                position: sound.position(),
                 volume: sound.volume,
-                looped: sound.looped,
+                looped: sound.looped(),
+                loops_remaining: sound.loop_control.remaining(),
                 paused: sound.sink.is_paused(),
+                speed: sound.sink.speed(),
+                pitch: sound.pitch.semitones(),
+                effects: sound.effects.chain(),
+                buses: sound.buses.clone(),
             })
             .collect();
         tracks.sort_by_key(|t| t.id);
         tracks
     }
 
-    pub async fn update(&mut self, check_devices: bool) {
-        if check_devices {
-            if let Some(input_device_name) = &self.input_device_name {
-                // Unlink devices if selected input device was removed
-                if self.input_link_sender.is_some() && get_device(input_device_name).await.is_err()
-                {
-                    eprintln!(
-                        "Selected input device {} was removed, unlinking devices",
-                        input_device_name
-                    );
-                    self.abort_link_thread();
-                }
-                // Link devices if not linked
-                else if self.input_link_sender.is_none() {
-                    self.link_devices().await.ok();
+    pub fn get_audio_info(&self) -> AudioInfo {
+        AudioInfo {
+            configured: with_daemon_config(|c| c.audio),
+            player: self
+                .stream_handle
+                .as_ref()
+                .and_then(|stream| stream.info.format()),
+            passthrough: self
+                .input_link_sender
+                .as_ref()
+                .and_then(|_| self.input_info.format()),
+        }
+    }
+
+    /// Track levels are post-fader, the master level is what leaves the limiter.
+    pub fn get_levels(&self) -> Levels {
+        Levels {
+            master: self.master_meter.level(),
+            input: self
+                .input_link_sender
+                .as_ref()
+                .map(|_| self.input_meter.level()),
+            tracks: self
+                .tracks
+                .values()
+                .map(|sound| (sound.id, sound.meter.level().scaled(sound.sink.volume())))
+                .collect(),
+        }
+    }
+
+    /// Links the player stream into the app streams matching the route targets and lets go
+    /// of the ones that are gone or no longer targeted. Nothing is linked while no sound plays.
+    async fn update_routes(&mut self) {
+        let Some(stream) = &self.stream_handle else {
+            return;
+        };
+        let targets = with_daemon_config(|c| c.route_targets.clone());
+        let app_streams = match get_app_streams().await {
+            Ok(streams) => streams,
+            Err(err) => {
+                eprintln!("Failed to get app streams: {}", err);
+                return;
+            }
+        };
+
+        let targeted: Vec<AudioDevice> = app_streams
+            .into_iter()
+            .filter(|app| targets.iter().any(|target| route_matches(target, app)))
+            .collect();
+        self.route_links
+            .retain(|id, _| targeted.iter().any(|app| app.id == *id));
+
+        for app in targeted {
+            if self.route_links.contains_key(&app.id) || app.inputs.is_empty() {
+                continue;
+            }
+            match create_link(map_ports(&stream.outputs, &app.inputs)).await {
+                Ok(link) => {
+                    self.route_links.insert(app.id, link);
                 }
+                Err(err) => eprintln!("Failed to route sounds to {}: {}", app.name, err),
             }
+        }
+    }
+
+    pub async fn get_app_streams(&self) -> Result<Vec<AppStream>> {
+        let targets = with_daemon_config(|c| c.route_targets.clone());
+        Ok(get_app_streams()
+            .await?
+            .into_iter()
+            .map(|app| AppStream {
+                targeted: targets.iter().any(|target| route_matches(target, &app)),
+                linked: self.route_links.contains_key(&app.id),
+                id: app.id,
+                name: app.name,
+                app_name: app.app_name,
+                app_binary: app.app_binary,
+            })
+            .collect())
+    }
 
-            if self.stream_handle.is_some() && self.player_link_sender.is_none() {
-                self.link_player().await.ok();
+    /// Replaces the app streams the sounds are linked into, see [`route_matches`].
+    pub async fn set_route_targets(&mut self, targets: Vec<String>) {
+        with_daemon_config(|c| c.route_targets = targets);
+        self.update_routes().await;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn clips_saved(&self) -> u64 {
+        self.clips_saved.load(Ordering::Relaxed)
+    }
+
+    /// Starts writing `options.source` to clips in the PWSP audio dir. The mix of a bus is
+    /// captured from its virtual mic, so it has everything other apps hear.
+    pub async fn start_recording(&mut self, options: RecordOptions) -> Result<()> {
+        if self.recording.is_some() {
+            return Err(anyhow!("Already recording"));
+        }
+
+        let bus = match &options.bus {
+            Some(name) => self
+                .buses
+                .iter()
+                .position(|bus| &bus.settings.name == name)
+                .ok_or_else(|| anyhow!("Unknown bus '{}'", name))?,
+            None => self.main_bus,
+        };
+        let format = match (options.source, self.buses.get(bus)) {
+            (RecordSource::Mic, _) => with_daemon_config(|c| c.audio.virtual_mic_format()),
+            (_, Some(bus)) => with_daemon_config(|c| bus.settings.format(&c.audio)),
+            (_, None) => return Err(anyhow!("No bus to record")),
+        };
+
+        if options.source == RecordSource::Mix {
+            let tap = self.record_tap.clone();
+            self.record_capture = Some(
+                create_capture_stream(
+                    RECORD_NODE,
+                    &self.buses[bus].settings.node_name(),
+                    false,
+                    format,
+                    Arc::new(StreamInfo::default()),
+                    Box::new(move |samples: &[f32], _channels: u32| {
+                        if tap.wants(RecordSource::Mix, bus) {
+                            tap.push(samples);
+                        }
+                    }),
+                )
+                .await?,
+            );
+        }
+
+        let recording = Recording::start(
+            options,
+            self.record_tap.clone(),
+            bus,
+            ensure_pwsp_audio_dir()?,
+            format.channels as u16,
+            format.rate,
+            self.clips_saved.clone(),
+        );
+        match recording {
+            Ok(recording) => self.recording = Some(recording),
+            Err(err) => {
+                self.record_capture = None;
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Hands the running recording back so it can be finished without holding the player.
+    pub fn stop_recording(&mut self) -> Result<Recording> {
+        self.record_capture = None;
+        self.recording
+            .take()
+            .ok_or_else(|| anyhow!("Not recording"))
+    }
+
+    /// Sets the replay buffer up from the config and captures its source unless that is the
+    /// microphone, which feeds it from the passthrough.
+    async fn start_replay(&mut self) {
+        let (settings, format) =
+            with_daemon_config(|c| (c.replay.clone(), c.audio.virtual_mic_format()));
+        if !settings.enabled {
+            self.replay_capture = None;
+            self.replay.disable();
+            return;
+        }
+
+        self.replay.configure(
+            settings.seconds(),
+            format.rate,
+            format.channels,
+            settings.source.is_none(),
+        );
+        let Some(source) = settings.source else {
+            self.replay_capture = None;
+            return;
+        };
+        if self.replay_capture.is_some() {
+            return;
+        }
+
+        let device = match get_device(&source).await {
+            Ok(device) => device,
+            Err(err) => {
+                eprintln!("{}, not buffering replays", err);
+                return;
             }
+        };
+        let replay = self.replay.clone();
+        let capture = create_capture_stream(
+            REPLAY_NODE,
+            &device.name,
+            false,
+            format,
+            self.replay_info.clone(),
+            Box::new(move |samples: &[f32], channels: u32| replay.push(samples, channels)),
+        )
+        .await;
+        match capture {
+            Ok(capture) => self.replay_capture = Some(capture),
+            Err(err) => eprintln!("Failed to capture replay source {}: {}", device.name, err),
+        }
+    }
+
+    pub async fn set_replay(&mut self, settings: ReplaySettings) {
+        with_daemon_config(|c| c.replay = settings);
+        self.replay_capture = None;
+        self.start_replay().await;
+    }
+
+    /// Checks that the replay buffer is on and returns the save of its last `seconds`, all of
+    /// it when unset, to the PWSP audio dir. The save blocks while the file is written, run it
+    /// once the player is released.
+    pub fn save_replay(
+        &self,
+        seconds: Option<f32>,
+    ) -> Result<impl FnOnce() -> Result<PathBuf> + Send + use<>> {
+        if !self.replay.is_enabled() {
+            return Err(anyhow!("Replay buffer is disabled"));
         }
 
-        // Handle looped sounds
+        let replay = self.replay.clone();
+        let saved = self.clips_saved.clone();
+        let dir = ensure_pwsp_audio_dir()?;
+        Ok(move || {
+            let path = replay.save(seconds, &dir)?;
+            saved.fetch_add(1, Ordering::Relaxed);
+            Ok(path)
+        })
+    }
+
+    /// Starts over on a new PipeWire connection. The streams and links died with the old
+    /// one, so the sounds that were playing are stopped and everything else is recreated.
+    pub async fn reconnect(&mut self) {
+        self.stop(None);
+        self.drop_stream();
+        self.record_capture = None;
+        for bus in &mut self.buses {
+            bus.link = None;
+            bus.stream = None;
+        }
+        self.input_link_sender = None;
+        self.mic_links.clear();
+        self.replay_capture = None;
+
+        self.start_buses().await;
+        if self.input_device_name.is_some() {
+            self.link_devices().await.ok();
+        }
+        self.start_replay().await;
+    }
+
+    /// Unlinks the microphone when it went away and links it, or a bus to its virtual mic,
+    /// when it is back. Called whenever an audio source is added or removed.
+    ///
+    /// Returns whether everything configured is linked, the daemon calls it again until it is.
+    pub async fn check_devices(&mut self) -> bool {
+        if let Some(input_device_name) = &self.input_device_name {
+            // Unlink devices if selected input device was removed
+            if self.input_link_sender.is_some() && get_device(input_device_name).await.is_err() {
+                eprintln!(
+                    "Selected input device {} was removed, unlinking devices",
+                    input_device_name
+                );
+                self.abort_link_thread();
+            }
+            // Link devices if not linked
+            else if self.input_link_sender.is_none() {
+                self.link_devices().await.ok();
+            }
+        }
+
+        if self.buses.iter().any(|bus| bus.link.is_none()) {
+            self.start_buses().await;
+            // A virtual mic that came back needs the microphone linked again as well
+            self.link_mic().await;
+        }
+        self.link_player().await;
+
+        // The replay source comes and goes like the microphone
+        if self.replay_capture.is_some()
+            && let Some(source) = with_daemon_config(|c| c.replay.source.clone())
+            && get_device(&source).await.is_err()
+        {
+            eprintln!("Replay source {} was removed", source);
+            self.replay_capture = None;
+        }
+        self.start_replay().await;
+
+        // Apps restarting come back as new streams
+        self.update_routes().await;
+
+        let replay = with_daemon_config(|c| c.replay.enabled && c.replay.source.is_some());
+        (self.input_device_name.is_none() || self.input_link_sender.is_some())
+            && self.buses.iter().all(|bus| bus.link.is_some())
+            && (self.stream_handle.is_none() || self.player_link.is_some())
+            && (!replay || self.replay_capture.is_some())
+    }
+
+    pub async fn update(&mut self) {
+        // Loops restart inside the source, this only catches sounds that could not seek back
         let mut restarts = vec![];
 
         for (id, sound) in &self.tracks {
-            if sound.sink.empty() && sound.looped {
+            if sound.sink.empty() && sound.loop_control.take_restart() {
                 restarts.push(*id);
             }
         }
@@ -450,13 +1380,17 @@ impl AudioPlayer {
         for id in restarts {
             if let Some(sound) = self.tracks.get(&id) {
                 let path = sound.path.clone();
+                let control = sound.loop_control.clone();
                 let handle = tokio::task::spawn_blocking(move || {
-                    if let Ok(file) = fs::File::open(&path)
-                        && let Ok(source) = Decoder::try_from(file)
-                    {
-                        return Some((id, source));
-                    }
-                    None
+                    let settings = SoundConfig::load()
+                        .map(|c| c.get(&path))
+                        .unwrap_or_default();
+                    let silence = with_daemon_config(|c| c.silence_trim);
+                    let settings = playback_settings(&path, &settings, &silence);
+                    open_sound(&path, &settings).ok().map(|source| {
+                        let region = LoopRegion::from_settings(&settings);
+                        (id, Looped::new(source, region, control))
+                    })
                 });
                 restart_futures.push(handle);
             }
@@ -467,19 +1401,46 @@ impl AudioPlayer {
                 && let Some((id, source)) = res
                 && let Some(sound) = self.tracks.get_mut(&id)
             {
-                sound.sink.append(source);
+                sound.sink.append(Metered::new(
+                    Effected::new(
+                        PitchShifted::new(source, sound.pitch.clone()),
+                        sound.effects.clone(),
+                    ),
+                    sound.meter.clone(),
+                ));
                 sound.sink.play();
             }
         }
 
         self.tracks
-            .retain(|_, sound| !sound.sink.empty() || sound.looped);
+            .retain(|_, sound| !sound.sink.empty() || sound.looped());
 
         if self.tracks.is_empty() {
             self.drop_stream();
+        } else if let Some(router) = &mut self.router {
+            let tracks = &self.tracks;
+            router.retain_routes(|mask| tracks.values().any(|sound| sound.route == mask));
         }
     }
 
+    /// Picks the channels of the input device used as the microphone and relinks it.
+    /// Empty lets PipeWire mix all of them down.
+    pub async fn set_input_channels(&mut self, channels: Vec<String>) -> Result<()> {
+        if !channels.is_empty()
+            && let Some(name) = &self.input_device_name
+        {
+            get_device(name)
+                .await?
+                .capture_ports(&channels)
+                .map_err(|e| anyhow!(e))?;
+        }
+
+        with_daemon_config(|c| c.input_channels = channels);
+        self.link_devices().await
+    }
+
+    /// Selects the microphone by [`DeviceSelector`]. A fuzzy or serial match is pinned to
+    /// the `node.name` it found, so it can't drift to another device later.
     pub async fn set_current_input_device(&mut self, name: &str) -> Result<()> {
         let input_device = get_device(name).await?;
 
@@ -487,7 +1448,11 @@ impl AudioPlayer {
             return Err(anyhow!("Selected device is not an input device"));
         }
 
-        self.input_device_name = Some(name.to_string());
+        self.input_device_name = Some(if DeviceSelector::parse(name).is_stable() {
+            name.to_string()
+        } else {
+            input_device.name
+        });
 
         self.link_devices().await?;
 
@@ -0,0 +1,682 @@
+use crate::{
+    types::pipewire::{AudioSettings, ChannelLayout, StreamFormat},
+    utils::pipewire::VIRTUAL_MIC_NODE,
+};
+use rodio::{
+    ChannelCount, SampleRate, Source,
+    mixer::{Mixer, MixerSource, mixer},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+    },
+    time::Duration,
+};
+
+/// Name of the bus that keeps the original virtual mic node
+pub const DEFAULT_BUS: &str = "default";
+/// Routes are bit masks over the buses
+const MAX_BUSES: usize = 64;
+/// Audio waiting for a bus stream beyond this is dropped. The daemon streams share a driver,
+/// so normally no more than one quantum waits.
+const MAX_BUS_LATENCY: Duration = Duration::from_millis(100);
+/// Frames the router collects before handing them to a bus
+const FLUSH_FRAMES: usize = 64;
+
+/// One virtual microphone, created when the daemon starts.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct BusSettings {
+    /// Picked by the `bus` argument of `play`
+    pub name: String,
+    /// Shown to other apps
+    pub description: String,
+    /// Mix the microphone passthrough into this bus
+    pub mic: bool,
+    /// Volume of the sounds on this bus, the microphone is not affected. On the main bus it
+    /// sets the monitor as well.
+    pub volume: f32,
+    /// `node.name` of the virtual mic, derived from `name` when unset
+    pub node_name: Option<String>,
+    /// Overrides `audio.virtual_mic_channels` for this virtual mic
+    pub channels: Option<ChannelLayout>,
+    /// `device.icon-name` shown by mixers and apps
+    pub icon: Option<String>,
+    /// Keep the virtual mic when the daemon exits, so apps don't lose it across restarts.
+    /// Changes to a lingering mic need a PipeWire restart to show up
+    pub linger: bool,
+}
+
+impl Default for BusSettings {
+    fn default() -> Self {
+        BusSettings {
+            name: DEFAULT_BUS.to_string(),
+            description: "PWSP Virtual Mic".to_string(),
+            mic: true,
+            volume: 1.0,
+            node_name: None,
+            channels: None,
+            icon: None,
+            linger: false,
+        }
+    }
+}
+
+impl BusSettings {
+    /// `node.name` of the virtual mic, the default bus keeps the name it always had
+    pub fn node_name(&self) -> String {
+        if let Some(node_name) = &self.node_name {
+            node_name.clone()
+        } else if self.name == DEFAULT_BUS {
+            VIRTUAL_MIC_NODE.to_string()
+        } else {
+            format!("{}-{}", VIRTUAL_MIC_NODE, self.name)
+        }
+    }
+
+    pub fn layout(&self, audio: &AudioSettings) -> ChannelLayout {
+        self.channels.unwrap_or(audio.virtual_mic_channels)
+    }
+
+    /// Format of the virtual mic and the bus stream
+    pub fn format(&self, audio: &AudioSettings) -> StreamFormat {
+        StreamFormat {
+            channels: self.layout(audio).channels(),
+            ..audio.virtual_mic_format()
+        }
+    }
+}
+
+/// Buses from the daemon config, the default bus alone when none are configured.
+/// Later buses with a name that is already taken and any beyond 64 are left out.
+pub fn configured_buses(buses: &[BusSettings]) -> Vec<BusSettings> {
+    if buses.is_empty() {
+        return vec![BusSettings::default()];
+    }
+
+    let mut unique: Vec<BusSettings> = vec![];
+    for bus in buses.iter().take(MAX_BUSES) {
+        if bus.name.is_empty() || unique.iter().any(|b| b.name == bus.name) {
+            eprintln!("Skipping bus with empty or duplicate name '{}'", bus.name);
+            continue;
+        }
+        unique.push(bus.clone());
+    }
+    unique
+}
+
+/// Index of the bus the player stream links straight into: the default bus, or the first one
+/// when none is called that. Its sounds are also what the default output plays.
+pub fn main_bus(buses: &[BusSettings]) -> usize {
+    buses
+        .iter()
+        .position(|bus| bus.name == DEFAULT_BUS)
+        .unwrap_or(0)
+}
+
+/// Reply entry of `get_buses`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BusInfo {
+    pub name: String,
+    pub description: String,
+    /// `node.name` of the virtual mic
+    pub node: String,
+    pub mic: bool,
+    pub volume: f32,
+    /// The bus stream is linked into its virtual mic
+    pub linked: bool,
+}
+
+/// State of one bus shared with the audio threads.
+///
+/// The player stream leaves the sounds routed to the bus here and the capture stream the
+/// microphone while the voice changer is on, the bus stream takes both out again. The main
+/// bus only queues the microphone, its sounds are linked in straight from the player stream.
+#[derive(Debug)]
+pub struct BusControl {
+    volume: AtomicU32,
+    mic: AtomicBool,
+    sounds: Mutex<VecDeque<f32>>,
+    mic_samples: Mutex<VecDeque<f32>>,
+    /// Scratch frame of the microphone conversion, so the capture callback doesn't allocate
+    frame: Mutex<Vec<f32>>,
+    max_samples: usize,
+    channels: usize,
+}
+
+impl BusControl {
+    /// `format` is the format of the bus stream
+    pub fn new(settings: &BusSettings, format: StreamFormat) -> Self {
+        let channels = format.channels.max(1) as usize;
+        let max_samples =
+            (MAX_BUS_LATENCY.as_secs_f32() * format.rate as f32 * channels as f32) as usize;
+        BusControl {
+            volume: AtomicU32::new(settings.volume.max(0.0).to_bits()),
+            mic: AtomicBool::new(settings.mic),
+            sounds: Mutex::new(VecDeque::with_capacity(max_samples)),
+            mic_samples: Mutex::new(VecDeque::with_capacity(max_samples)),
+            frame: Mutex::new(vec![0.0; channels]),
+            max_samples,
+            channels,
+        }
+    }
+
+    /// Upper bound of the queued samples, the bus stream sizes its own queues with it.
+    pub fn max_samples(&self) -> usize {
+        self.max_samples
+    }
+
+    pub fn volume(&self) -> f32 {
+        f32::from_bits(self.volume.load(Ordering::Relaxed))
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        self.volume
+            .store(volume.max(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn mic_enabled(&self) -> bool {
+        self.mic.load(Ordering::Relaxed)
+    }
+
+    pub fn set_mic(&self, enabled: bool) {
+        self.mic.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            lock(&self.mic_samples).clear();
+        }
+    }
+
+    pub fn push_sounds(&self, samples: &[f32]) {
+        push(&self.sounds, samples, self.max_samples);
+    }
+
+    /// Ignored while the microphone is off for this bus. `channels` is the channel count
+    /// of the capture, converted if the bus has another one.
+    pub fn push_mic(&self, samples: &[f32], channels: usize) {
+        if !self.mic_enabled() {
+            return;
+        }
+        if channels == self.channels {
+            push(&self.mic_samples, samples, self.max_samples);
+            return;
+        }
+
+        // Converted frame by frame straight into the queue
+        let channels = channels.max(1);
+        let frames = samples.len() / channels;
+        let kept = frames.min(self.max_samples / self.channels);
+        let mut queue = lock(&self.mic_samples);
+        let mut frame = self.frame.lock().unwrap_or_else(|e| e.into_inner());
+        let excess = (queue.len() + kept * self.channels).saturating_sub(self.max_samples);
+        queue.drain(..excess);
+        for input in samples.chunks_exact(channels).skip(frames - kept) {
+            remix(input, &mut frame);
+            queue.extend(frame.iter());
+        }
+    }
+
+    pub fn take_sounds(&self, into: &mut VecDeque<f32>) {
+        take(&self.sounds, into, self.max_samples);
+    }
+
+    pub fn take_mic(&self, into: &mut VecDeque<f32>) {
+        take(&self.mic_samples, into, self.max_samples);
+    }
+}
+
+fn lock(queue: &Mutex<VecDeque<f32>>) -> std::sync::MutexGuard<'_, VecDeque<f32>> {
+    queue.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Drops the oldest samples before adding new ones, so the queue never grows past the
+/// capacity it was created with.
+fn push(queue: &Mutex<VecDeque<f32>>, samples: &[f32], max_samples: usize) {
+    let samples = &samples[samples.len().saturating_sub(max_samples)..];
+    let mut queue = lock(queue);
+    let excess = (queue.len() + samples.len()).saturating_sub(max_samples);
+    queue.drain(..excess);
+    queue.extend(samples);
+}
+
+fn take(queue: &Mutex<VecDeque<f32>>, into: &mut VecDeque<f32>, max_samples: usize) {
+    let mut queue = lock(queue);
+    let excess = (into.len() + queue.len()).saturating_sub(max_samples);
+    let from_into = excess.min(into.len());
+    into.drain(..from_into);
+    queue.drain(..excess - from_into);
+    into.extend(queue.drain(..));
+}
+
+/// Converts one frame between channel counts, averaging down to mono and copying mono up.
+fn remix(input: &[f32], output: &mut [f32]) {
+    match (input.len(), output.len()) {
+        (a, b) if a == b => output.copy_from_slice(input),
+        (_, 1) => output[0] = input.iter().sum::<f32>() / input.len().max(1) as f32,
+        (1, _) => output.fill(input[0]),
+        _ => {
+            output.fill(0.0);
+            for (out, sample) in output.iter_mut().zip(input) {
+                *out = *sample;
+            }
+        }
+    }
+}
+
+/// Hands out one mixer per set of buses, so a track is decoded once however many buses
+/// it plays on. Sets are bit masks over the bus indices.
+pub struct BusRouter {
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+    mixers: HashMap<u64, Mixer>,
+    changes: Arc<Mutex<RouteChanges>>,
+}
+
+/// Routes handed between the router and the audio thread.
+#[derive(Default)]
+struct RouteChanges {
+    added: Vec<(u64, MixerSource)>,
+    removed: Vec<u64>,
+    /// Routes the audio thread let go of, dropped by the router
+    retired: Vec<MixerSource>,
+}
+
+impl BusRouter {
+    /// `buses` pairs each bus with the channel count of its stream. The returned source is
+    /// the mix of the `main` bus, which the player stream links straight into its virtual
+    /// mic. It has to be pulled for the other buses to get sound.
+    pub fn new(
+        channels: ChannelCount,
+        sample_rate: SampleRate,
+        main: usize,
+        buses: Vec<(Arc<BusControl>, usize)>,
+    ) -> (Self, RoutedMix) {
+        let changes = Arc::new(Mutex::new(RouteChanges::default()));
+        let frame_len = u16::from(channels).max(1) as usize;
+
+        let router = BusRouter {
+            channels,
+            sample_rate,
+            mixers: HashMap::new(),
+            changes: changes.clone(),
+        };
+        let mix = RoutedMix {
+            changes,
+            routes: vec![],
+            channels,
+            sample_rate,
+            main,
+            frame: vec![0.0; frame_len],
+            route_frame: vec![0.0; frame_len],
+            bus_frames: vec![vec![0.0; frame_len]; buses.len()],
+            converted: buses.iter().map(|(_, ch)| vec![0.0; *ch]).collect(),
+            pending: buses
+                .iter()
+                .map(|(_, ch)| Vec::with_capacity(FLUSH_FRAMES * ch))
+                .collect(),
+            buses: buses.into_iter().map(|(control, _)| control).collect(),
+            next: frame_len,
+        };
+        (router, mix)
+    }
+
+    fn changes(&self) -> std::sync::MutexGuard<'_, RouteChanges> {
+        self.changes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Mixer that feeds every bus in `mask`
+    pub fn mixer(&mut self, mask: u64) -> Mixer {
+        if let Some(mixer) = self.mixers.get(&mask) {
+            return mixer.clone();
+        }
+
+        let (mixer, source) = mixer(self.channels, self.sample_rate);
+        self.changes().added.push((mask, source));
+        self.mixers.insert(mask, mixer.clone());
+        mixer
+    }
+
+    /// Drops the mixers of the routes no track plays on anymore.
+    pub fn retain_routes(&mut self, keep: impl Fn(u64) -> bool) {
+        let unused: Vec<u64> = self
+            .mixers
+            .keys()
+            .filter(|mask| !keep(**mask))
+            .copied()
+            .collect();
+
+        let mut changes = self.changes.lock().unwrap_or_else(|e| e.into_inner());
+        changes.retired.clear();
+        for mask in unused {
+            self.mixers.remove(&mask);
+            changes.added.retain(|(added, _)| *added != mask);
+            changes.removed.push(mask);
+        }
+        // The audio thread moves routes over without allocating
+        let removed = changes.removed.len();
+        changes.retired.reserve(removed);
+    }
+}
+
+/// Pulls every route mixer frame by frame, adding the frame to the queue of each bus on the
+/// route. Returns the mix of the main bus and never ends.
+pub struct RoutedMix {
+    changes: Arc<Mutex<RouteChanges>>,
+    routes: Vec<(u64, MixerSource)>,
+    buses: Vec<Arc<BusControl>>,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+    main: usize,
+    frame: Vec<f32>,
+    route_frame: Vec<f32>,
+    bus_frames: Vec<Vec<f32>>,
+    /// One frame of each bus in the channel count of its stream
+    converted: Vec<Vec<f32>>,
+    pending: Vec<Vec<f32>>,
+    /// Next sample of `frame` to hand out
+    next: usize,
+}
+
+impl RoutedMix {
+    /// Never blocks the audio thread, changed routes can wait for the next frame
+    fn update_routes(&mut self) {
+        let Ok(mut changes) = self.changes.try_lock() else {
+            return;
+        };
+        let RouteChanges {
+            added,
+            removed,
+            retired,
+        } = &mut *changes;
+
+        for mask in removed.drain(..) {
+            if let Some(index) = self.routes.iter().position(|(route, _)| *route == mask) {
+                retired.push(self.routes.swap_remove(index).1);
+            }
+        }
+        self.routes.append(added);
+    }
+
+    fn render_frame(&mut self) {
+        self.update_routes();
+
+        for bus_frame in &mut self.bus_frames {
+            bus_frame.fill(0.0);
+        }
+
+        for (mask, route) in &mut self.routes {
+            // Empty mixers give nothing, one pull per channel keeps them aligned
+            for sample in self.route_frame.iter_mut() {
+                *sample = route.next().unwrap_or(0.0);
+            }
+            for (index, bus_frame) in self.bus_frames.iter_mut().enumerate() {
+                if *mask & (1 << index) != 0 {
+                    for (out, sample) in bus_frame.iter_mut().zip(&self.route_frame) {
+                        *out += sample;
+                    }
+                }
+            }
+        }
+
+        self.frame.fill(0.0);
+        if let Some(main) = self.buses.get(self.main) {
+            let volume = main.volume();
+            for (out, sample) in self.frame.iter_mut().zip(&self.bus_frames[self.main]) {
+                *out = sample * volume;
+            }
+        }
+
+        for (index, bus) in self.buses.iter().enumerate() {
+            if index == self.main {
+                continue;
+            }
+            let converted = &mut self.converted[index];
+            remix(&self.bus_frames[index], converted);
+
+            let pending = &mut self.pending[index];
+            pending.extend_from_slice(converted);
+            if pending.len() >= FLUSH_FRAMES * converted.len() {
+                bus.push_sounds(pending);
+                pending.clear();
+            }
+        }
+
+        self.next = 0;
+    }
+}
+
+impl Iterator for RoutedMix {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.frame.len() {
+            self.render_frame();
+        }
+        let sample = self.frame[self.next];
+        self.next += 1;
+        Some(sample)
+    }
+}
+
+impl Source for RoutedMix {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// The sounds routed to one bus, silent while none are queued. Never ends.
+pub struct BusInput {
+    control: Arc<BusControl>,
+    queued: VecDeque<f32>,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+    /// Samples to play silent before looking at the queue again
+    idle: usize,
+}
+
+impl BusInput {
+    pub fn new(control: Arc<BusControl>, channels: ChannelCount, sample_rate: SampleRate) -> Self {
+        BusInput {
+            queued: VecDeque::with_capacity(control.max_samples()),
+            control,
+            channels,
+            sample_rate,
+            idle: 0,
+        }
+    }
+}
+
+impl Iterator for BusInput {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.queued.is_empty() {
+            if self.idle > 0 {
+                self.idle -= 1;
+                return Some(0.0);
+            }
+            self.control.take_sounds(&mut self.queued);
+            if self.queued.is_empty() {
+                // Keeps the lock out of every sample while nothing plays, whole frames only
+                self.idle = FLUSH_FRAMES * u16::from(self.channels) as usize - 1;
+                return Some(0.0);
+            }
+        }
+        self.queued.pop_front()
+    }
+}
+
+impl Source for BusInput {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn control(channels: u32) -> Arc<BusControl> {
+        Arc::new(BusControl::new(
+            &BusSettings::default(),
+            StreamFormat {
+                rate: 48_000,
+                channels,
+                quantum: 512,
+            },
+        ))
+    }
+
+    fn drain(control: &BusControl) -> Vec<f32> {
+        let mut queued = VecDeque::new();
+        control.take_sounds(&mut queued);
+        queued.into()
+    }
+
+    #[test]
+    fn test_tracks_reach_only_their_buses() {
+        let channels: ChannelCount = 2u16.try_into().unwrap();
+        let sample_rate: SampleRate = 48_000u32.try_into().unwrap();
+        let (main, a, b) = (control(2), control(2), control(1));
+        let (mut router, mut mix) = BusRouter::new(
+            channels,
+            sample_rate,
+            0,
+            vec![(main.clone(), 2), (a.clone(), 2), (b.clone(), 1)],
+        );
+
+        let frames = FLUSH_FRAMES * 2;
+        router.mixer(0b011).add(SamplesBuffer::new(
+            channels,
+            sample_rate,
+            vec![0.25; frames * 2],
+        ));
+        router.mixer(0b110).add(SamplesBuffer::new(
+            channels,
+            sample_rate,
+            vec![0.5; frames * 2],
+        ));
+
+        // The player stream gets the main bus, which only has the first track
+        let output: Vec<f32> = mix.by_ref().take(frames * 2).collect();
+        assert!(output[4..].iter().all(|s| *s == 0.25));
+        assert!(drain(&main).is_empty());
+
+        // The second bus gets both tracks, the mono one only the second track
+        let a = drain(&a);
+        let b = drain(&b);
+        assert_eq!(a.len(), frames * 2);
+        assert_eq!(b.len(), frames);
+        assert_eq!(a.last(), Some(&0.75));
+        assert_eq!(b.last(), Some(&0.5));
+    }
+
+    #[test]
+    fn test_unused_routes_are_dropped() {
+        let channels: ChannelCount = 1u16.try_into().unwrap();
+        let sample_rate: SampleRate = 48_000u32.try_into().unwrap();
+        let (mut router, mut mix) = BusRouter::new(channels, sample_rate, 0, vec![(control(1), 1)]);
+
+        router.mixer(0b1);
+        mix.next();
+        assert_eq!(mix.routes.len(), 1);
+
+        router.retain_routes(|_| false);
+        assert!(router.mixers.is_empty());
+        mix.by_ref().take(2).count();
+        assert!(mix.routes.is_empty());
+        assert_eq!(router.changes().retired.len(), 1);
+
+        // Let go of on the next change, away from the audio thread
+        router.retain_routes(|_| false);
+        assert!(router.changes().retired.is_empty());
+    }
+
+    #[test]
+    fn test_bus_queue_is_bounded() {
+        let bus = control(2);
+        bus.push_sounds(&vec![1.0; 48_000]);
+        assert_eq!(drain(&bus).len(), 9_600);
+
+        // A mono microphone on a stereo bus
+        let mut mic = VecDeque::new();
+        bus.push_mic(&[0.5, 0.25], 1);
+        bus.take_mic(&mut mic);
+        assert_eq!(mic, [0.5, 0.5, 0.25, 0.25]);
+
+        // Converted frames are bounded like the rest, keeping the newest ones
+        mic.clear();
+        bus.push_mic(&vec![1.0; 48_000], 1);
+        bus.push_mic(&[0.5], 1);
+        bus.take_mic(&mut mic);
+        assert_eq!(mic.len(), 9_600);
+        assert_eq!(mic.back(), Some(&0.5));
+
+        mic.clear();
+        bus.set_mic(false);
+        bus.push_mic(&[1.0; 16], 2);
+        bus.take_mic(&mut mic);
+        assert!(mic.is_empty());
+    }
+
+    #[test]
+    fn test_configured_buses() {
+        assert_eq!(configured_buses(&[]), vec![BusSettings::default()]);
+        assert_eq!(main_bus(&configured_buses(&[])), 0);
+
+        let obs = BusSettings {
+            name: "obs".to_string(),
+            mic: false,
+            ..Default::default()
+        };
+        let buses = configured_buses(&[obs.clone(), obs.clone(), BusSettings::default()]);
+        assert_eq!(buses.len(), 2);
+        assert_eq!(buses[0].node_name(), "pwsp-virtual-mic-obs");
+        assert_eq!(buses[1].node_name(), VIRTUAL_MIC_NODE);
+        assert_eq!(main_bus(&buses), 1);
+    }
+
+    #[test]
+    fn test_virtual_mic_overrides() {
+        let audio = AudioSettings::default();
+        let bus = BusSettings {
+            name: "discord".to_string(),
+            node_name: Some("discord-mic".to_string()),
+            channels: Some(ChannelLayout::Mono),
+            ..Default::default()
+        };
+        assert_eq!(bus.node_name(), "discord-mic");
+        assert_eq!(bus.format(&audio).channels, 1);
+        assert_eq!(
+            BusSettings::default().format(&audio),
+            audio.virtual_mic_format()
+        );
+    }
+}
@@ -1,17 +1,34 @@
 use crate::{
     types::{
-        audio_player::{FullState, PlayerState},
-        config::{DaemonConfig, HotkeyConfig},
+        audio_player::{FullState, MAX_SPEED, MIN_SPEED, PlayOptions, PlayerState},
+        config::{DaemonConfig, HotkeyConfig, SoundConfig},
+        effects::{Effect, EffectPresets},
+        limiter::LimiterMode,
+        pipewire::DeviceSelector,
+        pitch::MAX_PITCH_SEMITONES,
+        random::{RandomMode, RandomSource},
+        recorder::{RecordFormat, RecordOptions, RecordSource, SilenceSplit},
+        replay::MAX_REPLAY_SECONDS,
         socket::{Request, Response},
+        tts::{MAX_TTS_RATE, MIN_TTS_RATE, TtsEngine, synthesize},
+        voice_changer::VoicePreset,
+        voices::VoiceStealing,
     },
     utils::{
         commands::parse_command,
-        daemon::{get_audio_player, with_daemon_config},
-        pipewire::{get_all_devices, get_device},
+        daemon::{get_audio_player, get_runtime_dir, wait_device_events, with_daemon_config},
+        pipewire::{get_all_devices, get_device, get_pw_graph, is_daemon_node},
     },
 };
 use async_trait::async_trait;
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+/// How long `wait_device_events` waits for an event by default
+const DEFAULT_DEVICE_EVENTS_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_DEVICE_EVENTS_TIMEOUT: Duration = Duration::from_secs(300);
+/// Reply to enabling the voice changer while no preset is picked
+const NO_VOICE_PRESET: &str =
+    "No voice preset selected, pick one before enabling the voice changer";
 
 #[async_trait]
 pub trait Executable {
@@ -66,13 +83,29 @@ pub struct SeekCommand {
     pub id: Option<u32>,
 }
 
+pub struct SetSpeedCommand {
+    pub speed: Option<f32>,
+    pub id: Option<u32>,
+}
+
+pub struct SetPitchCommand {
+    pub pitch: Option<f32>,
+    pub id: Option<u32>,
+}
+
 pub struct GetDurationCommand {
     pub id: Option<u32>,
 }
 
 pub struct PlayCommand {
     pub file_path: Option<PathBuf>,
-    pub concurrent: Option<bool>,
+    pub options: PlayOptions,
+}
+
+pub struct PlayRandomCommand {
+    pub source: Option<RandomSource>,
+    pub mode: Option<RandomMode>,
+    pub options: PlayOptions,
 }
 
 pub struct GetTracksCommand {}
@@ -85,8 +118,15 @@ pub struct SetCurrentInputCommand {
     pub name: Option<String>,
 }
 
+pub struct SetInputChannelsCommand {
+    /// Empty lets PipeWire mix all channels down
+    pub channels: Vec<String>,
+}
+
 pub struct SetLoopCommand {
     pub enabled: Option<bool>,
+    /// Total passes including the current one, unlimited when unset
+    pub count: Option<u32>,
     pub id: Option<u32>,
 }
 
@@ -121,6 +161,11 @@ pub struct SetHotkeyActionAndKeyCommand {
     pub key_chord: Option<String>,
 }
 
+pub struct SetHotkeyMaxVoicesCommand {
+    pub slot: Option<String>,
+    pub max_voices: Option<usize>,
+}
+
 pub struct PlayHotkeyCommand {
     pub slot: Option<String>,
 }
@@ -133,6 +178,154 @@ pub struct ClearHotkeyKeyCommand {
     pub slot: Option<String>,
 }
 
+pub struct GetSoundSettingsCommand {
+    pub file_path: Option<PathBuf>,
+}
+
+pub struct SetSoundSettingsCommand {
+    pub file_path: Option<PathBuf>,
+    /// Fields to change, the others keep their values
+    pub settings: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+pub struct SetVoiceLimitCommand {
+    /// Left alone when unset, 0 removes the limit and an inner None is an invalid value
+    pub max_voices: Option<Option<usize>>,
+    pub policy: Option<VoiceStealing>,
+}
+
+pub struct GetLevelsCommand {}
+
+pub struct GetLimiterCommand {}
+
+pub struct GetAudioInfoCommand {}
+
+pub struct GetPwNodesCommand {}
+
+pub struct GetPwPortsCommand {}
+
+pub struct GetPwLinksCommand {}
+
+pub struct GetAppStreamsCommand {}
+
+pub struct SetRouteTargetsCommand {
+    /// Empty stops routing sounds into apps
+    pub targets: Vec<String>,
+}
+
+pub struct WaitDeviceEventsCommand {
+    /// Last event the client has seen, only newer ones are returned
+    pub since: Option<u64>,
+    pub timeout: Option<Duration>,
+}
+
+pub struct StartRecordingCommand {
+    pub source: Option<RecordSource>,
+    pub bus: Option<String>,
+    pub format: Option<RecordFormat>,
+    /// An inner None records without splitting, None is an invalid value
+    pub split: Option<Option<SilenceSplit>>,
+}
+
+pub struct StopRecordingCommand {}
+
+pub struct SayCommand {
+    pub text: Option<String>,
+    pub options: PlayOptions,
+}
+
+pub struct SetTtsCommand {
+    pub engine: Option<TtsEngine>,
+    /// Empty goes back to the default voice of the engine
+    pub voice: Option<String>,
+    pub rate: Option<f32>,
+    pub pitch: Option<f32>,
+}
+
+pub struct SaveReplayCommand {
+    /// Length of the clip, the whole buffer when unset
+    pub seconds: Option<f32>,
+    /// Hotkey slot the clip is assigned to right away
+    pub slot: Option<String>,
+}
+
+pub struct SetReplayCommand {
+    pub enabled: Option<bool>,
+    pub seconds: Option<u32>,
+    /// `mic` or empty buffers the microphone again
+    pub source: Option<String>,
+}
+
+pub struct GetSampleCacheCommand {}
+
+pub struct SetSampleCacheCommand {
+    pub memory_budget_mb: Option<usize>,
+}
+
+pub struct GetBusesCommand {}
+
+pub struct SetBusVolumeCommand {
+    pub bus: Option<String>,
+    pub volume: Option<f32>,
+}
+
+pub struct SetBusMicCommand {
+    pub bus: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+pub struct SetLimiterCommand {
+    pub enabled: Option<bool>,
+    pub mode: Option<LimiterMode>,
+    pub ceiling_db: Option<f32>,
+    pub release_ms: Option<f32>,
+}
+
+pub struct SetSilenceTrimCommand {
+    pub enabled: Option<bool>,
+    pub threshold_db: Option<f32>,
+}
+
+pub struct GetEffectsCommand {
+    pub id: Option<u32>,
+}
+
+pub struct SetEffectsCommand {
+    pub id: Option<u32>,
+    pub effects: Option<Vec<Effect>>,
+    /// Name of a saved preset, used when `effects` is missing
+    pub preset: Option<String>,
+}
+
+pub struct GetEffectPresetsCommand {}
+
+pub struct SaveEffectPresetCommand {
+    pub name: Option<String>,
+    pub effects: Option<Vec<Effect>>,
+}
+
+pub struct DeleteEffectPresetCommand {
+    pub name: Option<String>,
+}
+
+pub struct GetVoiceChangerCommand {}
+
+pub struct SetVoiceChangerCommand {
+    pub enabled: Option<bool>,
+    pub preset: Option<String>,
+}
+
+pub struct ToggleVoiceChangerCommand {}
+
+pub struct SaveVoicePresetCommand {
+    pub name: Option<String>,
+    pub preset: Option<VoicePreset>,
+}
+
+pub struct DeleteVoicePresetCommand {
+    pub name: Option<String>,
+}
+
 pub struct GetDaemonConfigCommand {}
 
 pub struct SaveDaemonConfigCommand {}
@@ -141,6 +334,13 @@ pub struct UpdateDaemonConfigCommand {
     pub new_config: DaemonConfig,
 }
 
+/// Hotkey-bound and pinned sounds live in the sample cache, reload it after they change.
+async fn refresh_sample_cache() {
+    if let Ok(player) = get_audio_player().await {
+        player.lock().await.preload_sounds();
+    }
+}
+
 #[async_trait]
 impl Executable for PingCommand {
     async fn execute(&self) -> Response {
@@ -354,6 +554,52 @@ impl Executable for SeekCommand {
     }
 }
 
+#[async_trait]
+impl Executable for SetSpeedCommand {
+    async fn execute(&self) -> Response {
+        let Some(speed) = self.speed.filter(|v| v.is_finite()) else {
+            return Response::new(false, "Invalid speed value");
+        };
+        let mut audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+        match audio_player.set_speed(speed, self.id) {
+            Ok(_) => Response::new(
+                true,
+                format!(
+                    "Playback speed was set to {}",
+                    speed.clamp(MIN_SPEED, MAX_SPEED)
+                ),
+            ),
+            Err(err) => Response::new(false, err.to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for SetPitchCommand {
+    async fn execute(&self) -> Response {
+        let Some(pitch) = self.pitch.filter(|v| v.is_finite()) else {
+            return Response::new(false, "Invalid pitch value");
+        };
+        let mut audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+        match audio_player.set_pitch(pitch, self.id) {
+            Ok(_) => Response::new(
+                true,
+                format!(
+                    "Pitch was set to {} semitones",
+                    pitch.clamp(-MAX_PITCH_SEMITONES, MAX_PITCH_SEMITONES)
+                ),
+            ),
+            Err(err) => Response::new(false, err.to_string()),
+        }
+    }
+}
+
 #[async_trait]
 impl Executable for GetDurationCommand {
     async fn execute(&self) -> Response {
@@ -377,7 +623,7 @@ impl Executable for PlayCommand {
                 Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
             };
             match audio_player
-                .play(file_path, self.concurrent.unwrap_or(false))
+                .play_with_options(file_path, self.options.clone())
                 .await
             {
                 Ok(id) => Response::new(true, id.to_string()),
@@ -389,6 +635,43 @@ impl Executable for PlayCommand {
     }
 }
 
+#[async_trait]
+impl Executable for PlayRandomCommand {
+    async fn execute(&self) -> Response {
+        let Some(source) = &self.source else {
+            return Response::new(false, "Missing dir, files or tag");
+        };
+        let Some(mode) = self.mode else {
+            return Response::new(false, "Invalid random mode");
+        };
+
+        let candidates = match source.resolve() {
+            Ok(files) => files,
+            Err(err) => return Response::new(false, format!("Failed to list sounds: {}", err)),
+        };
+
+        let mut audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+
+        let Some(file_path) = audio_player
+            .random_picker
+            .pick(&source.key(), candidates, mode)
+        else {
+            return Response::new(false, "No sounds to pick from");
+        };
+
+        match audio_player
+            .play_with_options(&file_path, self.options.clone())
+            .await
+        {
+            Ok(id) => Response::new(true, id.to_string()),
+            Err(err) => Response::new(false, err.to_string()),
+        }
+    }
+}
+
 #[async_trait]
 impl Executable for GetTracksCommand {
     async fn execute(&self) -> Response {
@@ -433,9 +716,10 @@ impl Executable for GetAllInputsCommand {
             Ok(devices) => devices,
             Err(err) => return Response::new(false, format!("Failed to get devices: {}", err)),
         };
+        let buses = with_daemon_config(|c| c.buses.clone());
         let mut input_devices_strings = vec![];
         for device in input_devices {
-            if device.name == "pwsp-virtual-mic" {
+            if is_daemon_node(&device.name, &buses) {
                 continue;
             }
 
@@ -466,6 +750,27 @@ impl Executable for SetCurrentInputCommand {
     }
 }
 
+#[async_trait]
+impl Executable for SetInputChannelsCommand {
+    async fn execute(&self) -> Response {
+        let mut audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+
+        match audio_player.set_input_channels(self.channels.clone()).await {
+            Ok(_) if self.channels.is_empty() => {
+                Response::new(true, "Input device channels are mixed down")
+            }
+            Ok(_) => Response::new(
+                true,
+                format!("Input channels were set to {}", self.channels.join(", ")),
+            ),
+            Err(err) => Response::new(false, err.to_string()),
+        }
+    }
+}
+
 #[async_trait]
 impl Executable for SetLoopCommand {
     async fn execute(&self) -> Response {
@@ -476,7 +781,7 @@ impl Executable for SetLoopCommand {
 
         match self.enabled {
             Some(enabled) => {
-                audio_player.set_loop(enabled, self.id);
+                audio_player.set_loop(enabled, self.count, self.id);
                 Response::new(true, format!("Loop was set to {}", enabled))
             }
             None => Response::new(false, "Invalid enabled value"),
@@ -493,15 +798,15 @@ impl Executable for ToggleLoopCommand {
         };
         if let Some(id) = self.id {
             if let Some(track) = audio_player.tracks.get_mut(&id) {
-                track.looped = !track.looped;
-                Response::new(true, format!("Loop was set to {}", track.looped))
+                track.set_looped(!track.looped(), None);
+                Response::new(true, format!("Loop was set to {}", track.looped()))
             } else {
                 Response::new(false, "Track not found")
             }
         } else {
             // Toggle all?
-            for track in audio_player.tracks.values_mut() {
-                track.looped = !track.looped;
+            for track in audio_player.tracks.values() {
+                track.set_looped(!track.looped(), None);
             }
             Response::new(true, "Loop toggled for all tracks")
         }
@@ -529,25 +834,20 @@ impl Executable for GetFullStateCommand {
             Ok(player) => player.lock().await,
             Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
         };
-        if let Some(current_input_name) = &audio_player.input_device_name {
-            for device in input_devices {
-                if device.name == "pwsp-virtual-mic" {
-                    continue;
-                }
-                if device.name == *current_input_name {
-                    current_input_nick = format!("{} - {}", device.name, device.nick);
-                }
-
-                all_inputs.insert(device.name, device.nick);
+        let buses = with_daemon_config(|c| c.buses.clone());
+        let selector = audio_player
+            .input_device_name
+            .as_deref()
+            .map(DeviceSelector::parse);
+        for device in input_devices {
+            if is_daemon_node(&device.name, &buses) {
+                continue;
             }
-        } else {
-            for device in input_devices {
-                if device.name == "pwsp-virtual-mic" {
-                    continue;
-                }
-
-                all_inputs.insert(device.name, device.nick);
+            if selector.as_ref().is_some_and(|s| s.matches(&device)) {
+                current_input_nick = format!("{} - {}", device.name, device.nick);
             }
+
+            all_inputs.insert(device.name, device.nick);
         }
 
         let full_state = FullState {
@@ -557,6 +857,8 @@ impl Executable for GetFullStateCommand {
             volume_multiplier: audio_player.volume_multiplier,
             current_input: current_input_nick,
             all_inputs,
+            recording: audio_player.is_recording(),
+            clips_saved: audio_player.clips_saved(),
         };
 
         match serde_json::to_string(&full_state) {
@@ -600,7 +902,10 @@ impl Executable for SetHotkeyCommand {
         );
 
         match config.save() {
-            Ok(_) => Response::new(true, format!("Hotkey slot '{}' set", slot)),
+            Ok(_) => {
+                refresh_sample_cache().await;
+                Response::new(true, format!("Hotkey slot '{}' set", slot))
+            }
             Err(err) => Response::new(false, format!("Failed to save hotkeys: {}", err)),
         }
     }
@@ -624,7 +929,10 @@ impl Executable for SetHotkeyActionCommand {
         config.set_slot(slot.clone(), action.clone());
 
         match config.save() {
-            Ok(_) => Response::new(true, format!("Hotkey slot '{}' set", slot)),
+            Ok(_) => {
+                refresh_sample_cache().await;
+                Response::new(true, format!("Hotkey slot '{}' set", slot))
+            }
             Err(err) => Response::new(false, format!("Failed to save hotkeys: {}", err)),
         }
     }
@@ -659,6 +967,35 @@ impl Executable for SetHotkeyKeyCommand {
     }
 }
 
+#[async_trait]
+impl Executable for SetHotkeyMaxVoicesCommand {
+    async fn execute(&self) -> Response {
+        let Some(slot) = &self.slot else {
+            return Response::new(false, "Missing slot name");
+        };
+
+        let mut config = match HotkeyConfig::load() {
+            Ok(c) => c,
+            Err(err) => return Response::new(false, format!("Failed to load hotkeys: {}", err)),
+        };
+
+        let Some(hotkey_slot) = config.find_slot_mut(slot) else {
+            return Response::new(false, format!("Slot '{}' not found", slot));
+        };
+        hotkey_slot.max_voices = self.max_voices;
+
+        let message = match self.max_voices {
+            Some(max_voices) => format!("Max voices for slot '{}' set to {}", slot, max_voices),
+            None => format!("Max voices for slot '{}' cleared", slot),
+        };
+
+        match config.save() {
+            Ok(_) => Response::new(true, message),
+            Err(err) => Response::new(false, format!("Failed to save hotkeys: {}", err)),
+        }
+    }
+}
+
 #[async_trait]
 impl Executable for SetHotkeyActionAndKeyCommand {
     async fn execute(&self) -> Response {
@@ -687,13 +1024,16 @@ impl Executable for SetHotkeyActionAndKeyCommand {
         }
 
         match config.save() {
-            Ok(_) => Response::new(
-                true,
-                format!(
-                    "Hotkey slot '{}' set with action and key chord '{}'",
-                    slot, key_chord
-                ),
-            ),
+            Ok(_) => {
+                refresh_sample_cache().await;
+                Response::new(
+                    true,
+                    format!(
+                        "Hotkey slot '{}' set with action and key chord '{}'",
+                        slot, key_chord
+                    ),
+                )
+            }
             Err(err) => Response::new(false, format!("Failed to save hotkeys: {}", err)),
         }
     }
@@ -715,7 +1055,7 @@ impl Executable for PlayHotkeyCommand {
             return Response::new(false, format!("Slot '{}' not found", slot));
         };
 
-        let action = hotkey_slot.action.clone();
+        let action = hotkey_slot.playback_action();
 
         if let Some(cmd) = parse_command(&action) {
             cmd.execute().await
@@ -739,7 +1079,10 @@ impl Executable for ClearHotkeyCommand {
 
         if config.remove_slot(slot) {
             match config.save() {
-                Ok(_) => Response::new(true, format!("Hotkey slot '{}' cleared", slot)),
+                Ok(_) => {
+                    refresh_sample_cache().await;
+                    Response::new(true, format!("Hotkey slot '{}' cleared", slot))
+                }
                 Err(err) => Response::new(false, format!("Failed to save hotkeys: {}", err)),
             }
         } else {
@@ -771,6 +1114,913 @@ impl Executable for ClearHotkeyKeyCommand {
     }
 }
 
+#[async_trait]
+impl Executable for GetSoundSettingsCommand {
+    async fn execute(&self) -> Response {
+        let Some(file_path) = &self.file_path else {
+            return Response::new(false, "Missing file path");
+        };
+
+        let config = match SoundConfig::load() {
+            Ok(c) => c,
+            Err(err) => {
+                return Response::new(false, format!("Failed to load sound settings: {}", err));
+            }
+        };
+
+        match serde_json::to_string(&config.get(file_path)) {
+            Ok(json) => Response::new(true, json),
+            Err(err) => Response::new(
+                false,
+                format!("Failed to serialize sound settings: {}", err),
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for SetSoundSettingsCommand {
+    async fn execute(&self) -> Response {
+        let Some(file_path) = &self.file_path else {
+            return Response::new(false, "Missing file path");
+        };
+        let Some(settings) = &self.settings else {
+            return Response::new(false, "Missing or invalid settings");
+        };
+
+        let mut config = match SoundConfig::load() {
+            Ok(c) => c,
+            Err(err) => {
+                return Response::new(false, format!("Failed to load sound settings: {}", err));
+            }
+        };
+
+        let settings = match config.get(file_path).merged(settings) {
+            Ok(settings) => settings,
+            Err(err) => return Response::new(false, format!("Invalid sound settings: {}", err)),
+        };
+        config.set(file_path.clone(), settings);
+
+        match config.save() {
+            Ok(_) => {
+                refresh_sample_cache().await;
+                Response::new(
+                    true,
+                    format!("Sound settings for '{}' saved", file_path.display()),
+                )
+            }
+            Err(err) => Response::new(false, format!("Failed to save sound settings: {}", err)),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for SetVoiceLimitCommand {
+    async fn execute(&self) -> Response {
+        if self.max_voices == Some(None) {
+            return Response::new(false, "Invalid max voices value");
+        }
+
+        let (max_voices, policy) = with_daemon_config(|c| {
+            if let Some(Some(max_voices)) = self.max_voices {
+                c.max_voices = (max_voices > 0).then_some(max_voices);
+            }
+            if let Some(policy) = self.policy {
+                c.voice_stealing = policy;
+            }
+            (c.max_voices, c.voice_stealing)
+        });
+
+        match max_voices {
+            Some(max_voices) => Response::new(
+                true,
+                format!("Voice limit set to {} ({})", max_voices, policy.as_str()),
+            ),
+            None => Response::new(true, "No voice limit"),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for GetLevelsCommand {
+    async fn execute(&self) -> Response {
+        let audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+
+        match serde_json::to_string(&audio_player.get_levels()) {
+            Ok(json) => Response::new(true, json),
+            Err(err) => Response::new(false, format!("Failed to serialize levels: {}", err)),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for GetAudioInfoCommand {
+    async fn execute(&self) -> Response {
+        let audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+
+        match serde_json::to_string(&audio_player.get_audio_info()) {
+            Ok(json) => Response::new(true, json),
+            Err(err) => Response::new(false, format!("Failed to serialize audio info: {}", err)),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for GetPwNodesCommand {
+    async fn execute(&self) -> Response {
+        let graph = match get_pw_graph().await {
+            Ok(graph) => graph,
+            Err(err) => return Response::new(false, format!("Failed to get graph: {}", err)),
+        };
+        match serde_json::to_string(&graph.nodes) {
+            Ok(json) => Response::new(true, json),
+            Err(err) => Response::new(false, format!("Failed to serialize nodes: {}", err)),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for GetPwPortsCommand {
+    async fn execute(&self) -> Response {
+        let graph = match get_pw_graph().await {
+            Ok(graph) => graph,
+            Err(err) => return Response::new(false, format!("Failed to get graph: {}", err)),
+        };
+        match serde_json::to_string(&graph.ports) {
+            Ok(json) => Response::new(true, json),
+            Err(err) => Response::new(false, format!("Failed to serialize ports: {}", err)),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for GetPwLinksCommand {
+    async fn execute(&self) -> Response {
+        let graph = match get_pw_graph().await {
+            Ok(graph) => graph,
+            Err(err) => return Response::new(false, format!("Failed to get graph: {}", err)),
+        };
+        match serde_json::to_string(&graph.links) {
+            Ok(json) => Response::new(true, json),
+            Err(err) => Response::new(false, format!("Failed to serialize links: {}", err)),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for GetAppStreamsCommand {
+    async fn execute(&self) -> Response {
+        let audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+
+        let streams = match audio_player.get_app_streams().await {
+            Ok(streams) => streams,
+            Err(err) => return Response::new(false, format!("Failed to get app streams: {}", err)),
+        };
+        match serde_json::to_string(&streams) {
+            Ok(json) => Response::new(true, json),
+            Err(err) => Response::new(false, format!("Failed to serialize app streams: {}", err)),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for SetRouteTargetsCommand {
+    async fn execute(&self) -> Response {
+        let mut audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+
+        audio_player.set_route_targets(self.targets.clone()).await;
+        if self.targets.is_empty() {
+            Response::new(true, "Sounds are no longer routed to apps")
+        } else {
+            Response::new(
+                true,
+                format!("Sounds are routed to {}", self.targets.join(", ")),
+            )
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for WaitDeviceEventsCommand {
+    async fn execute(&self) -> Response {
+        let timeout = self
+            .timeout
+            .unwrap_or(DEFAULT_DEVICE_EVENTS_TIMEOUT)
+            .min(MAX_DEVICE_EVENTS_TIMEOUT);
+        let events = wait_device_events(self.since, timeout).await;
+
+        match serde_json::to_string(&events) {
+            Ok(json) => Response::new(true, json),
+            Err(err) => Response::new(false, format!("Failed to serialize device events: {}", err)),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for StartRecordingCommand {
+    async fn execute(&self) -> Response {
+        let source = match self.source {
+            Some(source) => source,
+            None => return Response::new(false, "Invalid record source"),
+        };
+        let format = match self.format {
+            Some(format) => format,
+            None => return Response::new(false, "Invalid record format"),
+        };
+        let Some(split) = self.split else {
+            return Response::new(false, "Invalid split");
+        };
+
+        let mut audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+        let options = RecordOptions {
+            source,
+            bus: self.bus.clone(),
+            format,
+            split,
+        };
+        match audio_player.start_recording(options).await {
+            Ok(()) => Response::new(true, format!("Recording {}", source.as_str())),
+            Err(err) => Response::new(false, format!("Failed to start recording: {}", err)),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for StopRecordingCommand {
+    async fn execute(&self) -> Response {
+        let recording = {
+            let mut audio_player = match get_audio_player().await {
+                Ok(player) => player.lock().await,
+                Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+            };
+            match audio_player.stop_recording() {
+                Ok(recording) => recording,
+                Err(err) => return Response::new(false, err.to_string()),
+            }
+        };
+
+        // Encoding the last clip can take a while, the player is not held meanwhile
+        let summary = match tokio::task::spawn_blocking(move || recording.finish()).await {
+            Ok(Ok(summary)) => summary,
+            Ok(Err(err)) => return Response::new(false, format!("Recording failed: {}", err)),
+            Err(err) => return Response::new(false, format!("Recording failed: {}", err)),
+        };
+        match serde_json::to_string(&summary) {
+            Ok(json) => Response::new(true, json),
+            Err(err) => Response::new(false, format!("Failed to serialize recording: {}", err)),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for SayCommand {
+    async fn execute(&self) -> Response {
+        let Some(text) = &self.text else {
+            return Response::new(false, "Missing text");
+        };
+
+        // The player is not held while the engine runs
+        let settings = with_daemon_config(|c| c.tts.clone());
+        let path = match synthesize(&settings, text, &get_runtime_dir().join("pwsp-tts")).await {
+            Ok(path) => path,
+            Err(err) => {
+                return Response::new(false, format!("Failed to synthesize speech: {}", err));
+            }
+        };
+
+        let mut audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+        let options = PlayOptions {
+            pitch: settings.pitch,
+            ..self.options.clone()
+        };
+        match audio_player.play_with_options(&path, options).await {
+            Ok(id) => Response::new(true, id.to_string()),
+            Err(err) => Response::new(false, err.to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for SetTtsCommand {
+    async fn execute(&self) -> Response {
+        if self.rate.is_some_and(|v| !v.is_finite()) || self.pitch.is_some_and(|v| !v.is_finite()) {
+            return Response::new(false, "Speech rate and pitch must be finite numbers");
+        }
+
+        let settings = with_daemon_config(|c| {
+            if let Some(engine) = self.engine {
+                c.tts.engine = engine;
+            }
+            if let Some(voice) = &self.voice {
+                c.tts.voice = (!voice.is_empty()).then(|| voice.clone());
+            }
+            if let Some(rate) = self.rate {
+                c.tts.rate = rate.clamp(MIN_TTS_RATE, MAX_TTS_RATE);
+            }
+            if let Some(pitch) = self.pitch {
+                c.tts.pitch = pitch.clamp(-MAX_PITCH_SEMITONES, MAX_PITCH_SEMITONES);
+            }
+            c.tts.clone()
+        });
+
+        Response::new(
+            true,
+            format!(
+                "Speaking with {} ({}), rate {:.2}, pitch {:+.1} semitones",
+                settings.engine.as_str(),
+                settings.voice.as_deref().unwrap_or("default voice"),
+                settings.rate,
+                settings.pitch
+            ),
+        )
+    }
+}
+
+#[async_trait]
+impl Executable for SaveReplayCommand {
+    async fn execute(&self) -> Response {
+        let save = {
+            let audio_player = match get_audio_player().await {
+                Ok(player) => player.lock().await,
+                Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+            };
+            match audio_player.save_replay(self.seconds) {
+                Ok(save) => save,
+                Err(err) => return Response::new(false, format!("Failed to save replay: {}", err)),
+            }
+        };
+
+        // Written without holding the player, playback goes on meanwhile
+        let path = match tokio::task::spawn_blocking(save).await {
+            Ok(Ok(path)) => path,
+            Ok(Err(err)) => return Response::new(false, format!("Failed to save replay: {}", err)),
+            Err(err) => return Response::new(false, format!("Failed to save replay: {}", err)),
+        };
+
+        let Some(slot) = &self.slot else {
+            return Response::new(true, path.to_string_lossy());
+        };
+        let mut config = match HotkeyConfig::load() {
+            Ok(c) => c,
+            Err(err) => return Response::new(false, format!("Failed to load hotkeys: {}", err)),
+        };
+        config.set_slot(slot.clone(), Request::play(&path.to_string_lossy(), false));
+        match config.save() {
+            Ok(_) => {
+                refresh_sample_cache().await;
+                Response::new(
+                    true,
+                    format!("Saved {} to hotkey slot '{}'", path.display(), slot),
+                )
+            }
+            Err(err) => Response::new(false, format!("Failed to save hotkeys: {}", err)),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for SetReplayCommand {
+    async fn execute(&self) -> Response {
+        if let Some(seconds) = self.seconds
+            && !(1..=MAX_REPLAY_SECONDS).contains(&seconds)
+        {
+            return Response::new(
+                false,
+                format!("Replay length must be 1 to {} seconds", MAX_REPLAY_SECONDS),
+            );
+        }
+
+        let mut settings = with_daemon_config(|c| c.replay.clone());
+        if let Some(enabled) = self.enabled {
+            settings.enabled = enabled;
+        }
+        if let Some(seconds) = self.seconds {
+            settings.seconds = seconds;
+        }
+        if let Some(source) = &self.source {
+            settings.source = match source.as_str() {
+                "" | "mic" => None,
+                source => Some(source.to_string()),
+            };
+        }
+
+        let mut audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+        audio_player.set_replay(settings.clone()).await;
+
+        if !settings.enabled {
+            return Response::new(true, "Replay buffer disabled");
+        }
+        Response::new(
+            true,
+            format!(
+                "Buffering the last {} seconds of {}",
+                settings.seconds(),
+                settings.source.as_deref().unwrap_or("the microphone")
+            ),
+        )
+    }
+}
+
+#[async_trait]
+impl Executable for GetSampleCacheCommand {
+    async fn execute(&self) -> Response {
+        let audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+
+        match serde_json::to_string(&audio_player.get_sample_cache()) {
+            Ok(json) => Response::new(true, json),
+            Err(err) => Response::new(
+                false,
+                format!("Failed to serialize sample cache info: {}", err),
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for SetSampleCacheCommand {
+    async fn execute(&self) -> Response {
+        let Some(memory_budget_mb) = self.memory_budget_mb else {
+            return Response::new(false, "Missing or invalid memory budget");
+        };
+
+        with_daemon_config(|c| c.sample_cache.memory_budget_mb = memory_budget_mb);
+
+        let audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+        audio_player.preload_sounds();
+
+        Response::new(
+            true,
+            format!("Sample cache budget set to {} MB", memory_budget_mb),
+        )
+    }
+}
+
+#[async_trait]
+impl Executable for GetBusesCommand {
+    async fn execute(&self) -> Response {
+        let audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+
+        match serde_json::to_string(&audio_player.get_buses()) {
+            Ok(json) => Response::new(true, json),
+            Err(err) => Response::new(false, format!("Failed to serialize buses: {}", err)),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for SetBusVolumeCommand {
+    async fn execute(&self) -> Response {
+        let Some(bus) = &self.bus else {
+            return Response::new(false, "Missing bus");
+        };
+        let Some(volume) = self.volume else {
+            return Response::new(false, "Missing or invalid volume");
+        };
+
+        let mut audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+
+        match audio_player.set_bus_volume(bus, volume) {
+            Ok(_) => {
+                let buses = audio_player.bus_settings();
+                with_daemon_config(|c| c.buses = buses);
+                Response::new(true, format!("Bus {} volume set to {}", bus, volume))
+            }
+            Err(err) => Response::new(false, err.to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for SetBusMicCommand {
+    async fn execute(&self) -> Response {
+        let Some(bus) = &self.bus else {
+            return Response::new(false, "Missing bus");
+        };
+        let Some(enabled) = self.enabled else {
+            return Response::new(false, "Missing or invalid enabled value");
+        };
+
+        let mut audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+
+        match audio_player.set_bus_mic(bus, enabled).await {
+            Ok(_) => {
+                let buses = audio_player.bus_settings();
+                with_daemon_config(|c| c.buses = buses);
+                Response::new(
+                    true,
+                    format!(
+                        "Microphone {} on bus {}",
+                        if enabled { "enabled" } else { "disabled" },
+                        bus
+                    ),
+                )
+            }
+            Err(err) => Response::new(false, err.to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for GetLimiterCommand {
+    async fn execute(&self) -> Response {
+        let audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+
+        match serde_json::to_string(&audio_player.limiter.status()) {
+            Ok(json) => Response::new(true, json),
+            Err(err) => Response::new(false, format!("Failed to serialize limiter: {}", err)),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for SetLimiterCommand {
+    async fn execute(&self) -> Response {
+        if self.ceiling_db.is_some_and(|v| !v.is_finite())
+            || self.release_ms.is_some_and(|v| !v.is_finite())
+        {
+            return Response::new(false, "Limiter ceiling and release must be finite numbers");
+        }
+
+        let audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+
+        let mut settings = audio_player.limiter.settings();
+        if let Some(enabled) = self.enabled {
+            settings.enabled = enabled;
+        }
+        if let Some(mode) = self.mode {
+            settings.mode = mode;
+        }
+        if let Some(ceiling_db) = self.ceiling_db {
+            settings.ceiling_db = ceiling_db;
+        }
+        if let Some(release_ms) = self.release_ms {
+            settings.release_ms = release_ms;
+        }
+
+        audio_player.limiter.apply(settings);
+        let settings = audio_player.limiter.settings();
+        with_daemon_config(|c| c.limiter = settings);
+
+        Response::new(
+            true,
+            format!(
+                "Limiter {} ({}, ceiling {:.1} dBFS)",
+                if settings.enabled {
+                    "enabled"
+                } else {
+                    "disabled"
+                },
+                settings.mode.as_str(),
+                settings.ceiling_db
+            ),
+        )
+    }
+}
+
+#[async_trait]
+impl Executable for SetSilenceTrimCommand {
+    async fn execute(&self) -> Response {
+        if self.threshold_db.is_some_and(|v| !v.is_finite()) {
+            return Response::new(false, "Silence threshold must be a finite number");
+        }
+
+        let settings = with_daemon_config(|c| {
+            if let Some(enabled) = self.enabled {
+                c.silence_trim.enabled = enabled;
+            }
+            if let Some(threshold_db) = self.threshold_db {
+                c.silence_trim.threshold_db = threshold_db;
+            }
+            c.silence_trim
+        });
+
+        Response::new(
+            true,
+            format!(
+                "Silence trimming {} (threshold {:.1} dBFS)",
+                if settings.enabled {
+                    "enabled"
+                } else {
+                    "disabled"
+                },
+                settings.threshold_db
+            ),
+        )
+    }
+}
+
+#[async_trait]
+impl Executable for GetEffectsCommand {
+    async fn execute(&self) -> Response {
+        let audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+
+        match audio_player.get_effects(self.id) {
+            Ok(effects) => match serde_json::to_string(&effects) {
+                Ok(json) => Response::new(true, json),
+                Err(err) => Response::new(false, format!("Failed to serialize effects: {}", err)),
+            },
+            Err(err) => Response::new(false, err.to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for SetEffectsCommand {
+    async fn execute(&self) -> Response {
+        let effects = match (&self.effects, &self.preset) {
+            (Some(effects), _) => effects.clone(),
+            (None, Some(preset)) => match EffectPresets::load() {
+                Ok(presets) => match presets.presets.get(preset) {
+                    Some(effects) => effects.clone(),
+                    None => return Response::new(false, format!("Unknown preset '{}'", preset)),
+                },
+                Err(err) => {
+                    return Response::new(false, format!("Failed to load presets: {}", err));
+                }
+            },
+            (None, None) => return Response::new(false, "Missing or invalid effects"),
+        };
+
+        let mut audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+
+        if let Err(err) = audio_player.set_effects(effects.clone(), self.id) {
+            return Response::new(false, err.to_string());
+        }
+
+        match self.id {
+            Some(id) => Response::new(
+                true,
+                format!("Set {} effects on track {}", effects.len(), id),
+            ),
+            None => {
+                let count = effects.len();
+                with_daemon_config(|c| c.master_effects = effects);
+                Response::new(true, format!("Set {} effects on the master bus", count))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for GetEffectPresetsCommand {
+    async fn execute(&self) -> Response {
+        match EffectPresets::load() {
+            Ok(presets) => match serde_json::to_string(&presets.presets) {
+                Ok(json) => Response::new(true, json),
+                Err(err) => Response::new(false, format!("Failed to serialize presets: {}", err)),
+            },
+            Err(err) => Response::new(false, format!("Failed to load presets: {}", err)),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for SaveEffectPresetCommand {
+    async fn execute(&self) -> Response {
+        let Some(name) = &self.name else {
+            return Response::new(false, "Missing preset name");
+        };
+        let Some(effects) = &self.effects else {
+            return Response::new(false, "Missing or invalid effects");
+        };
+
+        let mut presets = match EffectPresets::load() {
+            Ok(p) => p,
+            Err(err) => return Response::new(false, format!("Failed to load presets: {}", err)),
+        };
+        presets.presets.insert(name.clone(), effects.clone());
+
+        match presets.save() {
+            Ok(_) => Response::new(true, format!("Preset '{}' saved", name)),
+            Err(err) => Response::new(false, format!("Failed to save presets: {}", err)),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for DeleteEffectPresetCommand {
+    async fn execute(&self) -> Response {
+        let Some(name) = &self.name else {
+            return Response::new(false, "Missing preset name");
+        };
+
+        let mut presets = match EffectPresets::load() {
+            Ok(p) => p,
+            Err(err) => return Response::new(false, format!("Failed to load presets: {}", err)),
+        };
+        if presets.presets.remove(name).is_none() {
+            return Response::new(false, format!("Unknown preset '{}'", name));
+        }
+
+        match presets.save() {
+            Ok(_) => Response::new(true, format!("Preset '{}' deleted", name)),
+            Err(err) => Response::new(false, format!("Failed to save presets: {}", err)),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for GetVoiceChangerCommand {
+    async fn execute(&self) -> Response {
+        let serialized = with_daemon_config(|c| serde_json::to_string(&c.voice_changer));
+
+        match serialized {
+            Ok(json) => Response::new(true, json),
+            Err(err) => Response::new(
+                false,
+                format!("Failed to serialize voice changer settings: {}", err),
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for SetVoiceChangerCommand {
+    async fn execute(&self) -> Response {
+        let mut audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+
+        let result = with_daemon_config(|c| {
+            let mut settings = c.voice_changer.clone();
+            if let Some(preset) = &self.preset {
+                if !settings.presets.contains_key(preset) {
+                    return Err(format!("Unknown voice preset '{}'", preset));
+                }
+                settings.preset = Some(preset.clone());
+            }
+            if let Some(enabled) = self.enabled {
+                settings.enabled = enabled;
+            }
+            if settings.enabled && settings.active().is_none() {
+                return Err(NO_VOICE_PRESET.to_string());
+            }
+            c.voice_changer = settings.clone();
+            Ok(settings)
+        });
+        let settings = match result {
+            Ok(settings) => settings,
+            Err(err) => return Response::new(false, err),
+        };
+
+        audio_player.set_voice_preset(settings.active()).await;
+        Response::new(
+            true,
+            format!(
+                "Voice changer {} (preset {})",
+                if settings.enabled {
+                    "enabled"
+                } else {
+                    "disabled"
+                },
+                settings.preset.as_deref().unwrap_or("none")
+            ),
+        )
+    }
+}
+
+#[async_trait]
+impl Executable for ToggleVoiceChangerCommand {
+    async fn execute(&self) -> Response {
+        let mut audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+
+        let result = with_daemon_config(|c| {
+            let mut settings = c.voice_changer.clone();
+            settings.enabled = !settings.enabled;
+            if settings.enabled && settings.active().is_none() {
+                return Err(NO_VOICE_PRESET);
+            }
+            c.voice_changer = settings.clone();
+            Ok(settings)
+        });
+        let settings = match result {
+            Ok(settings) => settings,
+            Err(err) => return Response::new(false, err),
+        };
+
+        audio_player.set_voice_preset(settings.active()).await;
+        Response::new(
+            true,
+            if settings.enabled {
+                "Voice changer enabled"
+            } else {
+                "Voice changer disabled"
+            },
+        )
+    }
+}
+
+#[async_trait]
+impl Executable for SaveVoicePresetCommand {
+    async fn execute(&self) -> Response {
+        let Some(name) = &self.name else {
+            return Response::new(false, "Missing preset name");
+        };
+        let Some(preset) = &self.preset else {
+            return Response::new(false, "Missing or invalid voice preset");
+        };
+
+        let mut audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+
+        let active = with_daemon_config(|c| {
+            c.voice_changer.presets.insert(name.clone(), preset.clone());
+            c.voice_changer.active()
+        });
+
+        // Editing the active preset takes effect right away
+        audio_player.set_voice_preset(active).await;
+        Response::new(true, format!("Voice preset '{}' saved", name))
+    }
+}
+
+#[async_trait]
+impl Executable for DeleteVoicePresetCommand {
+    async fn execute(&self) -> Response {
+        let Some(name) = &self.name else {
+            return Response::new(false, "Missing preset name");
+        };
+
+        let mut audio_player = match get_audio_player().await {
+            Ok(player) => player.lock().await,
+            Err(err) => return Response::new(false, format!("Audio player error: {}", err)),
+        };
+
+        let active = with_daemon_config(|c| {
+            c.voice_changer.presets.remove(name).map(|_| {
+                // Deleting the active preset turns the voice changer off
+                if c.voice_changer.preset.as_ref() == Some(name) {
+                    c.voice_changer.preset = None;
+                    c.voice_changer.enabled = false;
+                }
+                c.voice_changer.active()
+            })
+        });
+
+        match active {
+            Some(active) => {
+                audio_player.set_voice_preset(active).await;
+                Response::new(true, format!("Voice preset '{}' deleted", name))
+            }
+            None => Response::new(false, format!("Unknown voice preset '{}'", name)),
+        }
+    }
+}
+
 #[async_trait]
 impl Executable for GetDaemonConfigCommand {
     async fn execute(&self) -> Response {
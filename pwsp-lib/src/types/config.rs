@@ -1,9 +1,15 @@
 use crate::{
-    types::socket::Request,
+    types::{
+        buses::BusSettings, effects::Effect, limiter::LimiterSettings, pipewire::AudioSettings,
+        replay::ReplaySettings, sample_cache::SampleCacheSettings, socket::Request,
+        trim::SilenceTrimSettings, tts::TtsSettings, voice_changer::VoiceChangerSettings,
+        voices::VoiceStealing,
+    },
     utils::{config::get_config_path, gui::ensure_pwsp_audio_dir},
 };
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::{
     cmp::Ordering,
     collections::HashMap,
@@ -16,8 +22,37 @@ use std::{
 #[serde(default)]
 pub struct DaemonConfig {
     pub default_input_name: Option<String>,
+    /// Channels of the input device used as the microphone, e.g. `AUX2`.
+    /// PipeWire mixes all of them down when empty
+    pub input_channels: Vec<String>,
     pub default_volume: Option<f32>,
     pub default_volume_multiplier: Option<f32>,
+
+    /// Upper bound on simultaneously playing tracks, unlimited when unset
+    pub max_voices: Option<usize>,
+    pub voice_stealing: VoiceStealing,
+
+    pub limiter: LimiterSettings,
+    pub silence_trim: SilenceTrimSettings,
+    /// Effect chain on the master bus, before the limiter
+    pub master_effects: Vec<Effect>,
+    /// Voice changer on the microphone passthrough
+    pub voice_changer: VoiceChangerSettings,
+    /// Sample rate, latency and channels of the player stream and virtual mic,
+    /// picked up the next time they are created
+    pub audio: AudioSettings,
+    /// Decoded hotkey-bound and pinned sounds kept in memory
+    pub sample_cache: SampleCacheSettings,
+    /// Recent audio kept in memory for `save_replay`
+    pub replay: ReplaySettings,
+    /// Engine and voice `say` speaks with
+    pub tts: TtsSettings,
+    /// App capture streams the sounds are linked into as well, by application name,
+    /// binary or `node.name`
+    pub route_targets: Vec<String>,
+    /// Virtual mics the sounds can be routed to, created when the daemon starts.
+    /// Empty means a single default bus
+    pub buses: Vec<BusSettings>,
 }
 
 impl DaemonConfig {
@@ -178,6 +213,25 @@ pub struct HotkeySlot {
     pub slot: String,
     pub action: Request,
     pub key_chord: Option<String>,
+    /// How many tracks started from this slot may play at once
+    #[serde(default)]
+    pub max_voices: Option<usize>,
+}
+
+impl HotkeySlot {
+    /// The slot action with the slot identity attached, so the player can enforce per-slot limits.
+    pub fn playback_action(&self) -> Request {
+        let mut action = self.action.clone();
+        if matches!(action.name.as_str(), "play" | "play_random") {
+            action.args.insert("slot".to_string(), self.slot.clone());
+            if let Some(max_voices) = self.max_voices {
+                action
+                    .args
+                    .insert("max_voices".to_string(), max_voices.to_string());
+            }
+        }
+        action
+    }
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
@@ -231,6 +285,7 @@ impl HotkeyConfig {
                 slot,
                 action,
                 key_chord: None,
+                max_voices: None,
             });
         }
     }
@@ -283,6 +338,112 @@ impl HotkeyConfig {
     }
 }
 
+/// Per-file settings, kept separately from hotkeys so a sound can be tweaked without binding it.
+#[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SoundSettings {
+    pub tags: Vec<String>,
+    /// How many copies of this sound may play at once
+    pub max_voices: Option<usize>,
+    /// Starting a sound stops every other playing sound of the same group
+    pub choke_group: Option<String>,
+    /// Playback starts this many seconds into the file
+    pub start: f32,
+    /// Playback stops at this many seconds into the file, the end of the file if unset
+    pub end: Option<f32>,
+    /// Fade in length in seconds
+    pub fade_in: f32,
+    /// Fade out length in seconds
+    pub fade_out: f32,
+    /// While looping, playback jumps back here, in seconds into the file
+    pub loop_start: f32,
+    /// While looping, playback jumps back once it gets here, the end if unset
+    pub loop_end: Option<f32>,
+    /// Overrides the daemon-wide silence trimming for this sound
+    pub trim_silence: Option<bool>,
+    /// Effect chain applied every time this sound is played
+    pub effects: Vec<Effect>,
+    /// Keep the decoded sound in memory so it starts instantly, like hotkey-bound sounds
+    pub pinned: bool,
+}
+
+impl SoundSettings {
+    /// Applies the fields present in `changes` on top of these settings, leaving the rest as
+    /// they are.
+    pub fn merged(&self, changes: &Map<String, Value>) -> Result<SoundSettings> {
+        let Value::Object(mut merged) = serde_json::to_value(self)? else {
+            return Err(anyhow!("Sound settings are not an object"));
+        };
+        for (key, value) in changes {
+            if !merged.contains_key(key) {
+                return Err(anyhow!("Unknown sound setting '{}'", key));
+            }
+            merged.insert(key.clone(), value.clone());
+        }
+        Ok(serde_json::from_value(Value::Object(merged))?)
+    }
+}
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct SoundConfig {
+    #[serde(default)]
+    pub sounds: HashMap<PathBuf, SoundSettings>,
+}
+
+impl SoundConfig {
+    pub fn config_path() -> Result<PathBuf> {
+        Ok(get_config_path()?.join("sounds.json"))
+    }
+
+    pub fn load() -> Result<SoundConfig> {
+        let path = Self::config_path()?;
+        if !path.exists() {
+            return Ok(SoundConfig::default());
+        }
+        let bytes = fs::read(&path)?;
+        match serde_json::from_slice::<SoundConfig>(&bytes) {
+            Ok(config) => Ok(config),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::config_path()?;
+        if let Some(dir) = path.parent()
+            && !dir.exists()
+        {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn get(&self, path: &Path) -> SoundSettings {
+        self.sounds.get(path).cloned().unwrap_or_default()
+    }
+
+    /// Stores settings for a file, dropping the entry once it is back to defaults.
+    pub fn set(&mut self, path: PathBuf, settings: SoundSettings) {
+        if settings == SoundSettings::default() {
+            self.sounds.remove(&path);
+        } else {
+            self.sounds.insert(path, settings);
+        }
+    }
+
+    pub fn files_with_tag(&self, tag: &str) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self
+            .sounds
+            .iter()
+            .filter(|(_, s)| s.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+            .map(|(p, _)| p.clone())
+            .collect();
+        files.sort();
+        files
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -360,4 +521,70 @@ mod tests {
         assert_eq!(conflicts.len(), 1);
         assert!(conflicts.contains(&("slot1", "slot2")) || conflicts.contains(&("slot2", "slot1")));
     }
+
+    #[test]
+    fn test_sound_config_tags() {
+        let mut config = SoundConfig::default();
+        config.set(
+            PathBuf::from("/b.mp3"),
+            SoundSettings {
+                tags: vec!["Fart".to_string()],
+                ..Default::default()
+            },
+        );
+        config.set(
+            PathBuf::from("/a.mp3"),
+            SoundSettings {
+                tags: vec!["fart".to_string(), "applause".to_string()],
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(
+            config.files_with_tag("fart"),
+            vec![PathBuf::from("/a.mp3"), PathBuf::from("/b.mp3")]
+        );
+        assert_eq!(config.files_with_tag("applause").len(), 1);
+        assert!(config.files_with_tag("boo").is_empty());
+
+        // Default settings remove the entry
+        config.set(PathBuf::from("/a.mp3"), SoundSettings::default());
+        assert_eq!(config.sounds.len(), 1);
+        assert_eq!(config.get(Path::new("/a.mp3")), SoundSettings::default());
+    }
+
+    #[test]
+    fn test_sound_settings_merged() {
+        let settings = SoundSettings {
+            tags: vec!["applause".to_string()],
+            end: Some(4.0),
+            ..Default::default()
+        };
+        let changes = serde_json::json!({"fade_in": 0.5, "end": null});
+        let merged = settings.merged(changes.as_object().unwrap()).unwrap();
+        assert_eq!(merged.tags, vec!["applause".to_string()]);
+        assert_eq!(merged.fade_in, 0.5);
+        assert_eq!(merged.end, None);
+
+        let unknown = serde_json::json!({"volume": 0.5});
+        assert!(settings.merged(unknown.as_object().unwrap()).is_err());
+        let invalid = serde_json::json!({"fade_in": "long"});
+        assert!(settings.merged(invalid.as_object().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_hotkey_slot_playback_action() {
+        let mut config = HotkeyConfig::default();
+        config.set_slot("horn".to_string(), Request::play("/horn.mp3", true));
+        config.set_slot("stop".to_string(), Request::stop(None));
+
+        let slot = config.find_slot_mut("horn").unwrap();
+        slot.max_voices = Some(2);
+        let action = slot.playback_action();
+        assert_eq!(action.args.get("slot").map(String::as_str), Some("horn"));
+        assert_eq!(action.args.get("max_voices").map(String::as_str), Some("2"));
+
+        let action = config.find_slot("stop").unwrap().playback_action();
+        assert!(!action.args.contains_key("slot"));
+    }
 }
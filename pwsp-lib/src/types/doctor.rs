@@ -0,0 +1,190 @@
+use crate::{
+    types::{
+        buses::BusInfo,
+        pipewire::{PortDirection, PwGraph},
+    },
+    utils::pipewire::{PASSTHROUGH_NODE, bus_stream_name},
+};
+
+/// One line of `pwsp-cli doctor`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub ok: bool,
+    pub message: String,
+}
+
+impl Finding {
+    fn ok(message: String) -> Self {
+        Finding { ok: true, message }
+    }
+
+    fn problem(message: String) -> Self {
+        Finding { ok: false, message }
+    }
+}
+
+/// Checks the nodes, ports and links the daemon should have set up: every bus stream linked
+/// into its virtual mic and the selected microphone (its `node.name`) linked into the capture.
+pub fn diagnose(graph: &PwGraph, buses: &[BusInfo], input: Option<&str>) -> Vec<Finding> {
+    let mut findings = vec![];
+
+    for bus in buses {
+        let Some(mic) = graph.node(&bus.node) else {
+            findings.push(Finding::problem(format!(
+                "Virtual mic {} of bus {} does not exist, it is created when the daemon starts",
+                bus.node, bus.name
+            )));
+            continue;
+        };
+        if graph.ports(mic.id, PortDirection::In).is_empty() {
+            findings.push(Finding::problem(format!(
+                "Virtual mic {} has no input ports",
+                bus.node
+            )));
+            continue;
+        }
+
+        let stream_name = bus_stream_name(&bus.name);
+        let Some(stream) = graph.node(&stream_name) else {
+            findings.push(Finding::problem(format!(
+                "Stream {} of bus {} does not exist, check the daemon output for stream errors",
+                stream_name, bus.name
+            )));
+            continue;
+        };
+        if graph.ports(stream.id, PortDirection::Out).is_empty() {
+            findings.push(Finding::problem(format!(
+                "Stream {} has no output ports",
+                stream_name
+            )));
+            continue;
+        }
+
+        match graph.links_between(stream.id, mic.id) {
+            0 => findings.push(Finding::problem(format!(
+                "Bus {} is not linked into {}, sounds will not reach it",
+                bus.name, bus.node
+            ))),
+            links => findings.push(Finding::ok(format!(
+                "Bus {} is linked into {} ({} links)",
+                bus.name, bus.node, links
+            ))),
+        }
+    }
+
+    let Some(input) = input else {
+        findings.push(Finding::ok(
+            "No input device selected, the microphone is not passed through".to_string(),
+        ));
+        return findings;
+    };
+    let Some(device) = graph.node(input) else {
+        findings.push(Finding::problem(format!(
+            "Input device {} does not exist, pick another one with pwsp-cli set input",
+            input
+        )));
+        return findings;
+    };
+    if graph.ports(device.id, PortDirection::Out).is_empty() {
+        findings.push(Finding::problem(format!(
+            "Input device {} has no output ports",
+            input
+        )));
+        return findings;
+    }
+    let Some(capture) = graph.node(PASSTHROUGH_NODE) else {
+        findings.push(Finding::problem(format!(
+            "Microphone capture {} does not exist, the input device could not be linked",
+            PASSTHROUGH_NODE
+        )));
+        return findings;
+    };
+    match graph.links_between(device.id, capture.id) {
+        0 => findings.push(Finding::problem(format!(
+            "Input device {} is not linked into {}, check its input channels",
+            input, PASSTHROUGH_NODE
+        ))),
+        links => findings.push(Finding::ok(format!(
+            "Input device {} is linked into {} ({} links)",
+            input, PASSTHROUGH_NODE, links
+        ))),
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::pipewire::{DeviceType, Port, PwLink, PwNode};
+
+    fn node(id: u32, name: &str, kind: DeviceType) -> PwNode {
+        PwNode {
+            id,
+            name: name.to_string(),
+            nick: name.to_string(),
+            kind,
+            serial: None,
+            app_name: None,
+        }
+    }
+
+    fn link(id: u32, output: &Port, input: &Port) -> PwLink {
+        PwLink {
+            id,
+            output_node: output.node_id,
+            output_port: output.id,
+            input_node: input.node_id,
+            input_port: input.id,
+        }
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let bus = BusInfo {
+            name: "default".to_string(),
+            description: "PWSP Virtual Mic".to_string(),
+            node: "pwsp-virtual-mic".to_string(),
+            mic: true,
+            volume: 1.0,
+            linked: true,
+        };
+        // Every node numbers its ports from 0, links refer to the global ids
+        let mic_in = Port::new(10, 1, 0, "input_MONO", None, None);
+        let stream_out = Port::new(20, 2, 0, "output_MONO", None, None);
+        let usb_out = Port::new(30, 3, 0, "capture_MONO", None, None);
+        let capture_in = Port::new(40, 4, 0, "input_MONO", None, None);
+        let mut graph = PwGraph {
+            nodes: vec![
+                node(1, "pwsp-virtual-mic", DeviceType::Input),
+                node(2, "pwsp-bus-default", DeviceType::Output),
+                node(3, "alsa_input.usb", DeviceType::Input),
+                node(4, PASSTHROUGH_NODE, DeviceType::Capture),
+            ],
+            ports: vec![
+                mic_in.clone(),
+                stream_out.clone(),
+                usb_out.clone(),
+                capture_in.clone(),
+            ],
+            links: vec![link(100, &stream_out, &mic_in)],
+        };
+
+        let findings = diagnose(&graph, std::slice::from_ref(&bus), Some("alsa_input.usb"));
+        assert_eq!(findings.len(), 2);
+        assert!(findings[0].ok);
+        assert!(!findings[1].ok);
+        assert!(findings[1].message.contains("not linked"));
+
+        graph.links.push(link(101, &usb_out, &capture_in));
+        let findings = diagnose(&graph, std::slice::from_ref(&bus), Some("alsa_input.usb"));
+        assert!(findings.iter().all(|finding| finding.ok));
+
+        // A missing virtual mic is reported without looking at its stream
+        graph.nodes.remove(0);
+        let findings = diagnose(&graph, &[bus], None);
+        assert_eq!(findings.len(), 2);
+        assert!(findings[0].message.contains("does not exist"));
+        assert!(findings[1].ok);
+    }
+}
@@ -0,0 +1,771 @@
+use crate::utils::config::get_config_path;
+use anyhow::Result;
+use rodio::{ChannelCount, SampleRate, Source, source::SeekError};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    f32::consts::PI,
+    fs,
+    path::PathBuf,
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+/// Longest echo the delay effect can produce
+const MAX_DELAY: Duration = Duration::from_secs(2);
+
+/// One stage of an effect chain. Frequencies are in Hz, gains in dB, mixes in 0..1.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Effect {
+    /// Peaking band of a parametric EQ
+    Eq {
+        frequency: f32,
+        gain_db: f32,
+        q: f32,
+    },
+    HighPass {
+        frequency: f32,
+        q: f32,
+    },
+    LowPass {
+        frequency: f32,
+        q: f32,
+    },
+    Delay {
+        time_ms: f32,
+        feedback: f32,
+        mix: f32,
+    },
+    Reverb {
+        room_size: f32,
+        damping: f32,
+        mix: f32,
+    },
+    /// Reduces bit depth and holds every sample for `downsample` frames
+    Bitcrush {
+        bits: u32,
+        downsample: u32,
+    },
+    Distortion {
+        drive: f32,
+        mix: f32,
+    },
+}
+
+/// Named effect chains, stored in `effect_presets.json`.
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct EffectPresets {
+    #[serde(default)]
+    pub presets: HashMap<String, Vec<Effect>>,
+}
+
+impl EffectPresets {
+    pub fn config_path() -> Result<PathBuf> {
+        Ok(get_config_path()?.join("effect_presets.json"))
+    }
+
+    pub fn load() -> Result<EffectPresets> {
+        let path = Self::config_path()?;
+        if !path.exists() {
+            return Ok(EffectPresets::default());
+        }
+        let bytes = fs::read(&path)?;
+        Ok(serde_json::from_slice::<EffectPresets>(&bytes)?)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::config_path()?;
+        if let Some(dir) = path.parent()
+            && !dir.exists()
+        {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Effect chain of a track or the master bus, swapped by the daemon and picked up by the audio thread.
+///
+/// The processors are built here for every source playing the chain, so the audio thread only
+/// swaps them in.
+#[derive(Debug, Default)]
+pub struct EffectControl {
+    chain: Mutex<Vec<Effect>>,
+    version: AtomicU64,
+    slots: Mutex<Vec<Weak<ChainSlot>>>,
+}
+
+/// Processors waiting for one source. Once swapped in, the replaced ones wait here until the
+/// next change drops them off the audio thread.
+#[derive(Debug)]
+struct ChainSlot {
+    sample_rate: f32,
+    channels: usize,
+    pending: Mutex<(u64, EffectChain)>,
+}
+
+impl EffectControl {
+    pub fn new(chain: Vec<Effect>) -> Self {
+        EffectControl {
+            chain: Mutex::new(chain),
+            ..Default::default()
+        }
+    }
+
+    pub fn chain(&self) -> Vec<Effect> {
+        self.chain.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn set_chain(&self, chain: Vec<Effect>) {
+        let mut current = self.chain.lock().unwrap_or_else(|e| e.into_inner());
+        *current = chain;
+        let version = self.version.fetch_add(1, Ordering::Release) + 1;
+
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        slots.retain(|slot| slot.strong_count() > 0);
+        for slot in slots.iter().filter_map(Weak::upgrade) {
+            let processors = EffectChain::new(&current, slot.sample_rate, slot.channels);
+            *slot.pending.lock().unwrap_or_else(|e| e.into_inner()) = (version, processors);
+        }
+    }
+
+    /// Processors for a new source of the given format and the slot later chains arrive in.
+    fn subscribe(&self, sample_rate: f32, channels: usize) -> (u64, EffectChain, Arc<ChainSlot>) {
+        let chain = self.chain.lock().unwrap_or_else(|e| e.into_inner());
+        let version = self.version.load(Ordering::Acquire);
+        let slot = Arc::new(ChainSlot {
+            sample_rate,
+            channels,
+            pending: Mutex::new((version, EffectChain::default())),
+        });
+        self.slots
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Arc::downgrade(&slot));
+        (
+            version,
+            EffectChain::new(&chain, sample_rate, channels),
+            slot,
+        )
+    }
+}
+
+/// RBJ cookbook biquad, one state per channel
+#[derive(Debug, Clone)]
+struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    state: Vec<[f32; 4]>,
+}
+
+impl Biquad {
+    fn new(effect: &Effect, sample_rate: f32, channels: usize) -> Self {
+        let (frequency, q, gain_db) = match *effect {
+            Effect::Eq {
+                frequency,
+                gain_db,
+                q,
+            } => (frequency, q, gain_db),
+            Effect::HighPass { frequency, q } | Effect::LowPass { frequency, q } => {
+                (frequency, q, 0.0)
+            }
+            _ => (1000.0, 0.707, 0.0),
+        };
+
+        let frequency = frequency.clamp(10.0, sample_rate * 0.49);
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q.max(0.05));
+
+        let (b, a0, a) = match effect {
+            Effect::HighPass { .. } => (
+                [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+                1.0 + alpha,
+                [-2.0 * cos, 1.0 - alpha],
+            ),
+            Effect::LowPass { .. } => (
+                [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+                1.0 + alpha,
+                [-2.0 * cos, 1.0 - alpha],
+            ),
+            _ => {
+                let amp = 10f32.powf(gain_db / 40.0);
+                (
+                    [1.0 + alpha * amp, -2.0 * cos, 1.0 - alpha * amp],
+                    1.0 + alpha / amp,
+                    [-2.0 * cos, 1.0 - alpha / amp],
+                )
+            }
+        };
+
+        Biquad {
+            b: b.map(|x| x / a0),
+            a: a.map(|x| x / a0),
+            state: vec![[0.0; 4]; channels],
+        }
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        for (sample, [x1, x2, y1, y2]) in frame.iter_mut().zip(&mut self.state) {
+            let x = *sample;
+            let y = self.b[0] * x + self.b[1] * *x1 + self.b[2] * *x2
+                - self.a[0] * *y1
+                - self.a[1] * *y2;
+            (*x2, *x1, *y2, *y1) = (*x1, x, *y1, y);
+            *sample = y;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state.fill([0.0; 4]);
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Comb {
+    buffer: Vec<f32>,
+    pos: usize,
+    store: f32,
+}
+
+impl Comb {
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let output = self.buffer[self.pos];
+        self.store = output * (1.0 - damping) + self.store * damping;
+        self.buffer[self.pos] = input + self.store * feedback;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        output
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Allpass {
+    buffer: Vec<f32>,
+    pos: usize,
+}
+
+impl Allpass {
+    fn process(&mut self, input: f32) -> f32 {
+        let buffered = self.buffer[self.pos];
+        self.buffer[self.pos] = input + buffered * 0.5;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        buffered - input
+    }
+}
+
+/// Freeverb-style reverb: parallel combs into series allpasses, per channel
+#[derive(Debug, Clone)]
+struct Reverb {
+    combs: Vec<Vec<Comb>>,
+    allpasses: Vec<Vec<Allpass>>,
+    feedback: f32,
+    damping: f32,
+    mix: f32,
+}
+
+impl Reverb {
+    const COMB_TUNING: [usize; 4] = [1116, 1188, 1277, 1356];
+    const ALLPASS_TUNING: [usize; 2] = [556, 441];
+    /// Right channels get slightly longer lines for a wider image
+    const STEREO_SPREAD: usize = 23;
+
+    fn new(room_size: f32, damping: f32, mix: f32, sample_rate: f32, channels: usize) -> Self {
+        let scale = |length: usize, channel: usize| {
+            (((length + channel * Self::STEREO_SPREAD) as f32 * sample_rate / 44100.0) as usize)
+                .max(1)
+        };
+
+        Reverb {
+            combs: (0..channels)
+                .map(|channel| {
+                    Self::COMB_TUNING
+                        .iter()
+                        .map(|&length| Comb {
+                            buffer: vec![0.0; scale(length, channel)],
+                            pos: 0,
+                            store: 0.0,
+                        })
+                        .collect()
+                })
+                .collect(),
+            allpasses: (0..channels)
+                .map(|channel| {
+                    Self::ALLPASS_TUNING
+                        .iter()
+                        .map(|&length| Allpass {
+                            buffer: vec![0.0; scale(length, channel)],
+                            pos: 0,
+                        })
+                        .collect()
+                })
+                .collect(),
+            feedback: room_size.clamp(0.0, 1.0) * 0.28 + 0.7,
+            damping: damping.clamp(0.0, 1.0) * 0.4,
+            mix: mix.clamp(0.0, 1.0),
+        }
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        for (channel, sample) in frame.iter_mut().enumerate() {
+            let input = *sample * 0.015;
+            let mut wet = self.combs[channel]
+                .iter_mut()
+                .map(|comb| comb.process(input, self.feedback, self.damping))
+                .sum::<f32>();
+            for allpass in &mut self.allpasses[channel] {
+                wet = allpass.process(wet);
+            }
+            *sample = *sample * (1.0 - self.mix) + wet * self.mix * 3.0;
+        }
+    }
+
+    fn reset(&mut self) {
+        for comb in self.combs.iter_mut().flatten() {
+            comb.buffer.fill(0.0);
+            comb.pos = 0;
+            comb.store = 0.0;
+        }
+        for allpass in self.allpasses.iter_mut().flatten() {
+            allpass.buffer.fill(0.0);
+            allpass.pos = 0;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Processor {
+    Filter(Biquad),
+    Delay {
+        buffer: Vec<f32>,
+        channels: usize,
+        length: usize,
+        pos: usize,
+        feedback: f32,
+        mix: f32,
+    },
+    Reverb(Reverb),
+    Bitcrush {
+        levels: f32,
+        downsample: u32,
+        counter: u32,
+        held: Vec<f32>,
+    },
+    Distortion {
+        drive: f32,
+        mix: f32,
+    },
+}
+
+impl Processor {
+    fn new(effect: &Effect, sample_rate: f32, channels: usize) -> Self {
+        match *effect {
+            Effect::Eq { .. } | Effect::HighPass { .. } | Effect::LowPass { .. } => {
+                Processor::Filter(Biquad::new(effect, sample_rate, channels))
+            }
+            Effect::Delay {
+                time_ms,
+                feedback,
+                mix,
+            } => {
+                let max = (MAX_DELAY.as_secs_f32() * sample_rate) as usize;
+                let length = ((time_ms / 1000.0 * sample_rate) as usize).clamp(1, max);
+                Processor::Delay {
+                    buffer: vec![0.0; length * channels],
+                    channels,
+                    length,
+                    pos: 0,
+                    feedback: feedback.clamp(0.0, 0.95),
+                    mix: mix.clamp(0.0, 1.0),
+                }
+            }
+            Effect::Reverb {
+                room_size,
+                damping,
+                mix,
+            } => Processor::Reverb(Reverb::new(room_size, damping, mix, sample_rate, channels)),
+            Effect::Bitcrush { bits, downsample } => Processor::Bitcrush {
+                levels: 2f32.powi(bits.clamp(1, 24) as i32 - 1),
+                downsample: downsample.max(1),
+                counter: 0,
+                held: vec![0.0; channels],
+            },
+            Effect::Distortion { drive, mix } => Processor::Distortion {
+                drive: drive.max(1.0),
+                mix: mix.clamp(0.0, 1.0),
+            },
+        }
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        match self {
+            Processor::Filter(biquad) => biquad.process(frame),
+            Processor::Delay {
+                buffer,
+                channels,
+                length,
+                pos,
+                feedback,
+                mix,
+            } => {
+                let offset = *pos * *channels;
+                for (channel, sample) in frame.iter_mut().enumerate() {
+                    let delayed = buffer[offset + channel];
+                    buffer[offset + channel] = *sample + delayed * *feedback;
+                    *sample += delayed * *mix;
+                }
+                *pos = (*pos + 1) % *length;
+            }
+            Processor::Reverb(reverb) => reverb.process(frame),
+            Processor::Bitcrush {
+                levels,
+                downsample,
+                counter,
+                held,
+            } => {
+                if *counter == 0 {
+                    for (held, sample) in held.iter_mut().zip(frame.iter()) {
+                        *held = (*sample * *levels).round() / *levels;
+                    }
+                }
+                *counter = (*counter + 1) % *downsample;
+                frame.copy_from_slice(held);
+            }
+            Processor::Distortion { drive, mix } => {
+                let norm = drive.tanh();
+                for sample in frame.iter_mut() {
+                    let wet = (*sample * *drive).tanh() / norm;
+                    *sample = *sample * (1.0 - *mix) + wet * *mix;
+                }
+            }
+        }
+    }
+
+    /// Silences delay lines and filter state without reallocating
+    fn reset(&mut self) {
+        match self {
+            Processor::Filter(biquad) => biquad.reset(),
+            Processor::Delay { buffer, pos, .. } => {
+                buffer.fill(0.0);
+                *pos = 0;
+            }
+            Processor::Reverb(reverb) => reverb.reset(),
+            Processor::Bitcrush { counter, held, .. } => {
+                *counter = 0;
+                held.fill(0.0);
+            }
+            Processor::Distortion { .. } => {}
+        }
+    }
+}
+
+/// Processors of an effect chain, fed one interleaved frame at a time.
+#[derive(Debug, Default)]
+pub(crate) struct EffectChain {
+    processors: Vec<Processor>,
+}
+
+impl EffectChain {
+    pub(crate) fn new(effects: &[Effect], sample_rate: f32, channels: usize) -> Self {
+        EffectChain {
+            processors: effects
+                .iter()
+                .map(|effect| Processor::new(effect, sample_rate, channels))
+                .collect(),
+        }
+    }
+
+    pub(crate) fn process(&mut self, frame: &mut [f32]) {
+        for processor in &mut self.processors {
+            processor.process(frame);
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        for processor in &mut self.processors {
+            processor.reset();
+        }
+    }
+}
+
+/// Runs a source through the chain of an [`EffectControl`], picking up changes while playing.
+pub struct Effected<S: Source> {
+    input: S,
+    control: Arc<EffectControl>,
+    version: u64,
+    processors: EffectChain,
+    slot: Arc<ChainSlot>,
+    channels: usize,
+    frame: Vec<f32>,
+    frame_pos: usize,
+}
+
+impl<S: Source> Effected<S> {
+    pub fn new(input: S, control: Arc<EffectControl>) -> Self {
+        let channels = (u16::from(input.channels()) as usize).max(1);
+        let sample_rate = u32::from(input.sample_rate()) as f32;
+        let (version, processors, slot) = control.subscribe(sample_rate, channels);
+        Effected {
+            input,
+            control,
+            version,
+            processors,
+            slot,
+            channels,
+            frame: Vec::with_capacity(channels),
+            frame_pos: 0,
+        }
+    }
+
+    /// Swaps in the processors the daemon built for a changed chain, trying again on the
+    /// next frame while it is still building them.
+    fn pick_up_chain(&mut self) {
+        if let Ok(mut pending) = self.slot.pending.try_lock()
+            && pending.0 != self.version
+        {
+            std::mem::swap(&mut self.processors, &mut pending.1);
+            self.version = pending.0;
+        }
+    }
+
+    fn process_frame(&mut self) -> bool {
+        self.frame.clear();
+        for _ in 0..self.channels {
+            match self.input.next() {
+                Some(sample) => self.frame.push(sample),
+                None => break,
+            }
+        }
+        if self.frame.is_empty() {
+            return false;
+        }
+        self.frame.resize(self.channels, 0.0);
+
+        if self.control.version.load(Ordering::Acquire) != self.version {
+            self.pick_up_chain();
+        }
+        self.processors.process(&mut self.frame);
+        true
+    }
+}
+
+impl<S: Source> Iterator for Effected<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.frame_pos >= self.frame.len() {
+            if !self.process_frame() {
+                return None;
+            }
+            self.frame_pos = 0;
+        }
+
+        let sample = self.frame[self.frame_pos];
+        self.frame_pos += 1;
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S: Source> Source for Effected<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        // Drop tails of the old position
+        self.processors.reset();
+        self.frame.clear();
+        self.frame_pos = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn buffer(samples: Vec<f32>) -> SamplesBuffer {
+        let channels: ChannelCount = 1u16.try_into().unwrap();
+        let sample_rate: SampleRate = 8000u32.try_into().unwrap();
+        SamplesBuffer::new(channels, sample_rate, samples)
+    }
+
+    fn sine(frequency: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (i as f32 * frequency * 2.0 * PI / 8000.0).sin())
+            .collect()
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0f32, |peak, s| peak.max(s.abs()))
+    }
+
+    fn run(effects: Vec<Effect>, samples: Vec<f32>) -> Vec<f32> {
+        let control = Arc::new(EffectControl::new(effects));
+        Effected::new(buffer(samples), control).collect()
+    }
+
+    #[test]
+    fn test_empty_chain_is_transparent() {
+        let input = sine(440.0, 400);
+        assert_eq!(run(vec![], input.clone()), input);
+    }
+
+    #[test]
+    fn test_filters() {
+        let low_pass = vec![Effect::LowPass {
+            frequency: 200.0,
+            q: 0.707,
+        }];
+        let high_pass = vec![Effect::HighPass {
+            frequency: 2000.0,
+            q: 0.707,
+        }];
+
+        // Skip the filter settling time
+        assert!(peak(&run(low_pass.clone(), sine(3000.0, 4000))[1000..]) < 0.05);
+        assert!(peak(&run(low_pass, sine(50.0, 4000))[1000..]) > 0.9);
+        assert!(peak(&run(high_pass, sine(100.0, 4000))[1000..]) < 0.05);
+    }
+
+    #[test]
+    fn test_eq_boosts_band() {
+        let eq = vec![Effect::Eq {
+            frequency: 1000.0,
+            gain_db: 6.0,
+            q: 1.0,
+        }];
+        let boosted = peak(&run(eq, sine(1000.0, 4000))[1000..]);
+        assert!((boosted - 2.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_delay_repeats_impulse() {
+        let mut impulse = vec![0.0; 100];
+        impulse[0] = 1.0;
+        let output = run(
+            vec![Effect::Delay {
+                time_ms: 5.0,
+                feedback: 0.5,
+                mix: 1.0,
+            }],
+            impulse,
+        );
+        // 5 ms at 8 kHz is 40 frames
+        assert_eq!(output[0], 1.0);
+        assert_eq!(output[40], 1.0);
+        assert_eq!(output[80], 0.5);
+    }
+
+    #[test]
+    fn test_reverb_has_decaying_tail() {
+        let mut impulse = vec![0.0; 16000];
+        impulse[0] = 1.0;
+        let output = run(
+            vec![Effect::Reverb {
+                room_size: 0.5,
+                damping: 0.5,
+                mix: 1.0,
+            }],
+            impulse,
+        );
+        let early = peak(&output[..4000]);
+        let late = peak(&output[12000..]);
+        assert!(early > 0.0);
+        assert!(late < early);
+        assert!(output.iter().all(|s| s.is_finite()));
+    }
+
+    #[test]
+    fn test_bitcrush_and_distortion() {
+        let crushed = run(
+            vec![Effect::Bitcrush {
+                bits: 2,
+                downsample: 2,
+            }],
+            vec![0.3, 0.9, -0.4, 0.1],
+        );
+        assert_eq!(crushed, vec![0.5, 0.5, -0.5, -0.5]);
+
+        let distorted = run(
+            vec![Effect::Distortion {
+                drive: 10.0,
+                mix: 1.0,
+            }],
+            vec![0.5],
+        );
+        assert!(distorted[0] > 0.99);
+    }
+
+    #[test]
+    fn test_chain_change_is_picked_up() {
+        let control = Arc::new(EffectControl::default());
+        let mut source = Effected::new(buffer(vec![0.5; 10]), control.clone());
+        assert_eq!(source.next(), Some(0.5));
+
+        control.set_chain(vec![Effect::Bitcrush {
+            bits: 1,
+            downsample: 1,
+        }]);
+        assert_eq!(source.next(), Some(1.0));
+
+        // Sources that are gone get no more chains built for them
+        drop(source);
+        control.set_chain(vec![]);
+        assert!(control.slots.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_seek_resets_effect_tails() {
+        let delay = vec![Effect::Delay {
+            time_ms: 1.0,
+            feedback: 0.5,
+            mix: 1.0,
+        }];
+        let samples: Vec<f32> = (0..40)
+            .map(|i| if i % 10 == 0 { 1.0 } else { 0.0 })
+            .collect();
+        let fresh = run(delay.clone(), samples.clone());
+
+        let mut source = Effected::new(buffer(samples), Arc::new(EffectControl::new(delay)));
+        source.by_ref().take(25).for_each(drop);
+        source.try_seek(Duration::ZERO).unwrap();
+        assert_eq!(source.collect::<Vec<_>>(), fresh);
+    }
+
+    #[test]
+    fn test_effect_serialization() {
+        let effect: Effect =
+            serde_json::from_str(r#"{"type":"high_pass","frequency":120.0,"q":0.7}"#).unwrap();
+        assert_eq!(
+            effect,
+            Effect::HighPass {
+                frequency: 120.0,
+                q: 0.7
+            }
+        );
+    }
+}
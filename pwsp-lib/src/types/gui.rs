@@ -1,6 +1,9 @@
 use crate::types::{
     audio_player::{PlayerState, TrackInfo},
-    config::HotkeyConfig,
+    config::{HotkeyConfig, SoundSettings},
+    meter::Levels,
+    trim::Preview,
+    waveform::Waveform,
 };
 
 use egui::Id;
@@ -8,9 +11,27 @@ use egui::Id;
 use std::{
     collections::{HashMap, HashSet},
     path::PathBuf,
-    time::Instant,
+    sync::{Arc, Mutex},
+    time::{Instant, SystemTime},
 };
 
+#[derive(Debug, Clone)]
+pub enum WaveformState {
+    Loading,
+    Ready(Arc<Waveform>),
+    Failed,
+}
+
+/// Waveform of a file as it was at `modified`, an edited file is computed again.
+#[derive(Debug, Clone)]
+pub struct WaveformEntry {
+    pub modified: Option<SystemTime>,
+    pub state: WaveformState,
+}
+
+/// Waveforms are computed off the UI thread and collected here.
+pub type WaveformStore = Arc<Mutex<HashMap<PathBuf, WaveformEntry>>>;
+
 #[derive(Default, Debug)]
 pub struct TrackUiState {
     pub position_slider_value: f32,
@@ -21,6 +42,26 @@ pub struct TrackUiState {
 
     pub ignore_position_update_until: Option<Instant>,
     pub ignore_volume_update_until: Option<Instant>,
+
+    pub speed_slider_value: f32,
+    pub pitch_slider_value: f32,
+    pub ignore_rate_update_until: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrimHandle {
+    Start,
+    End,
+}
+
+/// The sound being trimmed in the trim editor.
+#[derive(Debug)]
+pub struct TrimEditorState {
+    pub path: PathBuf,
+    pub settings: SoundSettings,
+    pub dragging: Option<TrimHandle>,
+    pub preview: Option<Preview>,
+    pub error: Option<String>,
 }
 
 #[derive(Default, Debug)]
@@ -39,6 +80,10 @@ pub struct AppState {
 
     pub search_field_id: Option<Id>,
 
+    /// Text typed into the say field, kept so it can be repeated or bound to a hotkey
+    pub say_text: String,
+    pub say_field_id: Option<Id>,
+
     pub ignore_volume_update_until: Option<Instant>,
     pub ignore_volume_multiplier_update_until: Option<Instant>,
 
@@ -58,6 +103,10 @@ pub struct AppState {
 
     pub assigning_hotkey_slot: Option<String>,
     pub assigning_hotkey_for_file: Option<PathBuf>,
+    pub assigning_hotkey_for_dir: Option<PathBuf>,
+
+    pub waveforms: WaveformStore,
+    pub trim_editor: Option<TrimEditorState>,
 }
 
 #[derive(Default, Debug, Clone)]
@@ -66,6 +115,7 @@ pub struct AudioPlayerState {
     pub new_state: Option<PlayerState>,
 
     pub tracks: Vec<TrackInfo>,
+    pub levels: Levels,
 
     pub volume: f32, // Master volume
     pub volume_multiplier: f32,
@@ -76,5 +126,8 @@ pub struct AudioPlayerState {
 
     pub is_daemon_running: bool,
 
+    pub recording: bool,
+    pub clips_saved: u64,
+
     pub hotkey_config: Option<HotkeyConfig>,
 }
@@ -0,0 +1,423 @@
+use rodio::{ChannelCount, SampleRate, Source};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    str::FromStr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU8, AtomicU32, Ordering},
+    },
+    time::Duration,
+};
+
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LimiterMode {
+    /// Look-ahead peak limiter, transparent until the ceiling is hit
+    #[default]
+    Limiter,
+    /// Saturates smoothly towards the ceiling instead of ducking the whole mix
+    SoftClip,
+}
+
+impl LimiterMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LimiterMode::Limiter => "limiter",
+            LimiterMode::SoftClip => "soft_clip",
+        }
+    }
+}
+
+impl FromStr for LimiterMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "limiter" | "limit" => Ok(LimiterMode::Limiter),
+            "soft_clip" | "softclip" | "clip" => Ok(LimiterMode::SoftClip),
+            other => Err(format!("Unknown limiter mode: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct LimiterSettings {
+    pub enabled: bool,
+    pub mode: LimiterMode,
+    /// Output ceiling in dBFS
+    pub ceiling_db: f32,
+    pub lookahead_ms: f32,
+    pub release_ms: f32,
+}
+
+impl Default for LimiterSettings {
+    fn default() -> Self {
+        LimiterSettings {
+            enabled: false,
+            mode: LimiterMode::Limiter,
+            ceiling_db: -1.0,
+            lookahead_ms: 5.0,
+            release_ms: 100.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct LimiterStatus {
+    pub settings: LimiterSettings,
+    /// Current gain reduction in dB (0 when idle)
+    pub gain_reduction_db: f32,
+}
+
+pub fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+pub fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.max(1e-6).log10()
+}
+
+/// Limiter parameters shared between the daemon and the audio thread.
+#[derive(Debug)]
+pub struct LimiterControl {
+    enabled: AtomicBool,
+    mode: AtomicU8,
+    ceiling_db: AtomicU32,
+    release_ms: AtomicU32,
+    lookahead_ms: f32,
+    gain_reduction_db: AtomicU32,
+}
+
+impl LimiterControl {
+    pub fn new(settings: LimiterSettings) -> Self {
+        let defaults = LimiterSettings::default();
+        let control = LimiterControl {
+            enabled: AtomicBool::new(false),
+            mode: AtomicU8::new(0),
+            ceiling_db: AtomicU32::new(defaults.ceiling_db.to_bits()),
+            release_ms: AtomicU32::new(defaults.release_ms.to_bits()),
+            lookahead_ms: settings.lookahead_ms.clamp(0.0, 50.0),
+            gain_reduction_db: AtomicU32::new(0f32.to_bits()),
+        };
+        control.apply(settings);
+        control
+    }
+
+    /// Updates everything but the look-ahead, which only changes when the stream is reopened.
+    /// A non-finite ceiling or release keeps the previous value.
+    pub fn apply(&self, settings: LimiterSettings) {
+        self.enabled.store(settings.enabled, Ordering::Relaxed);
+        self.mode.store(
+            match settings.mode {
+                LimiterMode::Limiter => 0,
+                LimiterMode::SoftClip => 1,
+            },
+            Ordering::Relaxed,
+        );
+        if settings.ceiling_db.is_finite() {
+            self.ceiling_db.store(
+                settings.ceiling_db.clamp(-60.0, 0.0).to_bits(),
+                Ordering::Relaxed,
+            );
+        }
+        if settings.release_ms.is_finite() {
+            self.release_ms.store(
+                settings.release_ms.clamp(1.0, 5000.0).to_bits(),
+                Ordering::Relaxed,
+            );
+        }
+    }
+
+    pub fn settings(&self) -> LimiterSettings {
+        LimiterSettings {
+            enabled: self.enabled.load(Ordering::Relaxed),
+            mode: self.mode(),
+            ceiling_db: f32::from_bits(self.ceiling_db.load(Ordering::Relaxed)),
+            lookahead_ms: self.lookahead_ms,
+            release_ms: f32::from_bits(self.release_ms.load(Ordering::Relaxed)),
+        }
+    }
+
+    pub fn status(&self) -> LimiterStatus {
+        LimiterStatus {
+            settings: self.settings(),
+            gain_reduction_db: self.gain_reduction_db(),
+        }
+    }
+
+    pub fn gain_reduction_db(&self) -> f32 {
+        f32::from_bits(self.gain_reduction_db.load(Ordering::Relaxed))
+    }
+
+    fn mode(&self) -> LimiterMode {
+        match self.mode.load(Ordering::Relaxed) {
+            1 => LimiterMode::SoftClip,
+            _ => LimiterMode::Limiter,
+        }
+    }
+
+    fn set_gain_reduction(&self, gain: f32) {
+        self.gain_reduction_db
+            .store((-gain_to_db(gain)).max(0.0).to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Master bus stage that keeps the summed mix under the configured ceiling.
+///
+/// Frames are delayed by the look-ahead so the gain is already down when a peak arrives.
+pub struct Limiter<S: Source> {
+    input: S,
+    control: Arc<LimiterControl>,
+    channels: usize,
+    sample_rate: u32,
+
+    delay: VecDeque<f32>,
+    lookahead_frames: usize,
+    /// Monotonic queue of (frame index, required gain) for the sliding minimum
+    required: VecDeque<(u64, f32)>,
+    frame_index: u64,
+    gain: f32,
+
+    frame: Vec<f32>,
+    frame_pos: usize,
+    finished: bool,
+}
+
+impl<S: Source> Limiter<S> {
+    pub fn new(input: S, control: Arc<LimiterControl>) -> Self {
+        let channels = (u16::from(input.channels()) as usize).max(1);
+        let sample_rate = u32::from(input.sample_rate()).max(1);
+        let lookahead_frames =
+            ((control.lookahead_ms / 1000.0) * sample_rate as f32).round() as usize;
+
+        Limiter {
+            input,
+            control,
+            channels,
+            sample_rate,
+
+            delay: VecDeque::with_capacity((lookahead_frames + 1) * channels),
+            lookahead_frames,
+            required: VecDeque::new(),
+            frame_index: 0,
+            gain: 1.0,
+
+            frame: vec![0.0; channels],
+            frame_pos: channels,
+            finished: false,
+        }
+    }
+
+    /// Pulls one frame from the input into the delay line, padding a partial last frame with silence.
+    fn read_input_frame(&mut self, ceiling: f32) -> bool {
+        let Some(first) = self.input.next() else {
+            return false;
+        };
+
+        let mut peak = first.abs();
+        self.delay.push_back(first);
+        for _ in 1..self.channels {
+            let sample = self.input.next().unwrap_or_default();
+            peak = peak.max(sample.abs());
+            self.delay.push_back(sample);
+        }
+
+        let required = if peak > ceiling { ceiling / peak } else { 1.0 };
+        while self.required.back().is_some_and(|&(_, g)| g >= required) {
+            self.required.pop_back();
+        }
+        self.required.push_back((self.frame_index, required));
+        self.frame_index += 1;
+
+        true
+    }
+
+    fn next_frame(&mut self) -> bool {
+        let enabled = self.control.enabled.load(Ordering::Relaxed);
+        let ceiling = db_to_gain(f32::from_bits(
+            self.control.ceiling_db.load(Ordering::Relaxed),
+        ));
+
+        if !self.finished {
+            while self.delay.len() < (self.lookahead_frames + 1) * self.channels {
+                if !self.read_input_frame(ceiling) {
+                    self.finished = true;
+                    break;
+                }
+            }
+        }
+        if self.delay.len() < self.channels {
+            return false;
+        }
+
+        // Drop requirements for frames that already left the delay line
+        let output_index = self.frame_index - (self.delay.len() / self.channels) as u64;
+        while self
+            .required
+            .front()
+            .is_some_and(|&(i, _)| i < output_index)
+        {
+            self.required.pop_front();
+        }
+
+        for sample in self.frame.iter_mut() {
+            *sample = self.delay.pop_front().unwrap_or_default();
+        }
+
+        if !enabled {
+            self.gain = 1.0;
+            self.control.set_gain_reduction(1.0);
+            return true;
+        }
+
+        match self.control.mode() {
+            LimiterMode::Limiter => {
+                let target = self.required.front().map(|&(_, g)| g).unwrap_or(1.0);
+                if target < self.gain {
+                    // Reach the target gain within the look-ahead window
+                    let steps = self.lookahead_frames.max(1) as f32;
+                    self.gain = (self.gain - (self.gain - target) / steps * 4.0).max(target);
+                } else {
+                    let release_ms =
+                        f32::from_bits(self.control.release_ms.load(Ordering::Relaxed));
+                    let coeff = (-1.0 / (release_ms / 1000.0 * self.sample_rate as f32)).exp();
+                    self.gain = target + (self.gain - target) * coeff;
+                }
+
+                for sample in self.frame.iter_mut() {
+                    // The clamp only catches what the gain smoothing lets through
+                    *sample = (*sample * self.gain).clamp(-ceiling, ceiling);
+                }
+                self.control.set_gain_reduction(self.gain);
+            }
+            LimiterMode::SoftClip => {
+                let mut reduction = 1f32;
+                for sample in self.frame.iter_mut() {
+                    let clipped = ceiling * (*sample / ceiling).tanh();
+                    if sample.abs() > f32::EPSILON {
+                        reduction = reduction.min(clipped / *sample);
+                    }
+                    *sample = clipped;
+                }
+                self.gain = reduction;
+                self.control.set_gain_reduction(reduction);
+            }
+        }
+
+        true
+    }
+}
+
+impl<S: Source> Iterator for Limiter<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.frame_pos >= self.channels {
+            if !self.next_frame() {
+                return None;
+            }
+            self.frame_pos = 0;
+        }
+
+        let sample = self.frame[self.frame_pos];
+        self.frame_pos += 1;
+        Some(sample)
+    }
+}
+
+impl<S: Source> Source for Limiter<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn limited(samples: Vec<f32>, settings: LimiterSettings) -> (Vec<f32>, Arc<LimiterControl>) {
+        let channels: ChannelCount = 2u16.try_into().unwrap();
+        let sample_rate: SampleRate = 48_000u32.try_into().unwrap();
+        let control = Arc::new(LimiterControl::new(settings));
+        let source = SamplesBuffer::new(channels, sample_rate, samples);
+        (Limiter::new(source, control.clone()).collect(), control)
+    }
+
+    #[test]
+    fn test_disabled_limiter_is_transparent() {
+        let input: Vec<f32> = (0..960).map(|i| (i as f32 / 100.0).sin() * 1.5).collect();
+        let (output, control) = limited(input.clone(), LimiterSettings::default());
+        assert_eq!(output, input);
+        assert_eq!(control.gain_reduction_db(), 0.0);
+    }
+
+    #[test]
+    fn test_limiter_keeps_output_under_ceiling() {
+        let settings = LimiterSettings {
+            enabled: true,
+            ceiling_db: -3.0,
+            ..Default::default()
+        };
+        let input: Vec<f32> = (0..9600).map(|i| (i as f32 / 20.0).sin() * 2.0).collect();
+        let (output, control) = limited(input.clone(), settings);
+
+        assert_eq!(output.len(), input.len());
+        let ceiling = db_to_gain(-3.0);
+        assert!(output.iter().all(|s| s.abs() <= ceiling + 1e-6));
+        assert!(control.gain_reduction_db() > 3.0);
+    }
+
+    #[test]
+    fn test_soft_clip_saturates() {
+        let settings = LimiterSettings {
+            enabled: true,
+            mode: LimiterMode::SoftClip,
+            ceiling_db: 0.0,
+            ..Default::default()
+        };
+        let (output, _) = limited(vec![4.0, -4.0, 0.1, -0.1], settings);
+        assert!(output[0] < 1.0 && output[0] > 0.99);
+        assert!(output[1] > -1.0 && output[1] < -0.99);
+        assert!((output[2] - 0.1).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_limiter_ignores_non_finite_settings() {
+        let control = LimiterControl::new(LimiterSettings {
+            ceiling_db: f32::NAN,
+            ..Default::default()
+        });
+        assert_eq!(
+            control.settings().ceiling_db,
+            LimiterSettings::default().ceiling_db
+        );
+
+        control.apply(LimiterSettings {
+            ceiling_db: -6.0,
+            ..Default::default()
+        });
+        control.apply(LimiterSettings {
+            ceiling_db: f32::INFINITY,
+            release_ms: f32::NAN,
+            ..Default::default()
+        });
+        let settings = control.settings();
+        assert_eq!(settings.ceiling_db, -6.0);
+        assert_eq!(settings.release_ms, LimiterSettings::default().release_ms);
+    }
+}
@@ -0,0 +1,326 @@
+use crate::types::config::SoundSettings;
+use rodio::{ChannelCount, SampleRate, Source, source::SeekError};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+/// `restarts` value of a loop that runs until it is disabled
+const UNLIMITED: u32 = u32::MAX;
+
+/// Loop state of a playing track, shared with its source on the audio thread.
+///
+/// It also carries the position of the source, the player's own clock keeps counting across
+/// restarts.
+#[derive(Debug)]
+pub struct LoopControl {
+    enabled: AtomicBool,
+    /// Restarts left before the loop switches itself off
+    restarts: AtomicU32,
+    /// Frames into the trimmed sound
+    frame: AtomicU64,
+    sample_rate: AtomicU32,
+}
+
+impl Default for LoopControl {
+    fn default() -> Self {
+        LoopControl {
+            enabled: AtomicBool::new(false),
+            restarts: AtomicU32::new(UNLIMITED),
+            frame: AtomicU64::new(0),
+            sample_rate: AtomicU32::new(0),
+        }
+    }
+}
+
+impl LoopControl {
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Turns looping on or off. `count` limits how many more times the loop plays, counting the
+    /// pass that is playing now, unlimited when unset.
+    pub fn set(&self, enabled: bool, count: Option<u32>) {
+        let restarts = count.map_or(UNLIMITED, |count| count.saturating_sub(1));
+        self.restarts.store(restarts, Ordering::Relaxed);
+        self.enabled
+            .store(enabled && restarts > 0, Ordering::Relaxed);
+    }
+
+    /// Passes left after the current one, `None` while unlimited
+    pub fn remaining(&self) -> Option<u32> {
+        let restarts = self.restarts.load(Ordering::Relaxed);
+        (self.enabled() && restarts != UNLIMITED).then_some(restarts)
+    }
+
+    /// Uses up one restart, switching the loop off once the count runs out.
+    pub fn take_restart(&self) -> bool {
+        if !self.enabled() {
+            return false;
+        }
+
+        let taken = self
+            .restarts
+            .fetch_update(
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+                |restarts| match restarts {
+                    0 => None,
+                    UNLIMITED => Some(UNLIMITED),
+                    restarts => Some(restarts - 1),
+                },
+            );
+        match taken {
+            Ok(1) | Err(_) => self.enabled.store(false, Ordering::Relaxed),
+            Ok(_) => {}
+        }
+        taken.is_ok()
+    }
+
+    /// Position of the source, `None` before it played its first sample
+    pub fn position(&self) -> Option<f32> {
+        let sample_rate = self.sample_rate.load(Ordering::Relaxed);
+        (sample_rate > 0).then(|| self.frame.load(Ordering::Relaxed) as f32 / sample_rate as f32)
+    }
+
+    fn set_position(&self, frame: u64, sample_rate: u32) {
+        self.frame.store(frame, Ordering::Relaxed);
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+    }
+}
+
+/// The part of a sound that repeats, relative to the trimmed start. Whatever comes before it
+/// plays once as an intro, whatever comes after it once the loop stops.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LoopRegion {
+    pub start: Duration,
+    /// The end of the sound if unset
+    pub end: Option<Duration>,
+}
+
+impl LoopRegion {
+    pub fn from_settings(settings: &SoundSettings) -> Self {
+        let relative = |time: f32| Duration::from_secs_f32((time - settings.start).max(0.0));
+        let start = relative(settings.loop_start);
+        let end = settings.loop_end.map(relative).filter(|end| *end > start);
+        LoopRegion { start, end }
+    }
+}
+
+/// Jumps back to the start of the loop region the moment it reaches its end, as long as
+/// looping is enabled.
+///
+/// The jump happens between two frames, so the loop has no gap. It relies on the input seeking
+/// exactly, which sounds from the sample cache always do and most decoders do as well.
+pub struct Looped<S: Source> {
+    input: S,
+    control: Arc<LoopControl>,
+    channels: u64,
+    sample_rate: u32,
+    start: Duration,
+    start_frame: u64,
+    end_frame: Option<u64>,
+    /// Interleaved samples into the input
+    played: u64,
+}
+
+impl<S: Source> Looped<S> {
+    pub fn new(input: S, region: LoopRegion, control: Arc<LoopControl>) -> Self {
+        let channels = (u16::from(input.channels()) as u64).max(1);
+        let sample_rate = u32::from(input.sample_rate()).max(1);
+        let to_frames = |d: Duration| (d.as_secs_f64() * sample_rate as f64).round() as u64;
+
+        Looped {
+            channels,
+            sample_rate,
+            start: region.start,
+            start_frame: to_frames(region.start),
+            end_frame: region.end.map(to_frames),
+            input,
+            control,
+            played: 0,
+        }
+    }
+
+    /// A restart is only used up once the seek worked, otherwise the player reopens the
+    /// sound and takes it.
+    fn restart(&mut self) -> bool {
+        if !self.control.enabled() || self.input.try_seek(self.start).is_err() {
+            return false;
+        }
+        self.played = self.start_frame * self.channels;
+        self.control.take_restart()
+    }
+}
+
+impl<S: Source> Iterator for Looped<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let frame_start = self.played.is_multiple_of(self.channels);
+        if frame_start
+            && self
+                .end_frame
+                .is_some_and(|end| self.played / self.channels >= end)
+        {
+            self.restart();
+        }
+
+        let sample = match self.input.next() {
+            Some(sample) => sample,
+            // An input that is empty after the restart stays empty instead of spinning
+            None if frame_start && self.restart() => self.input.next()?,
+            None => return None,
+        };
+        if self.played.is_multiple_of(self.channels) {
+            self.control
+                .set_position(self.played / self.channels, self.sample_rate);
+        }
+        self.played += 1;
+        Some(sample)
+    }
+}
+
+impl<S: Source> Source for Looped<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    /// Length of one pass
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        let frame = (pos.as_secs_f64() * self.sample_rate as f64).round() as u64;
+        self.played = frame * self.channels;
+        self.control.set_position(frame, self.sample_rate);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn buffer() -> SamplesBuffer {
+        let channels: ChannelCount = 1u16.try_into().unwrap();
+        let sample_rate: SampleRate = 10u32.try_into().unwrap();
+        SamplesBuffer::new(channels, sample_rate, vec![1.0, 2.0, 3.0, 4.0])
+    }
+
+    fn looping(count: Option<u32>) -> Arc<LoopControl> {
+        let control = Arc::new(LoopControl::default());
+        control.set(true, count);
+        control
+    }
+
+    #[test]
+    fn test_loop_is_gapless() {
+        let control = looping(None);
+        let mut looped = Looped::new(buffer(), LoopRegion::default(), control.clone());
+
+        let samples: Vec<f32> = looped.by_ref().take(10).collect();
+        assert_eq!(
+            samples,
+            vec![1.0, 2.0, 3.0, 4.0, 1.0, 2.0, 3.0, 4.0, 1.0, 2.0]
+        );
+        assert_eq!(control.position(), Some(0.1));
+
+        // Disabling lets the current pass finish
+        control.set(false, None);
+        assert_eq!(looped.collect::<Vec<_>>(), vec![3.0, 4.0]);
+    }
+
+    #[test]
+    fn test_loop_region_and_count() {
+        // Intro, then 0.1..0.3 three times in total, then the outro
+        let region = LoopRegion {
+            start: Duration::from_millis(100),
+            end: Some(Duration::from_millis(300)),
+        };
+        let control = looping(Some(3));
+        let looped = Looped::new(buffer(), region, control.clone());
+
+        assert_eq!(
+            looped.collect::<Vec<_>>(),
+            vec![1.0, 2.0, 3.0, 2.0, 3.0, 2.0, 3.0, 4.0]
+        );
+        assert!(!control.enabled());
+    }
+
+    #[test]
+    fn test_failed_seek_keeps_the_restart() {
+        struct Unseekable(SamplesBuffer);
+
+        impl Iterator for Unseekable {
+            type Item = f32;
+
+            fn next(&mut self) -> Option<f32> {
+                self.0.next()
+            }
+        }
+
+        impl Source for Unseekable {
+            fn current_span_len(&self) -> Option<usize> {
+                None
+            }
+
+            fn channels(&self) -> ChannelCount {
+                self.0.channels()
+            }
+
+            fn sample_rate(&self) -> SampleRate {
+                self.0.sample_rate()
+            }
+
+            fn total_duration(&self) -> Option<Duration> {
+                None
+            }
+
+            fn try_seek(&mut self, _: Duration) -> Result<(), SeekError> {
+                Err(SeekError::NotSupported {
+                    underlying_source: "test",
+                })
+            }
+        }
+
+        let control = looping(Some(2));
+        let looped = Looped::new(Unseekable(buffer()), LoopRegion::default(), control.clone());
+        assert_eq!(looped.count(), 4);
+
+        // Left for the player, which reopens the sound
+        assert_eq!(control.remaining(), Some(1));
+        assert!(control.take_restart());
+    }
+
+    #[test]
+    fn test_loop_region_from_settings() {
+        let settings = SoundSettings {
+            start: 1.0,
+            loop_start: 3.0,
+            loop_end: Some(2.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            LoopRegion::from_settings(&settings),
+            LoopRegion {
+                start: Duration::from_secs(2),
+                end: None,
+            }
+        );
+    }
+}
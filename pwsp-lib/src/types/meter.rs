@@ -0,0 +1,240 @@
+use rodio::{ChannelCount, SampleRate, Source, source::SeekError};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicU32, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// Levels are published this often
+const WINDOW: Duration = Duration::from_millis(50);
+/// A meter that has not been fed for this long reads as silence
+const STALE_AFTER: Duration = Duration::from_millis(250);
+
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Level {
+    /// Linear sample peak, 1.0 is full scale
+    pub peak: f32,
+    /// Linear RMS
+    pub rms: f32,
+}
+
+impl Level {
+    pub fn scaled(&self, gain: f32) -> Level {
+        Level {
+            peak: self.peak * gain,
+            rms: self.rms * gain,
+        }
+    }
+
+    pub fn peak_db(&self) -> f32 {
+        20.0 * self.peak.max(1e-6).log10()
+    }
+
+    pub fn rms_db(&self) -> f32 {
+        20.0 * self.rms.max(1e-6).log10()
+    }
+
+    pub fn is_clipping(&self) -> bool {
+        self.peak >= 1.0
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct Levels {
+    pub master: Level,
+    /// Level of the selected microphone, if it is being captured
+    pub input: Option<Level>,
+    pub tracks: HashMap<u32, Level>,
+}
+
+fn now_ms() -> u64 {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    EPOCH.get_or_init(Instant::now).elapsed().as_millis() as u64
+}
+
+/// Latest published level, written by the audio thread and read by the daemon.
+#[derive(Debug, Default)]
+pub struct LevelMeter {
+    peak: AtomicU32,
+    rms: AtomicU32,
+    updated_ms: AtomicU64,
+}
+
+impl LevelMeter {
+    pub fn level(&self) -> Level {
+        let updated_ms = self.updated_ms.load(Ordering::Relaxed);
+        if now_ms().saturating_sub(updated_ms) > STALE_AFTER.as_millis() as u64 {
+            return Level::default();
+        }
+
+        Level {
+            peak: f32::from_bits(self.peak.load(Ordering::Relaxed)),
+            rms: f32::from_bits(self.rms.load(Ordering::Relaxed)),
+        }
+    }
+
+    fn publish(&self, level: Level) {
+        self.peak.store(level.peak.to_bits(), Ordering::Relaxed);
+        self.rms.store(level.rms.to_bits(), Ordering::Relaxed);
+        self.updated_ms.store(now_ms(), Ordering::Relaxed);
+    }
+}
+
+fn window_len(sample_rate: u32, channels: u16) -> usize {
+    (sample_rate as f64 * channels as f64 * WINDOW.as_secs_f64()).max(1.0) as usize
+}
+
+/// Collects samples and publishes peak/RMS to a [`LevelMeter`] once per window.
+#[derive(Debug)]
+pub struct LevelAccumulator {
+    meter: Arc<LevelMeter>,
+    window_len: usize,
+    count: usize,
+    sum_sq: f64,
+    peak: f32,
+}
+
+impl LevelAccumulator {
+    pub fn new(meter: Arc<LevelMeter>, sample_rate: u32, channels: u16) -> Self {
+        LevelAccumulator {
+            meter,
+            window_len: window_len(sample_rate, channels),
+            count: 0,
+            sum_sq: 0.0,
+            peak: 0.0,
+        }
+    }
+
+    /// Follows the format a stream negotiated, which may differ from the one it asked for.
+    pub fn set_format(&mut self, sample_rate: u32, channels: u16) {
+        self.window_len = window_len(sample_rate, channels);
+    }
+
+    #[inline]
+    pub fn push(&mut self, sample: f32) {
+        self.peak = self.peak.max(sample.abs());
+        self.sum_sq += (sample as f64) * (sample as f64);
+        self.count += 1;
+
+        if self.count >= self.window_len {
+            self.meter.publish(Level {
+                peak: self.peak,
+                rms: (self.sum_sq / self.count as f64).sqrt() as f32,
+            });
+            self.count = 0;
+            self.sum_sq = 0.0;
+            self.peak = 0.0;
+        }
+    }
+
+    pub fn push_all(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            self.push(sample);
+        }
+    }
+}
+
+/// Passes a source through unchanged while metering it.
+pub struct Metered<S: Source> {
+    input: S,
+    accumulator: LevelAccumulator,
+}
+
+impl<S: Source> Metered<S> {
+    pub fn new(input: S, meter: Arc<LevelMeter>) -> Self {
+        let accumulator = LevelAccumulator::new(
+            meter,
+            u32::from(input.sample_rate()),
+            u16::from(input.channels()),
+        );
+        Metered { input, accumulator }
+    }
+}
+
+impl<S: Source> Iterator for Metered<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.input.next()?;
+        self.accumulator.push(sample);
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S: Source> Source for Metered<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn test_metered_source_publishes_levels() {
+        let meter = Arc::new(LevelMeter::default());
+        assert_eq!(meter.level(), Level::default());
+
+        let channels: ChannelCount = 1u16.try_into().unwrap();
+        let sample_rate: SampleRate = 1000u32.try_into().unwrap();
+        let samples: Vec<f32> = (0..100)
+            .map(|i| if i % 2 == 0 { 0.5 } else { -0.5 })
+            .collect();
+        let source = Metered::new(
+            SamplesBuffer::new(channels, sample_rate, samples.clone()),
+            meter.clone(),
+        );
+
+        assert_eq!(source.collect::<Vec<_>>(), samples);
+        let level = meter.level();
+        assert_eq!(level.peak, 0.5);
+        assert!((level.rms - 0.5).abs() < 1e-6);
+        assert!(!level.is_clipping());
+    }
+
+    #[test]
+    fn test_level_db() {
+        let level = Level {
+            peak: 1.0,
+            rms: 0.5,
+        };
+        assert_eq!(level.peak_db(), 0.0);
+        assert!((level.rms_db() + 6.02).abs() < 0.01);
+        assert!(level.scaled(2.0).is_clipping());
+    }
+
+    #[test]
+    fn test_accumulator_follows_format() {
+        let meter = Arc::new(LevelMeter::default());
+        let mut accumulator = LevelAccumulator::new(meter.clone(), 48_000, 2);
+        accumulator.set_format(100, 1);
+        accumulator.push_all(&[0.25; 5]);
+        assert_eq!(meter.level().peak, 0.25);
+    }
+}
@@ -1,6 +1,22 @@
 pub mod audio_player;
+pub mod buses;
 pub mod commands;
 pub mod config;
+pub mod doctor;
+pub mod effects;
 pub mod gui;
+pub mod limiter;
+pub mod looping;
+pub mod meter;
 pub mod pipewire;
+pub mod pitch;
+pub mod random;
+pub mod recorder;
+pub mod replay;
+pub mod sample_cache;
 pub mod socket;
+pub mod trim;
+pub mod tts;
+pub mod voice_changer;
+pub mod voices;
+pub mod waveform;
@@ -1,18 +1,259 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
 #[derive(Debug)]
 pub struct Terminate {}
 
-#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelLayout {
+    Mono,
+    #[default]
+    Stereo,
+}
+
+impl ChannelLayout {
+    pub fn channels(&self) -> u32 {
+        match self {
+            ChannelLayout::Mono => 1,
+            ChannelLayout::Stereo => 2,
+        }
+    }
+
+    /// `audio.position` property value
+    pub fn positions(&self) -> &'static str {
+        match self {
+            ChannelLayout::Mono => "[ MONO ]",
+            ChannelLayout::Stereo => "[ FL FR ]",
+        }
+    }
+}
+
+/// Sample rate, buffering and channel layout of the daemon's streams and virtual mic.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub sample_rate: u32,
+    /// Requested quantum in frames, the latency is `quantum / sample_rate`
+    pub quantum: u32,
+    /// Layout of the player stream
+    pub channels: ChannelLayout,
+    /// Layout of the virtual mics and the microphone passthrough, buses can override it
+    pub virtual_mic_channels: ChannelLayout,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            sample_rate: 48_000,
+            quantum: 512,
+            channels: ChannelLayout::Stereo,
+            virtual_mic_channels: ChannelLayout::Stereo,
+        }
+    }
+}
+
+impl AudioSettings {
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate.clamp(8_000, 192_000)
+    }
+
+    pub fn quantum(&self) -> u32 {
+        self.quantum.clamp(32, 8192)
+    }
+
+    /// `node.latency` property value
+    pub fn latency(&self) -> String {
+        format!("{}/{}", self.quantum(), self.sample_rate())
+    }
+
+    pub fn player_format(&self) -> StreamFormat {
+        StreamFormat {
+            rate: self.sample_rate(),
+            channels: self.channels.channels(),
+            quantum: self.quantum(),
+        }
+    }
+
+    pub fn virtual_mic_format(&self) -> StreamFormat {
+        StreamFormat {
+            rate: self.sample_rate(),
+            channels: self.virtual_mic_channels.channels(),
+            quantum: self.quantum(),
+        }
+    }
+}
+
+/// Format of a daemon stream, requested or negotiated.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StreamFormat {
+    pub rate: u32,
+    pub channels: u32,
+    /// Frames per processing cycle
+    pub quantum: u32,
+}
+
+impl StreamFormat {
+    /// `node.latency` property value
+    pub fn latency(&self) -> String {
+        format!("{}/{}", self.quantum, self.rate)
+    }
+}
+
+/// What PipeWire actually negotiated for a stream, written from its callbacks.
+#[derive(Debug, Default)]
+pub struct StreamInfo {
+    rate: AtomicU32,
+    channels: AtomicU32,
+    quantum: AtomicU32,
+}
+
+impl StreamInfo {
+    pub fn set_format(&self, rate: u32, channels: u32) {
+        self.rate.store(rate, Ordering::Relaxed);
+        self.channels.store(channels, Ordering::Relaxed);
+    }
+
+    pub fn set_quantum(&self, quantum: u32) {
+        self.quantum.store(quantum, Ordering::Relaxed);
+    }
+
+    /// `None` until the format has been negotiated
+    pub fn format(&self) -> Option<StreamFormat> {
+        let rate = self.rate.load(Ordering::Relaxed);
+        (rate > 0).then(|| StreamFormat {
+            rate,
+            channels: self.channels.load(Ordering::Relaxed),
+            quantum: self.quantum.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// Reply to `get_audio_info`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AudioInfo {
+    /// As requested in the daemon config
+    pub configured: AudioSettings,
+    /// Player stream, `None` while nothing plays
+    pub player: Option<StreamFormat>,
+    /// Microphone capture feeding the buses, `None` while unlinked
+    pub passthrough: Option<StreamFormat>,
+}
+
+#[derive(Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PortDirection {
+    In,
+    Out,
+}
+
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Port {
+    /// Global id of the port object, what links refer to
+    pub id: u32,
     pub node_id: u32,
+    /// `port.id`, only unique within its node
     pub port_id: u32,
 
     pub name: String,
+    /// Position from `audio.channel`, e.g. FL, MONO or AUX2
+    pub channel: String,
+    pub direction: PortDirection,
 }
 
-#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
+impl Port {
+    /// Channel and direction come from the port name when the properties lack them
+    pub fn new(
+        id: u32,
+        node_id: u32,
+        port_id: u32,
+        name: &str,
+        channel: Option<&str>,
+        direction: Option<&str>,
+    ) -> Self {
+        let channel = channel
+            .or_else(|| name.rsplit_once('_').map(|(_, channel)| channel))
+            .unwrap_or(name);
+        let direction = match direction {
+            Some("in") => PortDirection::In,
+            Some("out") => PortDirection::Out,
+            _ if name.starts_with("input_") || name.starts_with("playback_") => PortDirection::In,
+            _ => PortDirection::Out,
+        };
+
+        Port {
+            id,
+            node_id,
+            port_id,
+            name: name.to_string(),
+            channel: channel.to_string(),
+            direction,
+        }
+    }
+}
+
+/// Pairs output ports with input ports for linking.
+///
+/// Ports are matched by channel position when every input has a counterpart. Otherwise a
+/// single output goes to every input, every output is summed into a single input, or the
+/// ports are paired in order.
+pub fn map_ports(outputs: &[Port], inputs: &[Port]) -> Vec<(Port, Port)> {
+    let by_channel: Option<Vec<(Port, Port)>> = inputs
+        .iter()
+        .map(|input| {
+            outputs
+                .iter()
+                .find(|output| output.channel == input.channel)
+                .map(|output| (output.clone(), input.clone()))
+        })
+        .collect();
+
+    match (by_channel, outputs, inputs) {
+        (Some(pairs), _, _) if !pairs.is_empty() => pairs,
+        (_, [output], _) => inputs
+            .iter()
+            .map(|input| (output.clone(), input.clone()))
+            .collect(),
+        (_, _, [input]) => outputs
+            .iter()
+            .map(|output| (output.clone(), input.clone()))
+            .collect(),
+        _ => outputs
+            .iter()
+            .cloned()
+            .zip(inputs.iter().cloned())
+            .collect(),
+    }
+}
+
+/// The left and right port of a set, the same port twice for mono.
+fn stereo_pair(ports: &[Port]) -> (Option<Port>, Option<Port>) {
+    let find = |channel: &str| ports.iter().find(|port| port.channel == channel).cloned();
+    if let (Some(fl), Some(fr)) = (find("FL"), find("FR")) {
+        return (Some(fl), Some(fr));
+    }
+
+    match ports {
+        [] => (None, None),
+        [port] => (Some(port.clone()), Some(port.clone())),
+        [first, second, ..] => match find("MONO") {
+            Some(mono) => (Some(mono.clone()), Some(mono)),
+            None => (Some(first.clone()), Some(second.clone())),
+        },
+    }
+}
+
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum DeviceType {
     Input,
     Output,
+    /// Capture streams of apps and the daemon
+    Capture,
 }
 
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
@@ -24,10 +265,21 @@ pub struct AudioDevice {
 
     pub device_type: DeviceType,
 
+    /// `object.serial`, unique for the lifetime of the PipeWire instance
+    pub serial: Option<String>,
+    /// `device.bus-path`, stable across reboots for the same port
+    pub bus_path: Option<String>,
+    /// `application.name` and `application.process.binary` of app streams
+    pub app_name: Option<String>,
+    pub app_binary: Option<String>,
+
     pub input_fl: Option<Port>,
     pub input_fr: Option<Port>,
     pub output_fl: Option<Port>,
     pub output_fr: Option<Port>,
+    /// Every port of the node in `port.id` order
+    pub inputs: Vec<Port>,
+    pub outputs: Vec<Port>,
 }
 
 impl AudioDevice {
@@ -47,36 +299,322 @@ impl AudioDevice {
                 .to_string(),
             name: name.unwrap_or_default().to_string(),
             device_type,
+            serial: None,
+            bus_path: None,
+            app_name: None,
+            app_binary: None,
             input_fl: None,
             input_fr: None,
             output_fl: None,
             output_fr: None,
+            inputs: vec![],
+            outputs: vec![],
         }
     }
 
     pub fn add_port(&mut self, port: Port) {
-        match port.name.as_str() {
-            "input_FL" => self.input_fl = Some(port),
-            "input_FR" => self.input_fr = Some(port),
-            "output_FL" | "capture_FL" => self.output_fl = Some(port),
-            "output_FR" | "capture_FR" => self.output_fr = Some(port),
-            "input_MONO" => {
-                self.input_fl = Some(port.clone());
-                self.input_fr = Some(port);
+        let ports = match port.direction {
+            PortDirection::In => &mut self.inputs,
+            PortDirection::Out => &mut self.outputs,
+        };
+        ports.retain(|p| p.id != port.id);
+        ports.push(port);
+        ports.sort_by_key(|p| p.port_id);
+
+        (self.input_fl, self.input_fr) = stereo_pair(&self.inputs);
+        (self.output_fl, self.output_fr) = stereo_pair(&self.outputs);
+    }
+
+    /// Drops the port with the global `id`, the stereo pair is picked again without it.
+    pub fn remove_port(&mut self, id: u32) {
+        self.inputs.retain(|p| p.id != id);
+        self.outputs.retain(|p| p.id != id);
+
+        (self.input_fl, self.input_fr) = stereo_pair(&self.inputs);
+        (self.output_fl, self.output_fr) = stereo_pair(&self.outputs);
+    }
+
+    /// Output ports carrying the given channels in that order, all of them when none are
+    /// given. Channel names are compared ignoring case.
+    pub fn capture_ports(&self, channels: &[String]) -> Result<Vec<Port>, String> {
+        if channels.is_empty() {
+            return Ok(self.outputs.clone());
+        }
+
+        channels
+            .iter()
+            .map(|channel| {
+                self.outputs
+                    .iter()
+                    .find(|port| port.channel.eq_ignore_ascii_case(channel))
+                    .cloned()
+                    .ok_or_else(|| {
+                        let available: Vec<&str> =
+                            self.outputs.iter().map(|p| p.channel.as_str()).collect();
+                        format!(
+                            "{} has no {} channel (available: {})",
+                            self.nick,
+                            channel,
+                            available.join(", ")
+                        )
+                    })
+            })
+            .collect()
+    }
+}
+
+/// How a device is picked: a plain `node.name`, `serial:<object.serial>`,
+/// `bus-path:<device.bus-path>` or `fuzzy:<part of the name or nick>`.
+///
+/// Only names and bus paths survive a PipeWire restart, the other ones are resolved to the
+/// `node.name` they found before being kept, see [`DeviceSelector::is_stable`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceSelector {
+    Name(String),
+    Serial(String),
+    BusPath(String),
+    Fuzzy(String),
+}
+
+impl DeviceSelector {
+    pub fn parse(selector: &str) -> Self {
+        if let Some(serial) = selector.strip_prefix("serial:") {
+            DeviceSelector::Serial(serial.to_string())
+        } else if let Some(bus_path) = selector.strip_prefix("bus-path:") {
+            DeviceSelector::BusPath(bus_path.to_string())
+        } else if let Some(part) = selector.strip_prefix("fuzzy:") {
+            DeviceSelector::Fuzzy(part.to_string())
+        } else {
+            DeviceSelector::Name(selector.to_string())
+        }
+    }
+
+    /// Whether the selector picks the same device after a restart. `object.serial` is
+    /// handed out again by every PipeWire instance, so it can't be stored.
+    pub fn is_stable(&self) -> bool {
+        matches!(self, DeviceSelector::Name(_) | DeviceSelector::BusPath(_))
+    }
+
+    pub fn matches(&self, device: &AudioDevice) -> bool {
+        match self {
+            DeviceSelector::Name(name) => device.name == *name,
+            DeviceSelector::Serial(serial) => device.serial.as_ref() == Some(serial),
+            DeviceSelector::BusPath(bus_path) => device.bus_path.as_ref() == Some(bus_path),
+            DeviceSelector::Fuzzy(part) => {
+                let part = part.to_lowercase();
+                device.name.to_lowercase().contains(&part)
+                    || device.nick.to_lowercase().contains(&part)
             }
-            "output_MONO" | "capture_MONO" => {
-                self.output_fl = Some(port.clone());
-                self.output_fr = Some(port);
+        }
+    }
+
+    /// The only device matching, an error listing the candidates when several do.
+    pub fn select(
+        &self,
+        devices: impl IntoIterator<Item = AudioDevice>,
+    ) -> Result<AudioDevice, String> {
+        let mut candidates: Vec<AudioDevice> =
+            devices.into_iter().filter(|d| self.matches(d)).collect();
+        match candidates.len() {
+            0 => Err(format!("Device {} not found", self)),
+            1 => Ok(candidates.remove(0)),
+            _ => {
+                let names: Vec<String> = candidates
+                    .iter()
+                    .map(|d| format!("{} ({})", d.name, d.nick))
+                    .collect();
+                Err(format!(
+                    "Device {} is ambiguous, candidates: {}",
+                    self,
+                    names.join(", ")
+                ))
             }
-            _ => {}
         }
     }
 }
 
+impl fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceSelector::Name(name) => write!(f, "{}", name),
+            DeviceSelector::Serial(serial) => write!(f, "serial:{}", serial),
+            DeviceSelector::BusPath(bus_path) => write!(f, "bus-path:{}", bus_path),
+            DeviceSelector::Fuzzy(part) => write!(f, "fuzzy:{}", part),
+        }
+    }
+}
+
+/// Reply entry of `get_pw_nodes`, one of the nodes the daemon keeps track of.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PwNode {
+    pub id: u32,
+    pub name: String,
+    pub nick: String,
+    pub kind: DeviceType,
+    pub serial: Option<String>,
+    pub app_name: Option<String>,
+}
+
+impl From<&AudioDevice> for PwNode {
+    fn from(device: &AudioDevice) -> Self {
+        PwNode {
+            id: device.id,
+            name: device.name.clone(),
+            nick: device.nick.clone(),
+            kind: device.device_type.clone(),
+            serial: device.serial.clone(),
+            app_name: device.app_name.clone(),
+        }
+    }
+}
+
+/// Reply entry of `get_pw_links`, any link in the graph.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PwLink {
+    pub id: u32,
+    pub output_node: u32,
+    pub output_port: u32,
+    pub input_node: u32,
+    pub input_port: u32,
+}
+
+/// What the daemon sees of the PipeWire graph.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PwGraph {
+    pub nodes: Vec<PwNode>,
+    pub ports: Vec<Port>,
+    pub links: Vec<PwLink>,
+}
+
+impl PwGraph {
+    pub fn node(&self, name: &str) -> Option<&PwNode> {
+        self.nodes.iter().find(|node| node.name == name)
+    }
+
+    pub fn ports(&self, node_id: u32, direction: PortDirection) -> Vec<&Port> {
+        self.ports
+            .iter()
+            .filter(|port| port.node_id == node_id && port.direction == direction)
+            .collect()
+    }
+
+    pub fn links_between(&self, output_node: u32, input_node: u32) -> usize {
+        self.links
+            .iter()
+            .filter(|link| link.output_node == output_node && link.input_node == input_node)
+            .count()
+    }
+}
+
+/// Whether a route target picks an app stream, by `node.name`, application name or binary.
+/// Application names are compared ignoring case.
+pub fn route_matches(target: &str, stream: &AudioDevice) -> bool {
+    stream.name == target
+        || stream
+            .app_name
+            .as_ref()
+            .is_some_and(|name| name.eq_ignore_ascii_case(target))
+        || stream.app_binary.as_deref() == Some(target)
+}
+
+/// Reply entry of `get_app_streams`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AppStream {
+    pub id: u32,
+    /// `node.name` of the capture stream
+    pub name: String,
+    pub app_name: Option<String>,
+    pub app_binary: Option<String>,
+    /// Matches one of the route targets
+    pub targeted: bool,
+    /// The player is linked into it, only while sounds play
+    pub linked: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceEventKind {
+    Added,
+    Removed,
+}
+
+/// An audio source appearing or disappearing in PipeWire
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DeviceEvent {
+    /// Increases by one with every event, starting at 1
+    pub seq: u64,
+    pub kind: DeviceEventKind,
+    pub name: String,
+    pub nick: String,
+    pub serial: Option<String>,
+}
+
+/// The latest device events, kept for clients catching up between requests.
+#[derive(Debug, Default)]
+pub struct DeviceEventLog {
+    events: VecDeque<DeviceEvent>,
+    last_seq: u64,
+}
+
+impl DeviceEventLog {
+    pub const CAPACITY: usize = 64;
+
+    pub fn push(&mut self, kind: DeviceEventKind, device: &AudioDevice) -> DeviceEvent {
+        self.last_seq += 1;
+        let event = DeviceEvent {
+            seq: self.last_seq,
+            kind,
+            name: device.name.clone(),
+            nick: device.nick.clone(),
+            serial: device.serial.clone(),
+        };
+        if self.events.len() == Self::CAPACITY {
+            self.events.pop_front();
+        }
+        self.events.push_back(event.clone());
+        event
+    }
+
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    /// Events newer than `seq`, oldest first
+    pub fn since(&self, seq: u64) -> Vec<DeviceEvent> {
+        self.events
+            .iter()
+            .filter(|event| event.seq > seq)
+            .cloned()
+            .collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_audio_settings() {
+        let settings = AudioSettings {
+            sample_rate: 44_100,
+            quantum: 1,
+            virtual_mic_channels: ChannelLayout::Mono,
+            ..Default::default()
+        };
+        assert_eq!(settings.latency(), "32/44100");
+        assert_eq!(settings.player_format().channels, 2);
+        assert_eq!(settings.virtual_mic_format().channels, 1);
+
+        let info = StreamInfo::default();
+        assert_eq!(info.format(), None);
+        info.set_format(48_000, 2);
+        info.set_quantum(256);
+        assert_eq!(
+            info.format().map(|f| f.latency()),
+            Some("256/48000".to_string())
+        );
+    }
+
     #[test]
     fn test_audio_device_new() {
         let device = AudioDevice::new(
@@ -104,16 +642,8 @@ mod tests {
     fn test_audio_device_add_port() {
         let mut device = AudioDevice::new(1, None, None, Some("device-name"), DeviceType::Input);
 
-        let port_fl = Port {
-            node_id: 1,
-            port_id: 10,
-            name: "input_FL".to_string(),
-        };
-        let port_fr = Port {
-            node_id: 1,
-            port_id: 11,
-            name: "input_FR".to_string(),
-        };
+        let port_fl = Port::new(110, 1, 10, "input_FL", None, None);
+        let port_fr = Port::new(111, 1, 11, "input_FR", None, None);
 
         device.add_port(port_fl.clone());
         device.add_port(port_fr.clone());
@@ -122,16 +652,8 @@ mod tests {
         assert_eq!(device.input_fr, Some(port_fr));
 
         // Test output ports
-        let port_out_fl = Port {
-            node_id: 1,
-            port_id: 12,
-            name: "output_FL".to_string(),
-        };
-        let port_out_fr = Port {
-            node_id: 1,
-            port_id: 13,
-            name: "capture_FR".to_string(),
-        };
+        let port_out_fl = Port::new(112, 1, 12, "output_FL", None, None);
+        let port_out_fr = Port::new(113, 1, 13, "capture_FR", None, None);
 
         device.add_port(port_out_fl.clone());
         device.add_port(port_out_fr.clone());
@@ -142,14 +664,159 @@ mod tests {
         // Test MONO ports
         let mut device_mono =
             AudioDevice::new(2, None, None, Some("mono-device"), DeviceType::Input);
-        let port_mono = Port {
-            node_id: 2,
-            port_id: 20,
-            name: "input_MONO".to_string(),
-        };
+        let port_mono = Port::new(220, 2, 20, "input_MONO", None, None);
         device_mono.add_port(port_mono.clone());
 
         assert_eq!(device_mono.input_fl, Some(port_mono.clone()));
         assert_eq!(device_mono.input_fr, Some(port_mono));
     }
+
+    #[test]
+    fn test_multichannel_ports() {
+        // A four channel interface without FL/FR
+        let mut device = AudioDevice::new(4, None, None, Some("interface"), DeviceType::Input);
+        for (id, channel) in ["AUX0", "AUX1", "AUX2", "AUX3"].into_iter().enumerate() {
+            device.add_port(Port::new(
+                400 + id as u32,
+                4,
+                id as u32,
+                &format!("capture_{}", channel),
+                Some(channel),
+                Some("out"),
+            ));
+        }
+        assert_eq!(device.output_fl.as_ref().map(|p| p.port_id), Some(0));
+        assert_eq!(device.output_fr.as_ref().map(|p| p.port_id), Some(1));
+
+        let aux2 = device.capture_ports(&["aux2".to_string()]).unwrap();
+        assert_eq!(aux2.len(), 1);
+        assert_eq!(aux2[0].port_id, 2);
+        let err = device.capture_ports(&["FL".to_string()]).unwrap_err();
+        assert!(err.contains("AUX0, AUX1, AUX2, AUX3"));
+
+        // One picked channel feeds both sides of a stereo capture
+        let stereo = [
+            Port::new(900, 9, 0, "input_FL", None, None),
+            Port::new(901, 9, 1, "input_FR", None, None),
+        ];
+        let pairs = map_ports(&aux2, &stereo);
+        assert_eq!(pairs.len(), 2);
+        assert!(pairs.iter().all(|(output, _)| output.channel == "AUX2"));
+
+        // Stereo into a mono virtual mic is summed, positions match when they can
+        let mono = [Port::new(700, 7, 0, "input_MONO", None, None)];
+        let player = [
+            Port::new(800, 8, 0, "output_FL", None, None),
+            Port::new(801, 8, 1, "output_FR", None, None),
+        ];
+        assert_eq!(map_ports(&player, &mono).len(), 2);
+        let pairs = map_ports(&player, &stereo);
+        assert!(pairs.iter().all(|(o, i)| o.channel == i.channel));
+    }
+
+    #[test]
+    fn test_device_selector() {
+        let mut usb = AudioDevice::new(
+            1,
+            Some("USB Mic"),
+            None,
+            Some("alsa_input.usb-mic"),
+            DeviceType::Input,
+        );
+        usb.serial = Some("51".to_string());
+        usb.bus_path = Some("pci-0000:00:14.0-usb-0:2:1.0".to_string());
+        let mut headset = AudioDevice::new(
+            2,
+            Some("USB Headset"),
+            None,
+            Some("alsa_input.usb-headset"),
+            DeviceType::Input,
+        );
+        headset.serial = Some("52".to_string());
+        let devices = vec![usb.clone(), headset.clone()];
+
+        // Plain names are exact
+        let exact = DeviceSelector::parse("alsa_input.usb-mic");
+        assert_eq!(exact.select(devices.clone()).unwrap().id, 1);
+        assert!(
+            DeviceSelector::parse("USB")
+                .select(devices.clone())
+                .is_err()
+        );
+
+        assert_eq!(
+            DeviceSelector::parse("serial:52")
+                .select(devices.clone())
+                .unwrap()
+                .id,
+            2
+        );
+        let bus_path = DeviceSelector::parse("bus-path:pci-0000:00:14.0-usb-0:2:1.0");
+        assert_eq!(bus_path.select(devices.clone()).unwrap().id, 1);
+        assert_eq!(
+            bus_path.to_string(),
+            "bus-path:pci-0000:00:14.0-usb-0:2:1.0"
+        );
+
+        assert!(exact.is_stable() && bus_path.is_stable());
+        assert!(!DeviceSelector::parse("serial:52").is_stable());
+
+        let fuzzy = DeviceSelector::parse("fuzzy:usb");
+        assert!(!fuzzy.is_stable());
+        let err = fuzzy.select(devices.clone()).unwrap_err();
+        assert!(err.contains("alsa_input.usb-mic (USB Mic)"));
+        assert!(err.contains("alsa_input.usb-headset (USB Headset)"));
+        assert_eq!(
+            DeviceSelector::parse("fuzzy:headset")
+                .select(devices)
+                .unwrap()
+                .id,
+            2
+        );
+    }
+
+    #[test]
+    fn test_device_event_log() {
+        let mut log = DeviceEventLog::default();
+        let mut mic = AudioDevice::new(
+            1,
+            Some("Mic"),
+            None,
+            Some("alsa_input.mic"),
+            DeviceType::Input,
+        );
+        mic.serial = Some("40".to_string());
+
+        assert!(log.since(0).is_empty());
+        assert_eq!(log.push(DeviceEventKind::Added, &mic).seq, 1);
+        let removed = log.push(DeviceEventKind::Removed, &mic);
+        assert_eq!(removed.seq, 2);
+        assert_eq!(removed.serial.as_deref(), Some("40"));
+        assert_eq!(log.since(1), vec![removed]);
+
+        for _ in 0..DeviceEventLog::CAPACITY {
+            log.push(DeviceEventKind::Added, &mic);
+        }
+        let events = log.since(0);
+        assert_eq!(events.len(), DeviceEventLog::CAPACITY);
+        assert_eq!(events[0].seq, 3);
+        assert_eq!(log.last_seq(), 2 + DeviceEventLog::CAPACITY as u64);
+    }
+
+    #[test]
+    fn test_route_matches() {
+        let mut discord =
+            AudioDevice::new(30, None, None, Some("Discord-input"), DeviceType::Capture);
+        discord.app_name = Some("Discord".to_string());
+        discord.app_binary = Some("Discord".to_string());
+        let mut obs = AudioDevice::new(31, None, None, Some("OBS-mic"), DeviceType::Capture);
+        obs.app_binary = Some("obs".to_string());
+
+        assert!(route_matches("discord", &discord));
+        assert!(route_matches("Discord-input", &discord));
+        assert!(!route_matches("discord-input", &discord));
+        assert!(route_matches("obs", &obs));
+        assert!(!route_matches("OBS", &obs));
+        assert!(!route_matches("obs", &discord));
+    }
 }
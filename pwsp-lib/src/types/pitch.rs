@@ -0,0 +1,253 @@
+use rodio::{ChannelCount, SampleRate, Source, source::SeekError};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::Duration,
+};
+
+/// Pitch shift is limited to two octaves either way
+pub const MAX_PITCH_SEMITONES: f32 = 24.0;
+
+/// Length of the crossfaded grains
+const WINDOW: Duration = Duration::from_millis(40);
+
+pub fn semitones_to_ratio(semitones: f32) -> f32 {
+    2f32.powf(semitones / 12.0)
+}
+
+/// Pitch of a playing track, changed by the daemon and read by the audio thread.
+#[derive(Debug, Default)]
+pub struct PitchControl {
+    semitones: AtomicU32,
+}
+
+impl PitchControl {
+    pub fn semitones(&self) -> f32 {
+        f32::from_bits(self.semitones.load(Ordering::Relaxed))
+    }
+
+    pub fn set_semitones(&self, semitones: f32) {
+        let semitones = semitones.clamp(-MAX_PITCH_SEMITONES, MAX_PITCH_SEMITONES);
+        self.semitones.store(semitones.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Delay line behind [`PitchShifted`], works on interleaved frames.
+///
+/// Two read heads sweep a short delay line at the pitch ratio and are crossfaded with
+/// triangular windows, so their gains always add up to one. Cheap and artifact-prone
+/// on sustained tones, which is fine for soundboard effects.
+#[derive(Debug)]
+pub(crate) struct PitchShifter {
+    channels: usize,
+    window: f32,
+    /// Interleaved ring of past frames
+    history: Vec<f32>,
+    history_frames: usize,
+    write_frame: usize,
+    /// Delay of the first read head in frames, 0..window
+    phase: f32,
+}
+
+impl PitchShifter {
+    pub(crate) fn new(sample_rate: f32, channels: usize) -> Self {
+        let channels = channels.max(1);
+        let window = (sample_rate * WINDOW.as_secs_f32()).max(4.0);
+        let history_frames = window as usize + 4;
+
+        PitchShifter {
+            channels,
+            window,
+            history: vec![0.0; history_frames * channels],
+            history_frames,
+            write_frame: 0,
+            phase: 0.0,
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.history.fill(0.0);
+        self.phase = 0.0;
+    }
+
+    /// Linearly interpolated sample `delay` frames behind the newest one
+    fn delayed(&self, channel: usize, delay: f32) -> f32 {
+        let back = delay.floor() as usize;
+        let fraction = delay - back as f32;
+        let at = |back: usize| {
+            let frame = (self.write_frame + self.history_frames - back) % self.history_frames;
+            self.history[frame * self.channels + channel]
+        };
+        at(back) * (1.0 - fraction) + at(back + 1) * fraction
+    }
+
+    fn gain(&self, delay: f32) -> f32 {
+        1.0 - (2.0 * delay / self.window - 1.0).abs()
+    }
+
+    /// Shifts one frame in place, `frame` must hold `channels` samples
+    pub(crate) fn process(&mut self, frame: &mut [f32], semitones: f32) {
+        self.write_frame = (self.write_frame + 1) % self.history_frames;
+        let offset = self.write_frame * self.channels;
+        self.history[offset..offset + self.channels].copy_from_slice(frame);
+
+        if semitones == 0.0 {
+            self.phase = 0.0;
+            return;
+        }
+
+        self.phase = (self.phase + 1.0 - semitones_to_ratio(semitones)).rem_euclid(self.window);
+        let first = self.phase;
+        let second = (self.phase + self.window / 2.0) % self.window;
+        let (first_gain, second_gain) = (self.gain(first), self.gain(second));
+
+        for (channel, sample) in frame.iter_mut().enumerate() {
+            *sample = self.delayed(channel, first) * first_gain
+                + self.delayed(channel, second) * second_gain;
+        }
+    }
+}
+
+/// Shifts pitch without changing tempo, see [`PitchShifter`].
+pub struct PitchShifted<S: Source> {
+    input: S,
+    control: Arc<PitchControl>,
+    channels: usize,
+    shifter: PitchShifter,
+    frame: Vec<f32>,
+    frame_pos: usize,
+}
+
+impl<S: Source> PitchShifted<S> {
+    pub fn new(input: S, control: Arc<PitchControl>) -> Self {
+        let channels = (u16::from(input.channels()) as usize).max(1);
+        let sample_rate = u32::from(input.sample_rate()) as f32;
+
+        PitchShifted {
+            input,
+            control,
+            channels,
+            shifter: PitchShifter::new(sample_rate, channels),
+            frame: Vec::with_capacity(channels),
+            frame_pos: 0,
+        }
+    }
+
+    fn process_frame(&mut self) -> bool {
+        self.frame.clear();
+        for _ in 0..self.channels {
+            match self.input.next() {
+                Some(sample) => self.frame.push(sample),
+                None => break,
+            }
+        }
+        if self.frame.is_empty() {
+            return false;
+        }
+        self.frame.resize(self.channels, 0.0);
+
+        self.shifter
+            .process(&mut self.frame, self.control.semitones());
+        true
+    }
+}
+
+impl<S: Source> Iterator for PitchShifted<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.frame_pos >= self.frame.len() {
+            if !self.process_frame() {
+                return None;
+            }
+            self.frame_pos = 0;
+        }
+
+        let sample = self.frame[self.frame_pos];
+        self.frame_pos += 1;
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S: Source> Source for PitchShifted<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.shifter.reset();
+        self.frame.clear();
+        self.frame_pos = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn sine(frequency: f32, len: usize) -> SamplesBuffer {
+        let channels: ChannelCount = 1u16.try_into().unwrap();
+        let sample_rate: SampleRate = 8000u32.try_into().unwrap();
+        let samples = (0..len)
+            .map(|i| (i as f32 * frequency * std::f32::consts::TAU / 8000.0).sin())
+            .collect::<Vec<_>>();
+        SamplesBuffer::new(channels, sample_rate, samples)
+    }
+
+    fn zero_crossings(samples: &[f32]) -> usize {
+        samples
+            .windows(2)
+            .filter(|w| (w[0] < 0.0) != (w[1] < 0.0))
+            .count()
+    }
+
+    #[test]
+    fn test_no_shift_is_transparent() {
+        let control = Arc::new(PitchControl::default());
+        let input: Vec<f32> = sine(440.0, 800).collect();
+        let output: Vec<f32> = PitchShifted::new(sine(440.0, 800), control).collect();
+        assert_eq!(input, output);
+    }
+
+    #[test]
+    fn test_octave_up_keeps_length_and_doubles_frequency() {
+        let control = Arc::new(PitchControl::default());
+        control.set_semitones(12.0);
+
+        let output: Vec<f32> = PitchShifted::new(sine(200.0, 8000), control).collect();
+        assert_eq!(output.len(), 8000);
+
+        // Skip the first window while the delay line fills up
+        let crossings = zero_crossings(&output[800..]) as f32;
+        let expected = zero_crossings(&sine(400.0, 7200).collect::<Vec<_>>()) as f32;
+        assert!((crossings / expected - 1.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_pitch_is_clamped() {
+        let control = PitchControl::default();
+        control.set_semitones(100.0);
+        assert_eq!(control.semitones(), MAX_PITCH_SEMITONES);
+    }
+}
@@ -0,0 +1,238 @@
+use crate::types::{audio_player::SUPPORTED_EXTENSIONS, config::SoundConfig};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    hash::{BuildHasher, Hasher, RandomState},
+    path::PathBuf,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RandomMode {
+    /// Every pick is independent, repeats are possible
+    #[default]
+    Uniform,
+    /// Plays every sound once in random order before reshuffling
+    Shuffle,
+    /// Plays sounds one after another in a stable order
+    RoundRobin,
+}
+
+impl RandomMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RandomMode::Uniform => "uniform",
+            RandomMode::Shuffle => "shuffle",
+            RandomMode::RoundRobin => "round_robin",
+        }
+    }
+}
+
+impl FromStr for RandomMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "uniform" | "random" => Ok(RandomMode::Uniform),
+            "shuffle" => Ok(RandomMode::Shuffle),
+            "round_robin" | "roundrobin" => Ok(RandomMode::RoundRobin),
+            other => Err(format!("Unknown random mode: {}", other)),
+        }
+    }
+}
+
+/// Where `play_random` takes its candidates from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RandomSource {
+    Dir(PathBuf),
+    Files(Vec<PathBuf>),
+    Tag(String),
+}
+
+impl RandomSource {
+    /// Key under which the shuffle/round-robin state of this source is kept.
+    pub fn key(&self) -> String {
+        match self {
+            RandomSource::Dir(dir) => format!("dir:{}", dir.display()),
+            RandomSource::Files(files) => format!(
+                "files:{}",
+                files
+                    .iter()
+                    .map(|f| f.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(";")
+            ),
+            RandomSource::Tag(tag) => format!("tag:{}", tag),
+        }
+    }
+
+    /// Lists the files this source currently points to, sorted by path.
+    pub fn resolve(&self) -> Result<Vec<PathBuf>> {
+        let mut files = match self {
+            RandomSource::Dir(dir) => {
+                let mut files = vec![];
+                for entry in fs::read_dir(dir)?.filter_map(|e| e.ok()) {
+                    let path = entry.path();
+                    let extension = path
+                        .extension()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_lowercase();
+                    if path.is_file() && SUPPORTED_EXTENSIONS.contains(&extension.as_str()) {
+                        files.push(path);
+                    }
+                }
+                files
+            }
+            RandomSource::Files(files) => files.clone(),
+            RandomSource::Tag(tag) => SoundConfig::load()?.files_with_tag(tag),
+        };
+
+        if files.is_empty() {
+            return Err(anyhow!("No sounds found"));
+        }
+
+        files.sort();
+        files.dedup();
+        Ok(files)
+    }
+}
+
+pub fn random_u64() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default();
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(nanos);
+    hasher.finish()
+}
+
+fn random_index(len: usize) -> usize {
+    (random_u64() % len as u64) as usize
+}
+
+#[derive(Default, Debug)]
+struct PoolState {
+    candidates: Vec<PathBuf>,
+    queue: Vec<PathBuf>,
+    next: usize,
+    last: Option<PathBuf>,
+}
+
+/// Keeps per-source state so shuffle and round-robin survive between requests.
+#[derive(Default, Debug)]
+pub struct RandomPicker {
+    pools: HashMap<String, PoolState>,
+}
+
+impl RandomPicker {
+    pub fn pick(
+        &mut self,
+        key: &str,
+        candidates: Vec<PathBuf>,
+        mode: RandomMode,
+    ) -> Option<PathBuf> {
+        if candidates.is_empty() {
+            return None;
+        }
+
+        let pool = self.pools.entry(key.to_string()).or_default();
+        if pool.candidates != candidates {
+            pool.queue.clear();
+            pool.next = 0;
+            pool.candidates = candidates;
+        }
+
+        let len = pool.candidates.len();
+        let picked = match mode {
+            RandomMode::Uniform => pool.candidates[random_index(len)].clone(),
+            RandomMode::Shuffle => {
+                if pool.queue.is_empty() {
+                    pool.queue = pool.candidates.clone();
+                    for i in (1..len).rev() {
+                        pool.queue.swap(i, random_index(i + 1));
+                    }
+                    // Avoid playing the same sound twice across a reshuffle
+                    if len > 1 && pool.queue.last() == pool.last.as_ref() {
+                        pool.queue.swap(0, len - 1);
+                    }
+                }
+                pool.queue.pop()?
+            }
+            RandomMode::RoundRobin => {
+                let index = pool.next % len;
+                pool.next = index + 1;
+                pool.candidates[index].clone()
+            }
+        };
+
+        pool.last = Some(picked.clone());
+        Some(picked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn files(n: usize) -> Vec<PathBuf> {
+        (0..n)
+            .map(|i| PathBuf::from(format!("{}.mp3", i)))
+            .collect()
+    }
+
+    #[test]
+    fn test_random_mode_from_str() {
+        assert_eq!("uniform".parse(), Ok(RandomMode::Uniform));
+        assert_eq!("Shuffle".parse(), Ok(RandomMode::Shuffle));
+        assert_eq!("round-robin".parse(), Ok(RandomMode::RoundRobin));
+        assert!("sometimes".parse::<RandomMode>().is_err());
+    }
+
+    #[test]
+    fn test_round_robin_cycles_in_order() {
+        let mut picker = RandomPicker::default();
+        let candidates = files(3);
+        let picked: Vec<_> = (0..4)
+            .filter_map(|_| picker.pick("k", candidates.clone(), RandomMode::RoundRobin))
+            .collect();
+        assert_eq!(
+            picked,
+            vec![
+                candidates[0].clone(),
+                candidates[1].clone(),
+                candidates[2].clone(),
+                candidates[0].clone()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shuffle_plays_everything_before_repeating() {
+        let mut picker = RandomPicker::default();
+        let candidates = files(5);
+
+        let mut previous = None;
+        for _ in 0..4 {
+            let round: Vec<_> = (0..5)
+                .filter_map(|_| picker.pick("k", candidates.clone(), RandomMode::Shuffle))
+                .collect();
+            let unique: HashSet<_> = round.iter().collect();
+            assert_eq!(unique.len(), 5);
+            assert_ne!(previous.as_ref(), round.first());
+            previous = round.last().cloned();
+        }
+    }
+
+    #[test]
+    fn test_pick_from_empty_pool() {
+        let mut picker = RandomPicker::default();
+        assert!(picker.pick("k", vec![], RandomMode::Uniform).is_none());
+    }
+}
@@ -0,0 +1,726 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU8, AtomicU64, AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread::JoinHandle,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Samples held for the writer before new ones are dropped, about 10 s of 48 kHz stereo
+const MAX_QUEUED_SAMPLES: usize = 1 << 20;
+/// How often the writer empties the tap
+const WRITE_INTERVAL: Duration = Duration::from_millis(50);
+const WAV_HEADER_LEN: u32 = 44;
+/// Most sample bytes a WAV file holds, its sizes are 32 bit. About 6 hours of 48 kHz stereo.
+const MAX_WAV_DATA_LEN: u32 = u32::MAX - (WAV_HEADER_LEN - 8);
+
+/// What gets recorded.
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordSource {
+    /// Everything a bus sends to its virtual mic, sounds and microphone
+    #[default]
+    Mix,
+    /// The microphone before the voice changer
+    Mic,
+    /// Only the sounds played on a bus
+    Sounds,
+}
+
+impl RecordSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecordSource::Mix => "mix",
+            RecordSource::Mic => "mic",
+            RecordSource::Sounds => "sounds",
+        }
+    }
+}
+
+impl FromStr for RecordSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "mix" | "virtual_mic" => Ok(RecordSource::Mix),
+            "mic" | "raw_mic" => Ok(RecordSource::Mic),
+            "sounds" | "soundboard" => Ok(RecordSource::Sounds),
+            other => Err(format!("Unknown record source: {}", other)),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordFormat {
+    #[default]
+    Wav,
+    Flac,
+    Ogg,
+}
+
+impl RecordFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RecordFormat::Wav => "wav",
+            RecordFormat::Flac => "flac",
+            RecordFormat::Ogg => "ogg",
+        }
+    }
+
+    /// Programs the encoders run, ffmpeg is the fallback of every format
+    fn encoder_programs(&self) -> &'static [&'static str] {
+        match self {
+            RecordFormat::Wav => &[],
+            RecordFormat::Flac => &["flac", "ffmpeg"],
+            RecordFormat::Ogg => &["oggenc", "ffmpeg"],
+        }
+    }
+
+    /// Whether clips can be saved in this format, WAV always can. The others need one of
+    /// their encoders on the `PATH`.
+    pub fn is_available(&self) -> bool {
+        *self == RecordFormat::Wav
+            || self
+                .encoder_programs()
+                .iter()
+                .any(|program| find_program(program))
+    }
+
+    fn missing_encoder(&self) -> String {
+        format!(
+            "No {} encoder found, install {} or ffmpeg",
+            self.extension(),
+            self.encoder_programs().first().unwrap_or(&"one")
+        )
+    }
+
+    /// Encoders tried in order, each reading the finished WAV
+    fn encoders(&self, wav: &Path, output: &Path) -> Vec<Command> {
+        let mut encoders = vec![];
+        let muxer = match self {
+            RecordFormat::Wav => return encoders,
+            RecordFormat::Flac => {
+                let mut flac = Command::new("flac");
+                flac.args(["--silent", "--force", "-o"])
+                    .arg(output)
+                    .arg(wav);
+                encoders.push(flac);
+                "flac"
+            }
+            RecordFormat::Ogg => {
+                let mut oggenc = Command::new("oggenc");
+                oggenc.args(["--quiet", "-o"]).arg(output).arg(wav);
+                encoders.push(oggenc);
+                "ogg"
+            }
+        };
+        // The output is a .part file, so ffmpeg can't guess the format from its name
+        let mut ffmpeg = Command::new("ffmpeg");
+        ffmpeg
+            .args(["-y", "-loglevel", "error", "-i"])
+            .arg(wav)
+            .args(["-f", muxer])
+            .arg(output);
+        encoders.push(ffmpeg);
+        encoders
+    }
+}
+
+impl FromStr for RecordFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "wav" => Ok(RecordFormat::Wav),
+            "flac" => Ok(RecordFormat::Flac),
+            "ogg" | "vorbis" => Ok(RecordFormat::Ogg),
+            other => Err(format!("Unknown record format: {}", other)),
+        }
+    }
+}
+
+/// Starts a new clip whenever the recording stays below `threshold_db` for `min_silence_ms`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SilenceSplit {
+    /// Anything quieter than this is considered silence, in dBFS
+    pub threshold_db: f32,
+    pub min_silence_ms: u32,
+}
+
+impl Default for SilenceSplit {
+    fn default() -> Self {
+        SilenceSplit {
+            threshold_db: -50.0,
+            min_silence_ms: 1500,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitAction {
+    Write,
+    /// Silence between clips, dropped
+    Skip,
+    /// The silence lasted long enough, the current clip ends before this frame
+    Cut,
+}
+
+/// Decides frame by frame where clips start and end. Leading silence is dropped, the silence
+/// after a clip is kept up to `min_silence_ms` so it does not end abruptly.
+#[derive(Debug)]
+pub struct SilenceSplitter {
+    threshold: f32,
+    min_silence_frames: u64,
+    silent_frames: u64,
+    in_clip: bool,
+}
+
+impl SilenceSplitter {
+    pub fn new(split: SilenceSplit, sample_rate: u32) -> Self {
+        SilenceSplitter {
+            threshold: 10f32.powf(split.threshold_db / 20.0),
+            min_silence_frames: (split.min_silence_ms as u64 * sample_rate as u64 / 1000).max(1),
+            silent_frames: 0,
+            in_clip: false,
+        }
+    }
+
+    pub fn frame(&mut self, frame: &[f32]) -> SplitAction {
+        if frame.iter().any(|sample| sample.abs() > self.threshold) {
+            self.silent_frames = 0;
+            self.in_clip = true;
+            return SplitAction::Write;
+        }
+        if !self.in_clip {
+            return SplitAction::Skip;
+        }
+
+        self.silent_frames += 1;
+        if self.silent_frames >= self.min_silence_frames {
+            self.silent_frames = 0;
+            self.in_clip = false;
+            return SplitAction::Cut;
+        }
+        SplitAction::Write
+    }
+}
+
+/// Samples the audio threads hand to the recording writer.
+///
+/// Nothing is queued unless the tap is armed for a source, the bus index only matters for
+/// the sources that come out of a bus.
+#[derive(Debug)]
+pub struct RecordTap {
+    /// 0 while nothing records, the source otherwise
+    source: AtomicU8,
+    bus: AtomicUsize,
+    queue: Mutex<VecDeque<f32>>,
+}
+
+impl Default for RecordTap {
+    fn default() -> Self {
+        RecordTap {
+            source: AtomicU8::new(0),
+            bus: AtomicUsize::new(0),
+            queue: Mutex::new(VecDeque::new()),
+        }
+    }
+}
+
+impl RecordTap {
+    fn code(source: RecordSource) -> u8 {
+        match source {
+            RecordSource::Mix => 1,
+            RecordSource::Mic => 2,
+            RecordSource::Sounds => 3,
+        }
+    }
+
+    pub fn arm(&self, source: RecordSource, bus: usize) {
+        self.lock().clear();
+        self.bus.store(bus, Ordering::Relaxed);
+        self.source.store(Self::code(source), Ordering::Release);
+    }
+
+    pub fn disarm(&self) {
+        self.source.store(0, Ordering::Release);
+    }
+
+    pub fn wants(&self, source: RecordSource, bus: usize) -> bool {
+        self.source.load(Ordering::Acquire) == Self::code(source)
+            && (source == RecordSource::Mic || self.bus.load(Ordering::Relaxed) == bus)
+    }
+
+    /// Takes whole buffers only, so the queue stays aligned to frames. A buffer that does not
+    /// fit is dropped.
+    pub fn push(&self, samples: &[f32]) {
+        let mut queue = self.lock();
+        if queue.len() + samples.len() <= MAX_QUEUED_SAMPLES {
+            queue.extend(samples);
+        }
+    }
+
+    pub fn take(&self, into: &mut Vec<f32>) {
+        into.extend(self.lock().drain(..));
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<f32>> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 16-bit PCM WAV file, the sizes in the header are filled in by `finish`. It is written
+/// next to `path` with `.part` added and only renamed to `path` once finished.
+pub struct WavWriter {
+    file: BufWriter<File>,
+    path: PathBuf,
+    data_len: u32,
+    max_data_len: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path, channels: u16, sample_rate: u32) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(part_path(path))?);
+        let block_align = channels * 2;
+        file.write_all(b"RIFF")?;
+        file.write_all(&(WAV_HEADER_LEN - 8).to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&channels.to_le_bytes())?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        file.write_all(&block_align.to_le_bytes())?;
+        file.write_all(&16u16.to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&0u32.to_le_bytes())?;
+
+        Ok(WavWriter {
+            file,
+            path: path.to_path_buf(),
+            data_len: 0,
+            max_data_len: MAX_WAV_DATA_LEN,
+        })
+    }
+
+    /// Whether `samples` more still fit, the file has to be finished before its sizes overflow.
+    pub fn has_room(&self, samples: usize) -> bool {
+        samples as u64 * 2 <= u64::from(self.max_data_len - self.data_len)
+    }
+
+    pub fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        if !self.has_room(samples.len()) {
+            return Err(io::Error::other("WAV file is full"));
+        }
+        for sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+            self.file.write_all(&value.to_le_bytes())?;
+        }
+        self.data_len += samples.len() as u32 * 2;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<PathBuf> {
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file
+            .write_all(&(WAV_HEADER_LEN - 8 + self.data_len).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&self.data_len.to_le_bytes())?;
+        self.file.flush()?;
+        drop(self.file);
+        fs::rename(part_path(&self.path), &self.path)?;
+        Ok(self.path)
+    }
+}
+
+/// Name a file is written under until it is complete, so the library never lists half of it.
+fn part_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".part");
+    PathBuf::from(name)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordOptions {
+    pub source: RecordSource,
+    /// Bus to record the mix or sounds of, the first one when unset
+    pub bus: Option<String>,
+    pub format: RecordFormat,
+    /// Splits the recording into clips on silence when set
+    pub split: Option<SilenceSplit>,
+}
+
+/// Clips a finished recording left in the library.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecordingSummary {
+    pub clips: Vec<PathBuf>,
+    /// Clips that were kept as WAV because no encoder worked, and similar problems
+    pub warnings: Vec<String>,
+}
+
+/// A running recording, the writer thread drains the tap into clips.
+pub struct Recording {
+    pub options: RecordOptions,
+    tap: Arc<RecordTap>,
+    stop: Arc<AtomicBool>,
+    writer: JoinHandle<Result<RecordingSummary>>,
+}
+
+struct ClipWriter {
+    dir: PathBuf,
+    prefix: &'static str,
+    format: RecordFormat,
+    channels: u16,
+    sample_rate: u32,
+    current: Option<WavWriter>,
+    /// Finished WAVs, encoded on their own thread so encoding never holds up the tap
+    encoder: mpsc::Sender<PathBuf>,
+}
+
+impl ClipWriter {
+    /// Drains `tap` into clips until `stop` is set, then cuts the last one.
+    fn record(
+        &mut self,
+        tap: &RecordTap,
+        stop: &AtomicBool,
+        mut splitter: Option<SilenceSplitter>,
+    ) -> Result<()> {
+        let mut samples = vec![];
+        loop {
+            let stopping = stop.load(Ordering::Acquire);
+            samples.clear();
+            tap.take(&mut samples);
+            for frame in samples.chunks_exact(self.channels as usize) {
+                match splitter.as_mut().map(|splitter| splitter.frame(frame)) {
+                    None | Some(SplitAction::Write) => self.write(frame)?,
+                    Some(SplitAction::Skip) => {}
+                    Some(SplitAction::Cut) => self.cut()?,
+                }
+            }
+            if stopping {
+                break;
+            }
+            std::thread::sleep(WRITE_INTERVAL);
+        }
+        self.cut()
+    }
+
+    fn write(&mut self, frame: &[f32]) -> Result<()> {
+        // A WAV can't grow past 4 GiB, long recordings go on in the next clip
+        if self
+            .current
+            .as_ref()
+            .is_some_and(|current| !current.has_room(frame.len()))
+        {
+            self.cut()?;
+        }
+        if self.current.is_none() {
+            let path = clip_path(&self.dir, self.prefix, self.format, SystemTime::now());
+            self.current = Some(WavWriter::create(&path, self.channels, self.sample_rate)?);
+        }
+        if let Some(current) = &mut self.current {
+            current.write(frame)?;
+        }
+        Ok(())
+    }
+
+    fn cut(&mut self) -> Result<()> {
+        let Some(current) = self.current.take() else {
+            return Ok(());
+        };
+        let wav = current.finish()?;
+        self.encoder
+            .send(wav)
+            .map_err(|_| anyhow!("Recording encoder stopped"))
+    }
+}
+
+/// Encodes the WAVs sent to it one after another until the sender is dropped.
+fn spawn_encoder(
+    format: RecordFormat,
+    saved: Arc<AtomicU64>,
+) -> (mpsc::Sender<PathBuf>, JoinHandle<RecordingSummary>) {
+    let (sender, wavs) = mpsc::channel::<PathBuf>();
+    let encoder = std::thread::spawn(move || {
+        let mut summary = RecordingSummary::default();
+        for wav in wavs {
+            let clip = match encode(&wav, format) {
+                Ok(clip) => clip,
+                Err(err) => {
+                    summary.warnings.push(err.to_string());
+                    wav
+                }
+            };
+            summary.clips.push(clip);
+            saved.fetch_add(1, Ordering::Relaxed);
+        }
+        summary
+    });
+    (sender, encoder)
+}
+
+impl Recording {
+    /// Arms `tap` and starts writing what it receives to `dir`. `saved` counts every clip
+    /// that is finished.
+    pub fn start(
+        options: RecordOptions,
+        tap: Arc<RecordTap>,
+        bus: usize,
+        dir: PathBuf,
+        channels: u16,
+        sample_rate: u32,
+        saved: Arc<AtomicU64>,
+    ) -> Result<Self> {
+        if !options.format.is_available() {
+            return Err(anyhow!(options.format.missing_encoder()));
+        }
+        fs::create_dir_all(&dir)?;
+        let (encoder, encoded) = spawn_encoder(options.format, saved);
+        let mut clips = ClipWriter {
+            dir,
+            prefix: "recording",
+            format: options.format,
+            channels: channels.max(1),
+            sample_rate,
+            current: None,
+            encoder,
+        };
+        let splitter = options
+            .split
+            .map(|split| SilenceSplitter::new(split, sample_rate));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        tap.arm(options.source, bus);
+        let writer = {
+            let tap = tap.clone();
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let recorded = clips.record(&tap, &stop, splitter);
+                if recorded.is_err() {
+                    // Nothing drains the tap anymore
+                    tap.disarm();
+                }
+                // Closes the queue, the encoder ends after the clips in it
+                drop(clips);
+                let summary = encoded
+                    .join()
+                    .map_err(|_| anyhow!("Recording encoder panicked"))?;
+                recorded.map(|_| summary)
+            })
+        };
+
+        Ok(Recording {
+            options,
+            tap,
+            stop,
+            writer,
+        })
+    }
+
+    /// Disarms the tap and waits for the writer to save the rest. Blocks while the queued
+    /// clips are encoded.
+    pub fn finish(self) -> Result<RecordingSummary> {
+        self.tap.disarm();
+        self.stop.store(true, Ordering::Release);
+        self.writer
+            .join()
+            .map_err(|_| anyhow!("Recording writer panicked"))?
+    }
+}
+
+/// Encodes a finished WAV into `format` and removes it. The WAV stays if every encoder fails.
+fn encode(wav: &Path, format: RecordFormat) -> Result<PathBuf> {
+    if format == RecordFormat::Wav {
+        return Ok(wav.to_path_buf());
+    }
+
+    let output = wav.with_extension(format.extension());
+    let part = part_path(&output);
+    for mut encoder in format.encoders(wav, &part) {
+        let encoded = encoder
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        if encoded {
+            fs::rename(&part, &output)?;
+            fs::remove_file(wav).ok();
+            return Ok(output);
+        }
+    }
+    fs::remove_file(&part).ok();
+    Err(anyhow!(
+        "{}, kept {}",
+        format.missing_encoder(),
+        wav.display()
+    ))
+}
+
+/// Whether `program` is a file in one of the `PATH` directories.
+fn find_program(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+/// `<prefix>-YYYY-MM-DD_HH-MM-SS` in UTC, with a counter added if the name is taken.
+pub fn clip_path(dir: &Path, prefix: &str, format: RecordFormat, now: SystemTime) -> PathBuf {
+    let secs = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let stem = format!("{}-{}", prefix, timestamp(secs));
+
+    let mut name = stem.clone();
+    let mut counter = 1;
+    while dir.join(format!("{}.wav", name)).exists()
+        || dir.join(format!("{}.wav.part", name)).exists()
+        || dir
+            .join(format!("{}.{}", name, format.extension()))
+            .exists()
+    {
+        counter += 1;
+        name = format!("{}-{}", stem, counter);
+    }
+    dir.join(format!("{}.wav", name))
+}
+
+fn timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+
+    // Civil date from days since 1970-01-01
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}_{:02}-{:02}-{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_silence_splitter() {
+        let split = SilenceSplit {
+            threshold_db: -40.0,
+            min_silence_ms: 3,
+        };
+        let mut splitter = SilenceSplitter::new(split, 1000);
+        let actions: Vec<SplitAction> = [0.0, 0.5, 0.0, 0.5, 0.0, 0.0, 0.0, 0.0, 0.5]
+            .iter()
+            .map(|sample| splitter.frame(&[*sample, 0.0]))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                SplitAction::Skip,
+                SplitAction::Write,
+                SplitAction::Write,
+                SplitAction::Write,
+                SplitAction::Write,
+                SplitAction::Write,
+                SplitAction::Cut,
+                SplitAction::Skip,
+                SplitAction::Write,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record_tap() {
+        let tap = RecordTap::default();
+        tap.push(&[1.0]);
+        assert!(!tap.wants(RecordSource::Mix, 0));
+
+        tap.arm(RecordSource::Mix, 1);
+        assert!(tap.wants(RecordSource::Mix, 1));
+        assert!(!tap.wants(RecordSource::Mix, 0));
+        assert!(!tap.wants(RecordSource::Sounds, 1));
+
+        tap.arm(RecordSource::Mic, 1);
+        assert!(tap.wants(RecordSource::Mic, 0));
+        tap.push(&[0.5, 0.25]);
+        tap.push(&vec![0.0; MAX_QUEUED_SAMPLES]);
+
+        let mut samples = vec![];
+        tap.take(&mut samples);
+        assert_eq!(samples, vec![0.5, 0.25]);
+
+        tap.disarm();
+        assert!(!tap.wants(RecordSource::Mic, 0));
+    }
+
+    #[test]
+    fn test_wav_writer() {
+        let dir = std::env::temp_dir().join(format!("pwsp-wav-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("clip.wav");
+
+        let mut writer = WavWriter::create(&path, 2, 48000).unwrap();
+        writer.write(&[0.0, 1.0, -1.0, 2.0]).unwrap();
+        assert!(!path.exists());
+        writer.finish().unwrap();
+        assert!(!part_path(&path).exists());
+
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 36 + 8);
+        assert_eq!(u16::from_le_bytes(bytes[22..24].try_into().unwrap()), 2);
+        assert_eq!(u32::from_le_bytes(bytes[24..28].try_into().unwrap()), 48000);
+        assert_eq!(u32::from_le_bytes(bytes[40..44].try_into().unwrap()), 8);
+        assert_eq!(i16::from_le_bytes([bytes[46], bytes[47]]), i16::MAX);
+        assert_eq!(i16::from_le_bytes([bytes[50], bytes[51]]), i16::MAX);
+
+        // Full files refuse more samples instead of wrapping their sizes
+        let mut writer = WavWriter::create(&path, 2, 48000).unwrap();
+        writer.max_data_len = 8;
+        assert!(writer.has_room(4));
+        writer.write(&[0.5; 4]).unwrap();
+        assert!(!writer.has_room(2));
+        assert!(writer.write(&[0.5; 2]).is_err());
+        writer.finish().unwrap();
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_clip_names() {
+        assert_eq!(timestamp(0), "1970-01-01_00-00-00");
+        assert_eq!(timestamp(951_827_696), "2000-02-29_12-34-56");
+        assert_eq!(timestamp(1_792_281_600), "2026-10-18_00-00-00");
+
+        let dir = Path::new("/nonexistent");
+        let now = UNIX_EPOCH + Duration::from_secs(60);
+        assert_eq!(
+            clip_path(dir, "recording", RecordFormat::Flac, now),
+            dir.join("recording-1970-01-01_00-01-00.wav")
+        );
+        assert_eq!("vorbis".parse::<RecordFormat>(), Ok(RecordFormat::Ogg));
+        assert_eq!("raw-mic".parse::<RecordSource>(), Ok(RecordSource::Mic));
+    }
+}
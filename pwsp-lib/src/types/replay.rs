@@ -0,0 +1,223 @@
+use crate::types::recorder::{RecordFormat, WavWriter, clip_path};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+    },
+    time::SystemTime,
+};
+
+/// Longest replay kept in memory, about 110 MB of 48 kHz stereo
+pub const MAX_REPLAY_SECONDS: u32 = 300;
+
+/// Rolling buffer of recent audio that `save_replay` writes out.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplaySettings {
+    pub enabled: bool,
+    /// How much audio is kept
+    pub seconds: u32,
+    /// Source to buffer instead of the microphone, in the same form as the input device.
+    /// An output device buffers what it plays
+    pub source: Option<String>,
+}
+
+impl Default for ReplaySettings {
+    fn default() -> Self {
+        ReplaySettings {
+            enabled: false,
+            seconds: 30,
+            source: None,
+        }
+    }
+}
+
+impl ReplaySettings {
+    pub fn seconds(&self) -> u32 {
+        self.seconds.clamp(1, MAX_REPLAY_SECONDS)
+    }
+}
+
+/// The last few seconds of a capture, filled on the audio thread.
+///
+/// The ring is allocated by `configure` and never grows, the audio thread only drops the
+/// oldest samples to make room for new ones.
+#[derive(Debug, Default)]
+pub struct ReplayBuffer {
+    samples: Mutex<VecDeque<f32>>,
+    /// Samples the ring holds, whole frames of the current channel count are kept
+    capacity: AtomicUsize,
+    seconds: AtomicU32,
+    /// Takes the microphone, otherwise only a capture of its own feeds it
+    from_mic: AtomicBool,
+    channels: AtomicU32,
+    sample_rate: AtomicU32,
+}
+
+impl ReplayBuffer {
+    /// Keeps what is buffered unless the length, format or source changes. `channels` is the
+    /// expected channel count, a capture with another one keeps a different length.
+    pub fn configure(&self, seconds: u32, sample_rate: u32, channels: u32, from_mic: bool) {
+        let channels = channels.max(1);
+        let capacity = seconds as usize * sample_rate as usize * channels as usize;
+        let seconds_changed = self.seconds.swap(seconds, Ordering::Relaxed) != seconds;
+        let rate_changed = self.sample_rate.swap(sample_rate, Ordering::Relaxed) != sample_rate;
+        let source_changed = self.from_mic.swap(from_mic, Ordering::Relaxed) != from_mic;
+        let capacity_changed = self.capacity.load(Ordering::Relaxed) != capacity;
+        if seconds_changed || rate_changed || source_changed || capacity_changed {
+            let ring = VecDeque::with_capacity(capacity);
+            let mut buffer = self.lock();
+            let old = std::mem::replace(&mut *buffer, ring);
+            self.capacity.store(capacity, Ordering::Relaxed);
+            self.channels.store(channels, Ordering::Relaxed);
+            // The old ring is freed here rather than on the audio thread
+            drop(buffer);
+            drop(old);
+        }
+    }
+
+    pub fn disable(&self) {
+        self.seconds.store(0, Ordering::Relaxed);
+        self.capacity.store(0, Ordering::Relaxed);
+        let old = std::mem::take(&mut *self.lock());
+        drop(old);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.seconds.load(Ordering::Relaxed) > 0
+    }
+
+    pub fn push_mic(&self, samples: &[f32], channels: u32) {
+        if self.from_mic.load(Ordering::Relaxed) {
+            self.push(samples, channels);
+        }
+    }
+
+    /// Drops the oldest frames once the buffer is full. Audio with another channel count
+    /// starts the buffer over.
+    pub fn push(&self, samples: &[f32], channels: u32) {
+        if self.seconds.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let channels = channels.max(1);
+
+        let mut buffer = self.lock();
+        if self.channels.swap(channels, Ordering::Relaxed) != channels {
+            buffer.clear();
+        }
+
+        let frame = channels as usize;
+        let capacity = self.capacity.load(Ordering::Relaxed) / frame * frame;
+        let samples = &samples[samples.len().saturating_sub(capacity)..];
+        let excess = (buffer.len() + samples.len()).saturating_sub(capacity);
+        let excess = (excess.div_ceil(frame) * frame).min(buffer.len());
+        buffer.drain(..excess);
+        buffer.extend(samples);
+    }
+
+    /// Up to the last `seconds` of audio, everything buffered when unset
+    pub fn snapshot(&self, seconds: Option<f32>) -> Vec<f32> {
+        let frame = self.channels.load(Ordering::Relaxed).max(1) as usize;
+        let wanted = match seconds {
+            Some(seconds) => {
+                let frames =
+                    (seconds.max(0.0) * self.sample_rate.load(Ordering::Relaxed) as f32) as usize;
+                frames * frame
+            }
+            None => usize::MAX,
+        };
+        // Allocated before locking, the audio thread only waits for the copy itself
+        let mut copy = Vec::with_capacity(wanted.min(self.capacity.load(Ordering::Relaxed)));
+
+        let buffer = self.lock();
+        let len = wanted.min(buffer.len() / frame * frame);
+        let start = buffer.len() - len;
+        let (front, back) = buffer.as_slices();
+        if start < front.len() {
+            copy.extend_from_slice(&front[start..]);
+            copy.extend_from_slice(back);
+        } else {
+            copy.extend_from_slice(&back[start - front.len()..]);
+        }
+        copy
+    }
+
+    /// Writes the last `seconds` to a new WAV in `dir`.
+    pub fn save(&self, seconds: Option<f32>, dir: &Path) -> Result<PathBuf> {
+        let samples = self.snapshot(seconds);
+        if samples.is_empty() {
+            return Err(anyhow!("Replay buffer is empty"));
+        }
+
+        let path = clip_path(dir, "replay", RecordFormat::Wav, SystemTime::now());
+        let mut writer = WavWriter::create(
+            &path,
+            self.channels.load(Ordering::Relaxed) as u16,
+            self.sample_rate.load(Ordering::Relaxed),
+        )?;
+        writer.write(&samples)?;
+        Ok(writer.finish()?)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<f32>> {
+        self.samples.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_buffer_keeps_last_seconds() {
+        let buffer = ReplayBuffer::default();
+        buffer.push(&[1.0; 8], 2);
+        assert!(buffer.snapshot(None).is_empty());
+
+        buffer.configure(1, 4, 2, false);
+        buffer.push_mic(&[1.0; 8], 2);
+        assert!(buffer.snapshot(None).is_empty());
+
+        let samples: Vec<f32> = (0..12).map(|i| i as f32).collect();
+        buffer.push(&samples, 2);
+        assert_eq!(
+            buffer.snapshot(None),
+            (4..12).map(|i| i as f32).collect::<Vec<_>>()
+        );
+        assert_eq!(buffer.snapshot(Some(0.5)), vec![8.0, 9.0, 10.0, 11.0]);
+        assert_eq!(buffer.snapshot(Some(10.0)).len(), 8);
+
+        // The ring never grows past what configure allocated
+        let capacity = buffer.lock().capacity();
+        for _ in 0..100 {
+            buffer.push(&samples, 2);
+        }
+        assert_eq!(buffer.lock().capacity(), capacity);
+        assert_eq!(buffer.snapshot(Some(0.5)), vec![8.0, 9.0, 10.0, 11.0]);
+
+        // Same settings keep the buffer, a new channel count clears it
+        buffer.configure(1, 4, 2, false);
+        assert_eq!(buffer.snapshot(None).len(), 8);
+        buffer.push(&[0.5, 0.5], 1);
+        assert_eq!(buffer.snapshot(None), vec![0.5, 0.5]);
+
+        buffer.disable();
+        buffer.push(&[1.0; 4], 1);
+        assert!(!buffer.is_enabled());
+        assert!(buffer.snapshot(None).is_empty());
+    }
+
+    #[test]
+    fn test_replay_settings() {
+        let settings = ReplaySettings {
+            seconds: 100_000,
+            ..Default::default()
+        };
+        assert_eq!(settings.seconds(), MAX_REPLAY_SECONDS);
+        assert_eq!(ReplaySettings::default().seconds(), 30);
+    }
+}
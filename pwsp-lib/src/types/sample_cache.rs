@@ -0,0 +1,432 @@
+use crate::types::{
+    config::{HotkeyConfig, SoundConfig},
+    waveform::Waveform,
+};
+use anyhow::Result;
+use rodio::{ChannelCount, Decoder, SampleRate, Source, source::SeekError};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+/// Memory budget of the decoded sample cache.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct SampleCacheSettings {
+    /// Zero disables the cache
+    pub memory_budget_mb: usize,
+}
+
+impl Default for SampleCacheSettings {
+    fn default() -> Self {
+        SampleCacheSettings {
+            memory_budget_mb: 256,
+        }
+    }
+}
+
+impl SampleCacheSettings {
+    pub fn budget_bytes(&self) -> usize {
+        self.memory_budget_mb.saturating_mul(1024 * 1024)
+    }
+}
+
+/// A whole sound file decoded to interleaved PCM.
+pub struct DecodedSound {
+    samples: Vec<f32>,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+    /// Modification time and size of the file when it was decoded
+    stamp: Option<(SystemTime, u64)>,
+    /// Envelope for silence trimming, so playing from memory never touches the disk
+    pub waveform: Waveform,
+}
+
+impl DecodedSound {
+    pub fn decode(path: &Path) -> Result<Self> {
+        let stamp = file_stamp(path);
+        let decoder = Decoder::try_from(fs::File::open(path)?)?;
+        let channels = decoder.channels();
+        let sample_rate = decoder.sample_rate();
+        let samples: Vec<f32> = decoder.collect();
+        let waveform = Waveform::from_samples(
+            samples.iter().copied(),
+            u32::from(sample_rate),
+            u16::from(channels),
+        );
+
+        Ok(DecodedSound {
+            samples,
+            channels,
+            sample_rate,
+            stamp,
+            waveform,
+        })
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.samples.len() * size_of::<f32>()
+    }
+
+    /// True if the file changed or disappeared since it was decoded
+    pub fn is_stale(&self, path: &Path) -> bool {
+        file_stamp(path) != self.stamp
+    }
+
+    pub fn source(self: &Arc<Self>) -> CachedSource {
+        CachedSource {
+            sound: self.clone(),
+            pos: 0,
+        }
+    }
+}
+
+/// Modification time and size of a file, a rewrite within the mtime granularity still
+/// shows up when the size changes
+fn file_stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Plays a [`DecodedSound`] without copying it, seeking is exact and free.
+pub struct CachedSource {
+    sound: Arc<DecodedSound>,
+    /// Next interleaved sample
+    pos: usize,
+}
+
+impl Iterator for CachedSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.sound.samples.get(self.pos).copied()?;
+        self.pos += 1;
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.sound.samples.len().saturating_sub(self.pos);
+        (remaining, Some(remaining))
+    }
+}
+
+impl Source for CachedSource {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.sound.channels
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.sound.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        let channels = u16::from(self.sound.channels).max(1) as usize;
+        let frames = self.sound.samples.len() / channels;
+        Some(Duration::from_secs_f64(
+            frames as f64 / u32::from(self.sound.sample_rate) as f64,
+        ))
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let channels = u16::from(self.sound.channels).max(1) as usize;
+        let sample_rate = u32::from(self.sound.sample_rate) as f64;
+        let frame = (pos.as_secs_f64() * sample_rate).round() as usize;
+        self.pos = frame.saturating_mul(channels).min(self.sound.samples.len());
+        Ok(())
+    }
+}
+
+/// Reply to `get_sample_cache`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SampleCacheInfo {
+    pub budget_bytes: usize,
+    pub used_bytes: usize,
+    /// Cached files, sorted
+    pub sounds: Vec<PathBuf>,
+}
+
+struct CacheEntry {
+    sound: Arc<DecodedSound>,
+    last_used: u64,
+}
+
+/// Decoded hotkey-bound and pinned sounds, evicting the least recently played one when the
+/// memory budget runs out.
+#[derive(Default)]
+pub struct SampleCache {
+    budget: usize,
+    used: usize,
+    entries: HashMap<PathBuf, CacheEntry>,
+    /// Sounds that belong in the cache, see [`preload_targets`]
+    targets: HashSet<PathBuf>,
+    clock: u64,
+}
+
+impl SampleCache {
+    pub fn new(budget: usize) -> Self {
+        SampleCache {
+            budget,
+            ..Default::default()
+        }
+    }
+
+    /// The decoded sound if the file is unchanged, a stale one is evicted
+    pub fn get(&mut self, path: &Path) -> Option<Arc<DecodedSound>> {
+        if self.entries.get(path)?.sound.is_stale(path) {
+            self.remove(path);
+            return None;
+        }
+
+        self.clock += 1;
+        let entry = self.entries.get_mut(path)?;
+        entry.last_used = self.clock;
+        Some(entry.sound.clone())
+    }
+
+    /// Cached and decoded from the current version of the file
+    pub fn is_fresh(&self, path: &Path) -> bool {
+        self.entries
+            .get(path)
+            .is_some_and(|entry| !entry.sound.is_stale(path))
+    }
+
+    pub fn is_target(&self, path: &Path) -> bool {
+        self.targets.contains(path)
+    }
+
+    /// Replaces the set of sounds to keep, dropping the ones that are no longer wanted.
+    pub fn set_targets(&mut self, targets: HashSet<PathBuf>) {
+        let dropped: Vec<PathBuf> = self
+            .entries
+            .keys()
+            .filter(|path| !targets.contains(*path))
+            .cloned()
+            .collect();
+        for path in dropped {
+            self.remove(&path);
+        }
+        self.targets = targets;
+    }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict(0);
+    }
+
+    /// Adds a sound, evicting least recently used ones if `evict` is set.
+    ///
+    /// Returns false if the sound did not fit.
+    pub fn insert(&mut self, path: PathBuf, sound: Arc<DecodedSound>, evict: bool) -> bool {
+        self.remove(&path);
+
+        let size = sound.size_bytes();
+        if size > self.budget || (!evict && self.used + size > self.budget) {
+            return false;
+        }
+        self.evict(size);
+
+        self.clock += 1;
+        self.used += size;
+        self.entries.insert(
+            path,
+            CacheEntry {
+                sound,
+                last_used: self.clock,
+            },
+        );
+        true
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        if let Some(entry) = self.entries.remove(path) {
+            self.used -= entry.sound.size_bytes();
+        }
+    }
+
+    /// Makes room for `size` more bytes
+    fn evict(&mut self, size: usize) {
+        while self.used + size > self.budget {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(path, _)| path.clone())
+            else {
+                break;
+            };
+            self.remove(&oldest);
+        }
+    }
+
+    pub fn info(&self) -> SampleCacheInfo {
+        let mut sounds: Vec<PathBuf> = self.entries.keys().cloned().collect();
+        sounds.sort();
+        SampleCacheInfo {
+            budget_bytes: self.budget,
+            used_bytes: self.used,
+            sounds,
+        }
+    }
+}
+
+/// Sounds played straight by a hotkey slot, plus the pinned ones.
+pub fn preload_targets(hotkeys: &HotkeyConfig, sounds: &SoundConfig) -> HashSet<PathBuf> {
+    let bound = hotkeys
+        .slots
+        .iter()
+        .filter(|slot| slot.action.name == "play")
+        .filter_map(|slot| slot.action.args.get("file_path"))
+        .map(PathBuf::from);
+    let pinned = sounds
+        .sounds
+        .iter()
+        .filter(|(_, settings)| settings.pinned)
+        .map(|(path, _)| path.clone());
+    bound.chain(pinned).collect()
+}
+
+/// Decodes every path that is not cached yet, blocking. Without `evict` loading stops
+/// touching the cache once it is full instead of pushing earlier sounds out.
+pub fn load_into(cache: &Mutex<SampleCache>, paths: &[PathBuf], evict: bool) {
+    for path in paths {
+        if cache.lock().unwrap().is_fresh(path) {
+            continue;
+        }
+
+        match DecodedSound::decode(path) {
+            Ok(sound) => {
+                cache
+                    .lock()
+                    .unwrap()
+                    .insert(path.clone(), Arc::new(sound), evict);
+            }
+            Err(err) => eprintln!("Failed to preload {}: {}", path.display(), err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        config::{HotkeySlot, SoundSettings},
+        socket::Request,
+    };
+
+    fn sound(len: usize) -> Arc<DecodedSound> {
+        Arc::new(DecodedSound {
+            samples: (0..len).map(|i| i as f32).collect(),
+            channels: 2u16.try_into().unwrap(),
+            sample_rate: 10u32.try_into().unwrap(),
+            stamp: None,
+            waveform: Waveform::default(),
+        })
+    }
+
+    #[test]
+    fn test_cached_source_seek() {
+        let mut source = sound(40).source();
+        assert_eq!(source.total_duration(), Some(Duration::from_secs(2)));
+
+        source.try_seek(Duration::from_millis(500)).unwrap();
+        assert_eq!(source.next(), Some(10.0));
+        assert_eq!(source.count(), 29);
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        // Room for two sounds of 10 samples
+        let mut cache = SampleCache::new(80);
+        assert!(cache.insert("a".into(), sound(10), true));
+        assert!(cache.insert("b".into(), sound(10), true));
+        assert!(cache.get(Path::new("a")).is_some());
+
+        // Without eviction a full cache refuses
+        assert!(!cache.insert("c".into(), sound(10), false));
+
+        // "b" is the least recently used one
+        assert!(cache.insert("c".into(), sound(10), true));
+        assert_eq!(
+            cache.info().sounds,
+            vec![PathBuf::from("a"), PathBuf::from("c")]
+        );
+        assert_eq!(cache.info().used_bytes, 80);
+
+        // Larger than the whole budget
+        assert!(!cache.insert("d".into(), sound(30), true));
+
+        cache.set_budget(40);
+        assert_eq!(cache.info().sounds.len(), 1);
+    }
+
+    #[test]
+    fn test_changed_file_is_evicted() {
+        let dir = std::env::temp_dir().join(format!("pwsp-sample-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("horn.wav");
+        fs::write(&path, [0u8; 16]).unwrap();
+
+        let mut cache = SampleCache::new(1024);
+        let decoded = DecodedSound {
+            stamp: file_stamp(&path),
+            ..Arc::into_inner(sound(10)).unwrap()
+        };
+        assert!(cache.insert(path.clone(), Arc::new(decoded), true));
+        assert!(cache.get(&path).is_some());
+
+        // Caught by the size even when the mtime did not move
+        fs::write(&path, [0u8; 32]).unwrap();
+        assert!(cache.get(&path).is_none());
+        assert_eq!(cache.info().used_bytes, 0);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_preload_targets() {
+        let hotkeys = HotkeyConfig {
+            slots: vec![
+                HotkeySlot {
+                    slot: "1".to_string(),
+                    action: Request::play("/sounds/horn.mp3", false),
+                    key_chord: None,
+                    max_voices: None,
+                },
+                HotkeySlot {
+                    slot: "2".to_string(),
+                    action: Request::stop(None),
+                    key_chord: None,
+                    max_voices: None,
+                },
+            ],
+        };
+        let mut sounds = SoundConfig::default();
+        sounds.set(
+            "/sounds/bed.ogg".into(),
+            SoundSettings {
+                pinned: true,
+                ..Default::default()
+            },
+        );
+        sounds.set(
+            "/sounds/other.ogg".into(),
+            SoundSettings {
+                tags: vec!["x".to_string()],
+                ..Default::default()
+            },
+        );
+
+        let targets = preload_targets(&hotkeys, &sounds);
+        assert_eq!(targets.len(), 2);
+        assert!(targets.contains(Path::new("/sounds/horn.mp3")));
+        assert!(targets.contains(Path::new("/sounds/bed.ogg")));
+    }
+}
@@ -1,4 +1,13 @@
-use crate::types::config::DaemonConfig;
+use crate::types::{
+    config::{DaemonConfig, SoundSettings},
+    effects::Effect,
+    limiter::LimiterMode,
+    random::{RandomMode, RandomSource},
+    recorder::{RecordFormat, RecordSource, SilenceSplit},
+    tts::TtsEngine,
+    voice_changer::VoicePreset,
+    voices::VoiceStealing,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -81,6 +90,56 @@ impl Request {
         )
     }
 
+    /// Speaks `text` with the configured TTS engine and plays it like a sound
+    pub fn say(text: &str, concurrent: bool) -> Self {
+        Request::new(
+            "say",
+            vec![("text", text), ("concurrent", &concurrent.to_string())],
+        )
+    }
+
+    /// `voice` empty goes back to the default voice of the engine
+    pub fn set_tts(
+        engine: Option<TtsEngine>,
+        voice: Option<&str>,
+        rate: Option<f32>,
+        pitch: Option<f32>,
+    ) -> Self {
+        let mut args: Vec<(&str, String)> = vec![];
+        if let Some(engine) = engine {
+            args.push(("engine", engine.as_str().to_string()));
+        }
+        if let Some(voice) = voice {
+            args.push(("voice", voice.to_string()));
+        }
+        if let Some(rate) = rate {
+            args.push(("rate", rate.to_string()));
+        }
+        if let Some(pitch) = pitch {
+            args.push(("pitch", pitch.to_string()));
+        }
+        let args = args.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        Request::new("set_tts", args)
+    }
+
+    pub fn play_random(source: &RandomSource, mode: RandomMode, concurrent: bool) -> Self {
+        let (source_key, source_value) = match source {
+            RandomSource::Dir(dir) => ("dir", dir.to_string_lossy().to_string()),
+            RandomSource::Files(files) => {
+                ("files", serde_json::to_string(files).unwrap_or_default())
+            }
+            RandomSource::Tag(tag) => ("tag", tag.clone()),
+        };
+        Request::new(
+            "play_random",
+            vec![
+                (source_key, source_value.as_str()),
+                ("mode", mode.as_str()),
+                ("concurrent", &concurrent.to_string()),
+            ],
+        )
+    }
+
     pub fn get_is_paused() -> Self {
         Request::new("is_paused", vec![])
     }
@@ -158,12 +217,40 @@ impl Request {
         Request::new("seek".to_string(), args)
     }
 
+    pub fn set_speed(speed: f32, id: Option<u32>) -> Self {
+        let mut args = vec![("speed".to_string(), speed.to_string())];
+        if let Some(id) = id {
+            args.push(("id".to_string(), id.to_string()));
+        }
+        Request::new("set_speed".to_string(), args)
+    }
+
+    pub fn set_pitch(semitones: f32, id: Option<u32>) -> Self {
+        let mut args = vec![("pitch".to_string(), semitones.to_string())];
+        if let Some(id) = id {
+            args.push(("id".to_string(), id.to_string()));
+        }
+        Request::new("set_pitch".to_string(), args)
+    }
+
     pub fn set_input(name: &str) -> Self {
         Request::new("set_input", vec![("input_name", name)])
     }
 
-    pub fn set_loop(enabled: &str, id: Option<u32>) -> Self {
+    /// Empty lets PipeWire mix all channels of the input device down
+    pub fn set_input_channels(channels: &[String]) -> Self {
+        Request::new(
+            "set_input_channels",
+            vec![("channels", &channels.join(","))],
+        )
+    }
+
+    /// `count` limits how many times the loop plays, including the current pass
+    pub fn set_loop(enabled: &str, count: Option<u32>, id: Option<u32>) -> Self {
         let mut args = vec![("enabled".to_string(), enabled.to_string())];
+        if let Some(count) = count {
+            args.push(("count".to_string(), count.to_string()));
+        }
         if let Some(id) = id {
             args.push(("id".to_string(), id.to_string()));
         }
@@ -202,6 +289,40 @@ impl Request {
         )
     }
 
+    pub fn get_sound_settings(file_path: &str) -> Self {
+        Request::new("get_sound_settings", vec![("file_path", file_path)])
+    }
+
+    pub fn set_sound_settings(file_path: &str, settings: &SoundSettings) -> Self {
+        Request::new(
+            "set_sound_settings",
+            vec![
+                ("file_path", file_path),
+                (
+                    "settings",
+                    &serde_json::to_string(settings).unwrap_or_default(),
+                ),
+            ],
+        )
+    }
+
+    /// Changes only the fields present in `changes`, see [`SoundSettings::merged`].
+    pub fn update_sound_settings(
+        file_path: &str,
+        changes: &serde_json::Map<String, serde_json::Value>,
+    ) -> Self {
+        Request::new(
+            "set_sound_settings",
+            vec![
+                ("file_path", file_path),
+                (
+                    "settings",
+                    &serde_json::to_string(changes).unwrap_or_default(),
+                ),
+            ],
+        )
+    }
+
     pub fn get_full_state() -> Self {
         Request::new("get_full_state", vec![])
     }
@@ -221,6 +342,281 @@ impl Request {
         )
     }
 
+    pub fn set_hotkey_max_voices(slot: &str, max_voices: Option<usize>) -> Self {
+        let mut args = vec![("slot", slot)];
+        let max_voices_str;
+        if let Some(max_voices) = max_voices {
+            max_voices_str = max_voices.to_string();
+            args.push(("max_voices", max_voices_str.as_str()));
+        }
+        Request::new("set_hotkey_max_voices", args)
+    }
+
+    pub fn set_voice_limit(max_voices: Option<usize>, policy: Option<VoiceStealing>) -> Self {
+        let mut args = vec![];
+        let max_voices_str;
+        if let Some(max_voices) = max_voices {
+            max_voices_str = max_voices.to_string();
+            args.push(("max_voices", max_voices_str.as_str()));
+        }
+        if let Some(policy) = policy {
+            args.push(("policy", policy.as_str()));
+        }
+        Request::new("set_voice_limit", args)
+    }
+
+    pub fn get_levels() -> Self {
+        Request::new("get_levels", vec![])
+    }
+
+    pub fn get_audio_info() -> Self {
+        Request::new("get_audio_info", vec![])
+    }
+
+    pub fn get_pw_nodes() -> Self {
+        Request::new("get_pw_nodes", vec![])
+    }
+
+    pub fn get_pw_ports() -> Self {
+        Request::new("get_pw_ports", vec![])
+    }
+
+    pub fn get_pw_links() -> Self {
+        Request::new("get_pw_links", vec![])
+    }
+
+    pub fn get_app_streams() -> Self {
+        Request::new("get_app_streams", vec![])
+    }
+
+    /// App capture streams the sounds are linked into, by application name, binary or
+    /// `node.name`. Empty stops routing into apps.
+    pub fn set_route_targets(targets: &[String]) -> Self {
+        Request::new("set_route_targets", vec![("targets", &targets.join(","))])
+    }
+
+    /// Waits for audio sources to be added or removed after event `since`, or after now
+    /// when unset. Answers with an empty list after `timeout_secs`.
+    pub fn wait_device_events(since: Option<u64>, timeout_secs: Option<u64>) -> Self {
+        let mut args: Vec<(&str, String)> = vec![];
+        if let Some(since) = since {
+            args.push(("since", since.to_string()));
+        }
+        if let Some(timeout_secs) = timeout_secs {
+            args.push(("timeout", timeout_secs.to_string()));
+        }
+        let args = args.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        Request::new("wait_device_events", args)
+    }
+
+    /// Records to the PWSP audio dir, `bus` picks the bus for the mix and sounds sources
+    pub fn start_recording(
+        source: RecordSource,
+        bus: Option<&str>,
+        format: RecordFormat,
+        split: Option<SilenceSplit>,
+    ) -> Self {
+        let mut args: Vec<(&str, String)> = vec![
+            ("source", source.as_str().to_string()),
+            ("format", format.extension().to_string()),
+        ];
+        if let Some(bus) = bus {
+            args.push(("bus", bus.to_string()));
+        }
+        if let Some(split) = split {
+            args.push(("split", serde_json::to_string(&split).unwrap_or_default()));
+        }
+        let args = args.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        Request::new("start_recording", args)
+    }
+
+    pub fn stop_recording() -> Self {
+        Request::new("stop_recording", vec![])
+    }
+
+    /// Saves the last `seconds` of the replay buffer to the library, and binds the clip to
+    /// hotkey `slot` when given
+    pub fn save_replay(seconds: Option<f32>, slot: Option<&str>) -> Self {
+        let mut args: Vec<(&str, String)> = vec![];
+        if let Some(seconds) = seconds {
+            args.push(("seconds", seconds.to_string()));
+        }
+        if let Some(slot) = slot {
+            args.push(("slot", slot.to_string()));
+        }
+        let args = args.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        Request::new("save_replay", args)
+    }
+
+    /// `source` is an audio source or `mic` for the microphone
+    pub fn set_replay(enabled: Option<bool>, seconds: Option<u32>, source: Option<&str>) -> Self {
+        let mut args: Vec<(&str, String)> = vec![];
+        if let Some(enabled) = enabled {
+            args.push(("enabled", enabled.to_string()));
+        }
+        if let Some(seconds) = seconds {
+            args.push(("seconds", seconds.to_string()));
+        }
+        if let Some(source) = source {
+            args.push(("source", source.to_string()));
+        }
+        let args = args.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        Request::new("set_replay", args)
+    }
+
+    pub fn get_sample_cache() -> Self {
+        Request::new("get_sample_cache", vec![])
+    }
+
+    pub fn set_sample_cache(memory_budget_mb: usize) -> Self {
+        Request::new(
+            "set_sample_cache",
+            vec![("memory_budget_mb", &memory_budget_mb.to_string())],
+        )
+    }
+
+    pub fn get_buses() -> Self {
+        Request::new("get_buses", vec![])
+    }
+
+    pub fn set_bus_volume(bus: &str, volume: f32) -> Self {
+        Request::new(
+            "set_bus_volume",
+            vec![("bus", bus), ("volume", &volume.to_string())],
+        )
+    }
+
+    pub fn set_bus_mic(bus: &str, enabled: bool) -> Self {
+        Request::new(
+            "set_bus_mic",
+            vec![("bus", bus), ("enabled", &enabled.to_string())],
+        )
+    }
+
+    pub fn get_limiter() -> Self {
+        Request::new("get_limiter", vec![])
+    }
+
+    pub fn set_limiter(
+        enabled: Option<bool>,
+        mode: Option<LimiterMode>,
+        ceiling_db: Option<f32>,
+        release_ms: Option<f32>,
+    ) -> Self {
+        let mut args: Vec<(&str, String)> = vec![];
+        if let Some(enabled) = enabled {
+            args.push(("enabled", enabled.to_string()));
+        }
+        if let Some(mode) = mode {
+            args.push(("mode", mode.as_str().to_string()));
+        }
+        if let Some(ceiling_db) = ceiling_db {
+            args.push(("ceiling_db", ceiling_db.to_string()));
+        }
+        if let Some(release_ms) = release_ms {
+            args.push(("release_ms", release_ms.to_string()));
+        }
+        let args = args.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        Request::new("set_limiter", args)
+    }
+
+    pub fn set_silence_trim(enabled: Option<bool>, threshold_db: Option<f32>) -> Self {
+        let mut args: Vec<(&str, String)> = vec![];
+        if let Some(enabled) = enabled {
+            args.push(("enabled", enabled.to_string()));
+        }
+        if let Some(threshold_db) = threshold_db {
+            args.push(("threshold_db", threshold_db.to_string()));
+        }
+        let args = args.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        Request::new("set_silence_trim", args)
+    }
+
+    pub fn get_effects(id: Option<u32>) -> Self {
+        let id_str;
+        let mut args = vec![];
+        if let Some(id) = id {
+            id_str = id.to_string();
+            args.push(("id", id_str.as_str()));
+        }
+        Request::new("get_effects", args)
+    }
+
+    /// Track effects when `id` is set, master bus effects otherwise
+    pub fn set_effects(effects: &[Effect], id: Option<u32>) -> Self {
+        let mut args = vec![(
+            "effects".to_string(),
+            serde_json::to_string(effects).unwrap_or_default(),
+        )];
+        if let Some(id) = id {
+            args.push(("id".to_string(), id.to_string()));
+        }
+        Request::new("set_effects".to_string(), args)
+    }
+
+    pub fn set_effects_preset(preset: &str, id: Option<u32>) -> Self {
+        let mut args = vec![("preset".to_string(), preset.to_string())];
+        if let Some(id) = id {
+            args.push(("id".to_string(), id.to_string()));
+        }
+        Request::new("set_effects".to_string(), args)
+    }
+
+    pub fn get_effect_presets() -> Self {
+        Request::new("get_effect_presets", vec![])
+    }
+
+    pub fn save_effect_preset(name: &str, effects: &[Effect]) -> Self {
+        Request::new(
+            "save_effect_preset",
+            vec![
+                ("name", name),
+                (
+                    "effects",
+                    &serde_json::to_string(effects).unwrap_or_default(),
+                ),
+            ],
+        )
+    }
+
+    pub fn delete_effect_preset(name: &str) -> Self {
+        Request::new("delete_effect_preset", vec![("name", name)])
+    }
+
+    pub fn get_voice_changer() -> Self {
+        Request::new("get_voice_changer", vec![])
+    }
+
+    pub fn set_voice_changer(enabled: Option<bool>, preset: Option<&str>) -> Self {
+        let mut args: Vec<(&str, String)> = vec![];
+        if let Some(enabled) = enabled {
+            args.push(("enabled", enabled.to_string()));
+        }
+        if let Some(preset) = preset {
+            args.push(("preset", preset.to_string()));
+        }
+        let args = args.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        Request::new("set_voice_changer", args)
+    }
+
+    pub fn toggle_voice_changer() -> Self {
+        Request::new("toggle_voice_changer", vec![])
+    }
+
+    pub fn save_voice_preset(name: &str, preset: &VoicePreset) -> Self {
+        Request::new(
+            "save_voice_preset",
+            vec![
+                ("name", name),
+                ("preset", &serde_json::to_string(preset).unwrap_or_default()),
+            ],
+        )
+    }
+
+    pub fn delete_voice_preset(name: &str) -> Self {
+        Request::new("delete_voice_preset", vec![("name", name)])
+    }
+
     pub fn clear_hotkey(slot: &str) -> Self {
         Request::new("clear_hotkey", vec![("slot", slot)])
     }
@@ -315,6 +711,254 @@ mod tests {
             Some("true")
         );
 
+        // test play_random
+        let req_random = Request::play_random(
+            &RandomSource::Dir("/sounds/farts".into()),
+            RandomMode::Shuffle,
+            true,
+        );
+        assert_eq!(req_random.name, "play_random");
+        assert_eq!(
+            req_random.args.get("dir").map(|s| s.as_str()),
+            Some("/sounds/farts")
+        );
+        assert_eq!(
+            req_random.args.get("mode").map(|s| s.as_str()),
+            Some("shuffle")
+        );
+        assert!(!req_random.args.contains_key("tag"));
+
+        // test set_voice_limit
+        let req_limit = Request::set_voice_limit(Some(8), Some(VoiceStealing::Quietest));
+        assert_eq!(req_limit.name, "set_voice_limit");
+        assert_eq!(
+            req_limit.args.get("max_voices").map(|s| s.as_str()),
+            Some("8")
+        );
+        assert_eq!(
+            req_limit.args.get("policy").map(|s| s.as_str()),
+            Some("quietest")
+        );
+        assert!(Request::set_voice_limit(None, None).args.is_empty());
+
+        // test set_limiter
+        let req_limiter = Request::set_limiter(Some(true), None, Some(-1.5), None);
+        assert_eq!(req_limiter.name, "set_limiter");
+        assert_eq!(
+            req_limiter.args.get("enabled").map(|s| s.as_str()),
+            Some("true")
+        );
+        assert_eq!(
+            req_limiter.args.get("ceiling_db").map(|s| s.as_str()),
+            Some("-1.5")
+        );
+        assert!(!req_limiter.args.contains_key("mode"));
+
+        // test effects
+        let effects = vec![Effect::Distortion {
+            drive: 4.0,
+            mix: 0.5,
+        }];
+        let req_effects = Request::set_effects(&effects, None);
+        assert_eq!(req_effects.name, "set_effects");
+        assert!(!req_effects.args.contains_key("id"));
+        assert_eq!(
+            serde_json::from_str::<Vec<Effect>>(&req_effects.args["effects"]).unwrap(),
+            effects
+        );
+        let req_preset = Request::set_effects_preset("radio", Some(3));
+        assert_eq!(
+            req_preset.args.get("preset").map(|s| s.as_str()),
+            Some("radio")
+        );
+        assert_eq!(req_preset.args.get("id").map(|s| s.as_str()), Some("3"));
+
+        // test voice changer
+        let req_voice = Request::set_voice_changer(None, Some("robot"));
+        assert_eq!(req_voice.name, "set_voice_changer");
+        assert!(!req_voice.args.contains_key("enabled"));
+        assert_eq!(
+            req_voice.args.get("preset").map(|s| s.as_str()),
+            Some("robot")
+        );
+        let preset = VoicePreset {
+            pitch: 3.0,
+            ..Default::default()
+        };
+        let req_voice_preset = Request::save_voice_preset("high", &preset);
+        assert_eq!(
+            serde_json::from_str::<VoicePreset>(&req_voice_preset.args["preset"]).unwrap(),
+            preset
+        );
+
+        // test get_audio_info
+        let req_audio_info = Request::get_audio_info();
+        assert_eq!(req_audio_info.name, "get_audio_info");
+        assert!(req_audio_info.args.is_empty());
+
+        // test set_loop
+        let req_loop = Request::set_loop("true", Some(3), None);
+        assert_eq!(req_loop.name, "set_loop");
+        assert_eq!(req_loop.args.get("count").map(|s| s.as_str()), Some("3"));
+        assert!(!req_loop.args.contains_key("id"));
+
+        // test set_sample_cache
+        let req_cache = Request::set_sample_cache(128);
+        assert_eq!(req_cache.name, "set_sample_cache");
+        assert_eq!(
+            req_cache.args.get("memory_budget_mb").map(|s| s.as_str()),
+            Some("128")
+        );
+
+        // test set_bus_volume and set_bus_mic
+        let req_bus = Request::set_bus_volume("obs", 0.5);
+        assert_eq!(req_bus.name, "set_bus_volume");
+        assert_eq!(req_bus.args.get("bus").map(|s| s.as_str()), Some("obs"));
+        assert_eq!(req_bus.args.get("volume").map(|s| s.as_str()), Some("0.5"));
+        let req_bus_mic = Request::set_bus_mic("obs", false);
+        assert_eq!(
+            req_bus_mic.args.get("enabled").map(|s| s.as_str()),
+            Some("false")
+        );
+
+        // test wait_device_events
+        let req_events = Request::wait_device_events(Some(4), None);
+        assert_eq!(req_events.name, "wait_device_events");
+        assert_eq!(req_events.args.get("since").map(|s| s.as_str()), Some("4"));
+        assert!(!req_events.args.contains_key("timeout"));
+        assert!(
+            !Request::wait_device_events(None, Some(10))
+                .args
+                .contains_key("since")
+        );
+
+        // test get_pw_nodes, get_pw_ports and get_pw_links
+        assert_eq!(Request::get_pw_nodes().name, "get_pw_nodes");
+        assert_eq!(Request::get_pw_ports().name, "get_pw_ports");
+        assert!(Request::get_pw_links().args.is_empty());
+
+        // test start_recording and stop_recording
+        let req_record = Request::start_recording(
+            RecordSource::Sounds,
+            Some("obs"),
+            RecordFormat::Flac,
+            Some(SilenceSplit::default()),
+        );
+        assert_eq!(req_record.name, "start_recording");
+        assert_eq!(
+            req_record.args.get("source").map(|s| s.as_str()),
+            Some("sounds")
+        );
+        assert_eq!(
+            req_record.args.get("format").map(|s| s.as_str()),
+            Some("flac")
+        );
+        assert_eq!(req_record.args.get("bus").map(|s| s.as_str()), Some("obs"));
+        assert_eq!(
+            req_record
+                .args
+                .get("split")
+                .and_then(|s| serde_json::from_str::<SilenceSplit>(s).ok()),
+            Some(SilenceSplit::default())
+        );
+        let req_record = Request::start_recording(RecordSource::Mic, None, RecordFormat::Wav, None);
+        assert!(!req_record.args.contains_key("bus"));
+        assert!(!req_record.args.contains_key("split"));
+        assert_eq!(Request::stop_recording().name, "stop_recording");
+
+        // test say and set_tts
+        let req_say = Request::say("hello there", true);
+        assert_eq!(req_say.name, "say");
+        assert_eq!(
+            req_say.args.get("text").map(|s| s.as_str()),
+            Some("hello there")
+        );
+        assert_eq!(
+            req_say.args.get("concurrent").map(|s| s.as_str()),
+            Some("true")
+        );
+        let req_tts = Request::set_tts(Some(TtsEngine::Piper), Some(""), None, Some(-2.0));
+        assert_eq!(req_tts.name, "set_tts");
+        assert_eq!(
+            req_tts
+                .args
+                .get("engine")
+                .and_then(|s| s.parse::<TtsEngine>().ok()),
+            Some(TtsEngine::Piper)
+        );
+        assert_eq!(req_tts.args.get("voice").map(|s| s.as_str()), Some(""));
+        assert_eq!(req_tts.args.get("pitch").map(|s| s.as_str()), Some("-2"));
+        assert!(!req_tts.args.contains_key("rate"));
+
+        // test save_replay and set_replay
+        let req_replay = Request::save_replay(Some(10.0), Some("replay"));
+        assert_eq!(req_replay.name, "save_replay");
+        assert_eq!(
+            req_replay.args.get("seconds").map(|s| s.as_str()),
+            Some("10")
+        );
+        assert_eq!(
+            req_replay.args.get("slot").map(|s| s.as_str()),
+            Some("replay")
+        );
+        assert!(Request::save_replay(None, None).args.is_empty());
+        let req_set_replay = Request::set_replay(Some(true), None, Some("mic"));
+        assert_eq!(req_set_replay.name, "set_replay");
+        assert_eq!(
+            req_set_replay.args.get("enabled").map(|s| s.as_str()),
+            Some("true")
+        );
+        assert_eq!(
+            req_set_replay.args.get("source").map(|s| s.as_str()),
+            Some("mic")
+        );
+        assert!(!req_set_replay.args.contains_key("seconds"));
+
+        // test get_app_streams and set_route_targets
+        assert_eq!(Request::get_app_streams().name, "get_app_streams");
+        let req_routes = Request::set_route_targets(&["discord".to_string(), "obs".to_string()]);
+        assert_eq!(req_routes.name, "set_route_targets");
+        assert_eq!(
+            req_routes.args.get("targets").map(|s| s.as_str()),
+            Some("discord,obs")
+        );
+
+        // test set_input_channels
+        let req_channels = Request::set_input_channels(&["AUX0".to_string(), "AUX1".to_string()]);
+        assert_eq!(req_channels.name, "set_input_channels");
+        assert_eq!(
+            req_channels.args.get("channels").map(|s| s.as_str()),
+            Some("AUX0,AUX1")
+        );
+
+        // test set_speed and set_pitch
+        let req_speed = Request::set_speed(1.5, Some(2));
+        assert_eq!(req_speed.name, "set_speed");
+        assert_eq!(req_speed.args.get("speed").map(|s| s.as_str()), Some("1.5"));
+        assert_eq!(req_speed.args.get("id").map(|s| s.as_str()), Some("2"));
+        let req_pitch = Request::set_pitch(-3.0, None);
+        assert_eq!(req_pitch.name, "set_pitch");
+        assert_eq!(req_pitch.args.get("pitch").map(|s| s.as_str()), Some("-3"));
+        assert!(!req_pitch.args.contains_key("id"));
+
+        // test set_silence_trim
+        let req_silence = Request::set_silence_trim(None, Some(-45.0));
+        assert_eq!(req_silence.name, "set_silence_trim");
+        assert_eq!(
+            req_silence.args.get("threshold_db").map(|s| s.as_str()),
+            Some("-45")
+        );
+        assert!(!req_silence.args.contains_key("enabled"));
+
+        // test update_sound_settings
+        let changes = serde_json::json!({"fade_in": 0.5});
+        let req_sound = Request::update_sound_settings("/a.mp3", changes.as_object().unwrap());
+        assert_eq!(req_sound.name, "set_sound_settings");
+        assert_eq!(
+            req_sound.args.get("settings").map(|s| s.as_str()),
+            Some(r#"{"fade_in":0.5}"#)
+        );
+
         // test set_volume
         let req_volume = Request::set_volume(0.8, Some(10));
         assert_eq!(req_volume.name, "set_volume");
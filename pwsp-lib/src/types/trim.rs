@@ -0,0 +1,436 @@
+use crate::types::{
+    config::SoundSettings, limiter::db_to_gain, sample_cache::DecodedSound, waveform::Waveform,
+};
+use anyhow::Result;
+use rodio::{
+    ChannelCount, Decoder, DeviceSinkBuilder, MixerDeviceSink, Player, SampleRate, Source,
+    source::SeekError,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+/// Longest silence skipped at the head of a file that was not analyzed yet
+pub const MAX_LEADING_SILENCE: Duration = Duration::from_secs(10);
+
+/// Daemon-wide leading/trailing silence trimming, sounds can override `enabled`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SilenceTrimSettings {
+    pub enabled: bool,
+    /// Anything quieter than this is considered silence, in dBFS
+    pub threshold_db: f32,
+}
+
+impl Default for SilenceTrimSettings {
+    fn default() -> Self {
+        SilenceTrimSettings {
+            enabled: false,
+            threshold_db: -50.0,
+        }
+    }
+}
+
+/// Plays only the `start..end` part of a source, with optional linear fades at both ends.
+///
+/// Positions seen from the outside are relative to `start`.
+pub struct Trimmed<S: Source> {
+    input: S,
+    channels: u64,
+    sample_rate: u64,
+    start: Duration,
+    /// Frames to play, unknown if neither `end` nor the input duration is known
+    length: Option<u64>,
+    fade_in: u64,
+    fade_out: u64,
+    /// Interleaved samples played so far
+    played: u64,
+}
+
+impl<S: Source> Trimmed<S> {
+    pub fn new(
+        mut input: S,
+        start: Duration,
+        end: Option<Duration>,
+        fade_in: Duration,
+        fade_out: Duration,
+    ) -> Self {
+        let channels = (u16::from(input.channels()) as u64).max(1);
+        let sample_rate = (u32::from(input.sample_rate()) as u64).max(1);
+        let to_frames = |d: Duration| (d.as_secs_f64() * sample_rate as f64).round() as u64;
+
+        if !start.is_zero() && input.try_seek(start).is_err() {
+            // Not every decoder can seek, skip the head by hand
+            for _ in 0..to_frames(start) * channels {
+                if input.next().is_none() {
+                    break;
+                }
+            }
+        }
+
+        let end = end.or(input.total_duration());
+        let length = end.map(|end| to_frames(end.saturating_sub(start)));
+
+        Trimmed {
+            input,
+            channels,
+            sample_rate,
+            start,
+            length,
+            fade_in: to_frames(fade_in),
+            fade_out: to_frames(fade_out),
+            played: 0,
+        }
+    }
+
+    fn gain(&self, frame: u64) -> f32 {
+        let mut gain = 1.0;
+        if frame < self.fade_in {
+            gain *= frame as f32 / self.fade_in as f32;
+        }
+        if let Some(length) = self.length {
+            let remaining = length.saturating_sub(frame);
+            if remaining < self.fade_out {
+                gain *= remaining as f32 / self.fade_out as f32;
+            }
+        }
+        gain
+    }
+}
+
+impl<S: Source> Iterator for Trimmed<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let frame = self.played / self.channels;
+        if self.length.is_some_and(|length| frame >= length) {
+            return None;
+        }
+
+        let sample = self.input.next()?;
+        self.played += 1;
+        Some(sample * self.gain(frame))
+    }
+}
+
+impl<S: Source> Source for Trimmed<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.length
+            .map(|length| Duration::from_secs_f64(length as f64 / self.sample_rate as f64))
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(self.start + pos)?;
+        let frame = (pos.as_secs_f64() * self.sample_rate as f64).round() as u64;
+        self.played = frame * self.channels;
+        // Only the first start fades in, loops and seeks come back at full level
+        self.fade_in = 0;
+        Ok(())
+    }
+}
+
+/// Narrows the trim of `settings` to the audible part of `waveform`, never widening it.
+pub fn trim_silence(
+    settings: &SoundSettings,
+    waveform: &Waveform,
+    threshold_db: f32,
+) -> SoundSettings {
+    let mut settings = settings.clone();
+    let Some((start, end)) = waveform.audible_range(db_to_gain(threshold_db)) else {
+        return settings;
+    };
+
+    settings.start = settings.start.max(start);
+    if end < waveform.duration {
+        settings.end = Some(settings.end.map_or(end, |e| e.min(end)));
+    }
+    if settings.end.is_some_and(|end| end <= settings.start) {
+        return SoundSettings {
+            start: 0.0,
+            end: None,
+            ..settings
+        };
+    }
+    settings
+}
+
+/// Sound settings with silence trimming applied if it is enabled for this sound.
+///
+/// Uses the cached waveform. A file that was not analyzed yet is analyzed in the background
+/// for the next play, this one only skips the silence at its head, which is read from the
+/// decoder up to [`MAX_LEADING_SILENCE`].
+pub fn playback_settings(
+    path: &Path,
+    settings: &SoundSettings,
+    silence: &SilenceTrimSettings,
+) -> SoundSettings {
+    if !settings.trim_silence.unwrap_or(silence.enabled) {
+        return settings.clone();
+    }
+
+    if let Some(waveform) = Waveform::cached(path) {
+        return trim_silence(settings, &waveform, silence.threshold_db);
+    }
+    analyze_in_background(path);
+
+    match leading_silence(path, settings, silence.threshold_db) {
+        Ok(skip) => {
+            let start = settings.start.max(0.0) + skip.as_secs_f32();
+            if settings.end.is_some_and(|end| end <= start) {
+                return settings.clone();
+            }
+            SoundSettings {
+                start,
+                ..settings.clone()
+            }
+        }
+        Err(err) => {
+            eprintln!("Failed to analyze silence of {}: {}", path.display(), err);
+            settings.clone()
+        }
+    }
+}
+
+/// How much silence follows the trim start of a file, zero when nothing audible comes
+/// within [`MAX_LEADING_SILENCE`].
+fn leading_silence(path: &Path, settings: &SoundSettings, threshold_db: f32) -> Result<Duration> {
+    let file = fs::File::open(path)?;
+    let mut decoder = Decoder::try_from(file)?;
+    let sample_rate = u32::from(decoder.sample_rate()).max(1);
+    let channels = u16::from(decoder.channels());
+
+    let start = Duration::from_secs_f32(settings.start.max(0.0));
+    if !start.is_zero() && decoder.try_seek(start).is_err() {
+        let skip = (start.as_secs_f64() * sample_rate as f64) as usize * channels.max(1) as usize;
+        decoder.by_ref().take(skip).for_each(drop);
+    }
+    let max_frames = (MAX_LEADING_SILENCE.as_secs_f64() * sample_rate as f64) as u64;
+    Ok(
+        silent_frames(decoder, channels, db_to_gain(threshold_db), max_frames)
+            .map(|frames| Duration::from_secs_f64(frames as f64 / sample_rate as f64))
+            .unwrap_or_default(),
+    )
+}
+
+/// Frames before the first one whose channel mix is louder than `threshold`, `None` if there
+/// is none within `max_frames`.
+fn silent_frames(
+    samples: impl Iterator<Item = f32>,
+    channels: u16,
+    threshold: f32,
+    max_frames: u64,
+) -> Option<u64> {
+    let channels = channels.max(1) as usize;
+    let mut samples = samples.peekable();
+    let mut frames = 0;
+    while frames < max_frames {
+        samples.peek()?;
+        let mix = samples.by_ref().take(channels).sum::<f32>() / channels as f32;
+        if mix.abs() > threshold {
+            return Some(frames);
+        }
+        frames += 1;
+    }
+    None
+}
+
+/// Computes and caches the waveform of `path` on the blocking pool, once at a time per file.
+fn analyze_in_background(path: &Path) {
+    static ANALYZING: OnceLock<Mutex<HashSet<PathBuf>>> = OnceLock::new();
+    let analyzing = || {
+        ANALYZING
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    };
+
+    let path = path.to_path_buf();
+    if !analyzing().insert(path.clone()) {
+        return;
+    }
+
+    tokio::task::spawn_blocking(move || {
+        if let Err(err) = Waveform::load(&path) {
+            eprintln!("Failed to analyze silence of {}: {}", path.display(), err);
+        }
+        analyzing().remove(&path);
+    });
+}
+
+/// Like [`playback_settings`] for a sound that is already decoded in memory.
+pub fn cached_playback_settings(
+    sound: &DecodedSound,
+    settings: &SoundSettings,
+    silence: &SilenceTrimSettings,
+) -> SoundSettings {
+    if !settings.trim_silence.unwrap_or(silence.enabled) {
+        return settings.clone();
+    }
+    trim_silence(settings, &sound.waveform, silence.threshold_db)
+}
+
+/// Applies the trim and fades from sound settings to a source.
+pub fn trimmed<S: Source>(source: S, settings: &SoundSettings) -> Trimmed<S> {
+    let secs = |s: f32| Duration::from_secs_f32(s.max(0.0));
+    Trimmed::new(
+        source,
+        secs(settings.start),
+        settings.end.map(secs),
+        secs(settings.fade_in),
+        secs(settings.fade_out),
+    )
+}
+
+/// Decodes a file and applies the trim and fades from its sound settings.
+pub fn open_sound(path: &Path, settings: &SoundSettings) -> Result<Trimmed<Decoder<fs::File>>> {
+    let file = fs::File::open(path)?;
+    let decoder = Decoder::try_from(file)?;
+    Ok(trimmed(decoder, settings))
+}
+
+/// Plays a sound on the default output only, so a trim can be auditioned without
+/// sending it to the virtual microphone.
+pub struct Preview {
+    _stream: MixerDeviceSink,
+    player: Player,
+}
+
+impl Preview {
+    pub fn start(path: &Path, settings: &SoundSettings) -> Result<Self> {
+        let source = open_sound(path, settings)?;
+
+        let mut stream = DeviceSinkBuilder::open_default_sink()?;
+        stream.log_on_drop(false);
+
+        let player = Player::connect_new(stream.mixer());
+        player.append(source);
+        player.play();
+
+        Ok(Preview {
+            _stream: stream,
+            player,
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.player.empty()
+    }
+
+    /// Position relative to the trimmed start
+    pub fn position(&self) -> Duration {
+        self.player.get_pos()
+    }
+}
+
+impl fmt::Debug for Preview {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Preview")
+            .field("position", &self.position())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn buffer(len: usize) -> SamplesBuffer {
+        let channels: ChannelCount = 1u16.try_into().unwrap();
+        let sample_rate: SampleRate = 10u32.try_into().unwrap();
+        SamplesBuffer::new(channels, sample_rate, vec![1.0; len])
+    }
+
+    #[test]
+    fn test_trim_start_and_end() {
+        let trimmed = Trimmed::new(
+            buffer(100),
+            Duration::from_secs(2),
+            Some(Duration::from_secs(5)),
+            Duration::ZERO,
+            Duration::ZERO,
+        );
+        assert_eq!(trimmed.total_duration(), Some(Duration::from_secs(3)));
+        assert_eq!(trimmed.count(), 30);
+    }
+
+    #[test]
+    fn test_fades() {
+        let trimmed = Trimmed::new(
+            buffer(40),
+            Duration::ZERO,
+            None,
+            Duration::from_secs(1),
+            Duration::from_secs(1),
+        );
+        let samples: Vec<f32> = trimmed.collect();
+        assert_eq!(samples.len(), 40);
+        assert_eq!(samples[0], 0.0);
+        assert_eq!(samples[5], 0.5);
+        assert_eq!(samples[20], 1.0);
+        assert_eq!(samples[35], 0.5);
+        assert_eq!(samples[39], 0.1);
+    }
+
+    #[test]
+    fn test_fade_in_only_on_first_start() {
+        let mut trimmed = Trimmed::new(
+            buffer(40),
+            Duration::ZERO,
+            None,
+            Duration::from_secs(1),
+            Duration::ZERO,
+        );
+        assert_eq!(trimmed.next(), Some(0.0));
+
+        trimmed.try_seek(Duration::ZERO).unwrap();
+        assert_eq!(trimmed.next(), Some(1.0));
+    }
+
+    #[test]
+    fn test_trim_silence() {
+        let waveform = Waveform {
+            duration: 0.05,
+            peaks_per_second: 100,
+            peaks: vec![(0.0, 0.0), (-0.5, 0.5), (-0.5, 0.5), (0.0, 0.0), (0.0, 0.0)],
+        };
+
+        let trimmed = trim_silence(&SoundSettings::default(), &waveform, -50.0);
+        assert!((trimmed.start - 0.01).abs() < 1e-6);
+        assert!((trimmed.end.unwrap() - 0.03).abs() < 1e-6);
+
+        // A manual trim that is already tighter wins
+        let manual = SoundSettings {
+            start: 0.02,
+            end: Some(0.025),
+            ..Default::default()
+        };
+        assert_eq!(trim_silence(&manual, &waveform, -50.0), manual);
+    }
+
+    #[test]
+    fn test_silent_frames() {
+        let samples = [0.0, 0.001, 0.0, -0.002, 0.4, 0.0, 0.0, 0.0];
+        assert_eq!(silent_frames(samples.into_iter(), 2, 0.01, 100), Some(2));
+        assert_eq!(silent_frames(samples.into_iter(), 1, 0.01, 100), Some(4));
+        assert_eq!(silent_frames(samples.into_iter(), 1, 0.01, 3), None);
+        assert_eq!(silent_frames([0.0; 6].into_iter(), 2, 0.01, 100), None);
+    }
+}
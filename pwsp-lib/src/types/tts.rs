@@ -0,0 +1,300 @@
+use crate::utils::hash::fnv1a;
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
+    time::Duration,
+};
+use tokio::io::AsyncWriteExt;
+
+/// Speaking rate range accepted by `set_tts`
+pub const MIN_TTS_RATE: f32 = 0.25;
+pub const MAX_TTS_RATE: f32 = 4.0;
+/// Longest text `say` speaks, in characters
+pub const MAX_TTS_TEXT: usize = 2000;
+/// Spoken clips kept around so repeated phrases play right away
+const MAX_CACHED_CLIPS: usize = 32;
+const SYNTHESIS_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TtsEngine {
+    #[default]
+    EspeakNg,
+    Piper,
+    RhVoice,
+}
+
+impl TtsEngine {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TtsEngine::EspeakNg => "espeak-ng",
+            TtsEngine::Piper => "piper",
+            TtsEngine::RhVoice => "rhvoice",
+        }
+    }
+
+    fn program(&self) -> &'static str {
+        match self {
+            TtsEngine::EspeakNg => "espeak-ng",
+            TtsEngine::Piper => "piper",
+            TtsEngine::RhVoice => "RHVoice-test",
+        }
+    }
+}
+
+impl FromStr for TtsEngine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('_', "-").as_str() {
+            "espeak-ng" | "espeak" => Ok(TtsEngine::EspeakNg),
+            "piper" => Ok(TtsEngine::Piper),
+            "rhvoice" => Ok(TtsEngine::RhVoice),
+            other => Err(format!("Unknown TTS engine: {}", other)),
+        }
+    }
+}
+
+/// Local speech synthesis behind `say`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TtsSettings {
+    pub engine: TtsEngine,
+    /// Voice of espeak-ng or RHVoice, path to the `.onnx` model for piper.
+    /// The engine default when unset
+    pub voice: Option<String>,
+    /// Speaking rate, 1.0 is the normal speed of the engine
+    pub rate: f32,
+    /// Pitch shift in semitones, applied by the player so it works with every engine
+    pub pitch: f32,
+}
+
+impl Default for TtsSettings {
+    fn default() -> Self {
+        TtsSettings {
+            engine: TtsEngine::EspeakNg,
+            voice: None,
+            rate: 1.0,
+            pitch: 0.0,
+        }
+    }
+}
+
+impl TtsSettings {
+    fn rate(&self) -> f32 {
+        self.rate.clamp(MIN_TTS_RATE, MAX_TTS_RATE)
+    }
+
+    /// Engine invocation that reads the text from stdin and writes a WAV to `output`.
+    pub fn command(&self, output: &Path) -> Result<Command> {
+        let mut command = Command::new(self.engine.program());
+        match self.engine {
+            TtsEngine::EspeakNg => {
+                command
+                    .arg("--stdin")
+                    .arg("-w")
+                    .arg(output)
+                    .args(["-s", &((175.0 * self.rate()).round() as u32).to_string()]);
+                if let Some(voice) = &self.voice {
+                    command.args(["-v", voice]);
+                }
+            }
+            TtsEngine::Piper => {
+                let model = self.voice.as_ref().ok_or_else(|| {
+                    anyhow!("piper needs a voice model, set the TTS voice to a .onnx file")
+                })?;
+                command
+                    .arg("--model")
+                    .arg(model)
+                    .arg("--output_file")
+                    .arg(output)
+                    .args(["--length_scale", &(1.0 / self.rate()).to_string()]);
+            }
+            TtsEngine::RhVoice => {
+                command
+                    .arg("-o")
+                    .arg(output)
+                    .args(["-r", &((100.0 * self.rate()).round() as u32).to_string()]);
+                if let Some(voice) = &self.voice {
+                    command.args(["-p", voice]);
+                }
+            }
+        }
+        Ok(command)
+    }
+
+    /// File name the speech for `text` is cached under, the pitch is left out as the player
+    /// applies it. FNV-1a keeps the names the same across builds, so the cache survives updates.
+    pub fn clip_name(&self, text: &str) -> String {
+        let voice = self.voice.as_deref().unwrap_or_default();
+        let hash = fnv1a(&[
+            self.engine.as_str().as_bytes(),
+            voice.as_bytes(),
+            &self.rate().to_bits().to_le_bytes(),
+            text.as_bytes(),
+        ]);
+        format!("say-{:016x}.wav", hash)
+    }
+}
+
+/// Speaks `text` into a WAV in `dir`, reusing the clip when the same text was spoken before.
+pub async fn synthesize(settings: &TtsSettings, text: &str, dir: &Path) -> Result<PathBuf> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(anyhow!("Nothing to say"));
+    }
+    if text.chars().count() > MAX_TTS_TEXT {
+        return Err(anyhow!("Text is longer than {} characters", MAX_TTS_TEXT));
+    }
+
+    let path = dir.join(settings.clip_name(text));
+    if path.exists() {
+        return Ok(path);
+    }
+    fs::create_dir_all(dir)?;
+
+    // Engines may leave half a file behind when they fail
+    let partial = path.with_extension("part");
+    let mut child = match tokio::process::Command::from(settings.command(&partial)?)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            return Err(anyhow!(
+                "{} is not installed, install it or pick another TTS engine",
+                settings.engine.program()
+            ));
+        }
+        Err(err) => return Err(err.into()),
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // An engine that fails early closes stdin, its exit status tells what went wrong
+        stdin.write_all(text.as_bytes()).await.ok();
+    }
+
+    let output = tokio::time::timeout(SYNTHESIS_TIMEOUT, child.wait_with_output())
+        .await
+        .context("Speech synthesis timed out")??;
+    if !output.status.success() || !partial.exists() {
+        fs::remove_file(&partial).ok();
+        return Err(anyhow!(
+            "{} failed: {}",
+            settings.engine.program(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    fs::rename(&partial, &path)?;
+
+    prune_clips(dir);
+    Ok(path)
+}
+
+/// Drops the oldest spoken clips beyond `MAX_CACHED_CLIPS`.
+fn prune_clips(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut clips: Vec<(std::time::SystemTime, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "wav"))
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .collect();
+    if clips.len() <= MAX_CACHED_CLIPS {
+        return;
+    }
+
+    clips.sort();
+    for (_, path) in &clips[..clips.len() - MAX_CACHED_CLIPS] {
+        fs::remove_file(path).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(command: &Command) -> Vec<String> {
+        command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_tts_commands() {
+        let output = Path::new("/tmp/out.wav");
+        let mut settings = TtsSettings {
+            rate: 2.0,
+            voice: Some("en-us".to_string()),
+            ..Default::default()
+        };
+        let command = settings.command(output).unwrap();
+        assert_eq!(command.get_program(), "espeak-ng");
+        assert_eq!(
+            args(&command),
+            vec!["--stdin", "-w", "/tmp/out.wav", "-s", "350", "-v", "en-us"]
+        );
+
+        settings.engine = TtsEngine::RhVoice;
+        settings.rate = 100.0;
+        let command = settings.command(output).unwrap();
+        assert_eq!(command.get_program(), "RHVoice-test");
+        assert_eq!(
+            args(&command),
+            vec!["-o", "/tmp/out.wav", "-r", "400", "-p", "en-us"]
+        );
+
+        settings.engine = TtsEngine::Piper;
+        settings.rate = 0.5;
+        settings.voice = Some("/voices/en.onnx".to_string());
+        assert_eq!(
+            args(&settings.command(output).unwrap()),
+            vec![
+                "--model",
+                "/voices/en.onnx",
+                "--output_file",
+                "/tmp/out.wav",
+                "--length_scale",
+                "2"
+            ]
+        );
+        settings.voice = None;
+        assert!(settings.command(output).is_err());
+    }
+
+    #[test]
+    fn test_tts_clip_name() {
+        let settings = TtsSettings::default();
+        // Stable across builds and Rust versions
+        assert_eq!(settings.clip_name("hello"), "say-2fbe1ab07aa5f1c1.wav");
+        assert_ne!(
+            settings.clip_name("hello"),
+            settings.clip_name("hello there")
+        );
+
+        let faster = TtsSettings {
+            rate: 1.5,
+            ..Default::default()
+        };
+        assert_ne!(settings.clip_name("hello"), faster.clip_name("hello"));
+
+        let higher = TtsSettings {
+            pitch: 3.0,
+            ..Default::default()
+        };
+        assert_eq!(settings.clip_name("hello"), higher.clip_name("hello"));
+
+        assert_eq!("espeak".parse::<TtsEngine>(), Ok(TtsEngine::EspeakNg));
+        assert_eq!("RHVoice".parse::<TtsEngine>(), Ok(TtsEngine::RhVoice));
+        assert!("festival".parse::<TtsEngine>().is_err());
+    }
+}
@@ -0,0 +1,559 @@
+use crate::types::{
+    effects::{Effect, EffectChain},
+    pitch::{MAX_PITCH_SEMITONES, PitchShifter, semitones_to_ratio},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    f32::consts::{PI, TAU},
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+/// Formant shift is limited to an octave either way
+pub const MAX_FORMANT_SEMITONES: f32 = 12.0;
+
+/// Range of voice pitches the formant shifter looks for
+const MIN_VOICE_HZ: f32 = 60.0;
+const MAX_VOICE_HZ: f32 = 400.0;
+/// Period assumed until a voice is detected
+const DEFAULT_VOICE_HZ: f32 = 120.0;
+/// How often the voice period is estimated again
+const DETECT_INTERVAL: Duration = Duration::from_millis(20);
+
+/// One way of changing the microphone voice.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct VoicePreset {
+    /// Pitch shift in semitones
+    pub pitch: f32,
+    /// Formant shift in semitones, changes the timbre but not the pitch
+    pub formant: f32,
+    /// Ring modulator frequency in Hz for robotic voices, 0 disables it
+    pub ring_mod_hz: f32,
+    pub ring_mod_mix: f32,
+    /// Applied after the voice processing, e.g. a reverb
+    pub effects: Vec<Effect>,
+}
+
+impl Default for VoicePreset {
+    fn default() -> Self {
+        VoicePreset {
+            pitch: 0.0,
+            formant: 0.0,
+            ring_mod_hz: 0.0,
+            ring_mod_mix: 1.0,
+            effects: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct VoiceChangerSettings {
+    pub enabled: bool,
+    /// Preset used while enabled
+    pub preset: Option<String>,
+    pub presets: HashMap<String, VoicePreset>,
+}
+
+impl Default for VoiceChangerSettings {
+    fn default() -> Self {
+        VoiceChangerSettings {
+            enabled: false,
+            preset: None,
+            presets: builtin_presets(),
+        }
+    }
+}
+
+impl VoiceChangerSettings {
+    /// Preset the microphone goes through right now, `None` passes it through untouched.
+    pub fn active(&self) -> Option<VoicePreset> {
+        if !self.enabled {
+            return None;
+        }
+        self.preset
+            .as_ref()
+            .and_then(|name| self.presets.get(name))
+            .cloned()
+    }
+}
+
+fn builtin_presets() -> HashMap<String, VoicePreset> {
+    HashMap::from([
+        (
+            "chipmunk".to_string(),
+            VoicePreset {
+                pitch: 7.0,
+                formant: 4.0,
+                ..Default::default()
+            },
+        ),
+        (
+            "deep".to_string(),
+            VoicePreset {
+                pitch: -5.0,
+                formant: -3.0,
+                ..Default::default()
+            },
+        ),
+        (
+            "robot".to_string(),
+            VoicePreset {
+                ring_mod_hz: 50.0,
+                effects: vec![Effect::Delay {
+                    time_ms: 8.0,
+                    feedback: 0.5,
+                    mix: 0.3,
+                }],
+                ..Default::default()
+            },
+        ),
+        (
+            "cave".to_string(),
+            VoicePreset {
+                effects: vec![Effect::Reverb {
+                    room_size: 0.9,
+                    damping: 0.3,
+                    mix: 0.4,
+                }],
+                ..Default::default()
+            },
+        ),
+    ])
+}
+
+/// Active voice preset, swapped by the daemon and picked up by the PipeWire thread.
+#[derive(Debug, Default)]
+pub struct VoiceChangerControl {
+    preset: Mutex<Option<VoicePreset>>,
+    version: AtomicU64,
+    /// Voice changers waiting for a new preset, dead ones are pruned on the next change
+    slots: Mutex<Vec<Weak<VoiceSlot>>>,
+}
+
+/// Processing state built for one voice changer. Once swapped in, the replaced state waits
+/// here until the next change drops it off the audio thread.
+#[derive(Debug)]
+struct VoiceSlot {
+    sample_rate: f32,
+    channels: usize,
+    pending: Mutex<(u64, VoiceProcessor)>,
+}
+
+impl VoiceChangerControl {
+    pub fn new(preset: Option<VoicePreset>) -> Self {
+        VoiceChangerControl {
+            preset: Mutex::new(preset),
+            ..Default::default()
+        }
+    }
+
+    pub fn preset(&self) -> Option<VoicePreset> {
+        self.preset
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// The microphone goes through the voice changer rather than straight to the virtual mics
+    pub fn is_active(&self) -> bool {
+        self.preset
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_some()
+    }
+
+    pub fn set_preset(&self, preset: Option<VoicePreset>) {
+        let mut current = self.preset.lock().unwrap_or_else(|e| e.into_inner());
+        *current = preset;
+        let version = self.version.fetch_add(1, Ordering::Release) + 1;
+
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        slots.retain(|slot| slot.strong_count() > 0);
+        for slot in slots.iter().filter_map(Weak::upgrade) {
+            let voice = VoiceProcessor::new(current.clone(), slot.sample_rate, slot.channels);
+            *slot.pending.lock().unwrap_or_else(|e| e.into_inner()) = (version, voice);
+        }
+    }
+
+    /// State for a new voice changer of the given format and the slot later presets arrive in.
+    fn subscribe(
+        &self,
+        sample_rate: f32,
+        channels: usize,
+    ) -> (u64, VoiceProcessor, Arc<VoiceSlot>) {
+        let preset = self.preset.lock().unwrap_or_else(|e| e.into_inner());
+        let version = self.version.load(Ordering::Acquire);
+        let slot = Arc::new(VoiceSlot {
+            sample_rate,
+            channels,
+            pending: Mutex::new((version, VoiceProcessor::new(None, sample_rate, channels))),
+        });
+        self.slots
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Arc::downgrade(&slot));
+        (
+            version,
+            VoiceProcessor::new(preset.clone(), sample_rate, channels),
+            slot,
+        )
+    }
+}
+
+/// Moves the formants of a voice without changing its pitch.
+///
+/// Grains one voice period long are resampled by the formant ratio and overlap-added
+/// at the original period, a crude PSOLA. Output lags the input by a fixed delay.
+#[derive(Debug)]
+struct FormantShifter {
+    history: Vec<f32>,
+    output: Vec<f32>,
+    mask: usize,
+    /// Samples written so far
+    time: i64,
+    /// Center of the next grain
+    next_grain: i64,
+    period: usize,
+    min_period: usize,
+    max_period: usize,
+    delay: i64,
+    detect_interval: i64,
+    next_detect: i64,
+    scores: Vec<f32>,
+}
+
+impl FormantShifter {
+    fn new(sample_rate: f32) -> Self {
+        let min_period = ((sample_rate / MAX_VOICE_HZ) as usize).max(2);
+        let max_period = ((sample_rate / MIN_VOICE_HZ) as usize).max(min_period + 1);
+        let delay = Self::lookahead(max_period) + max_period as i64 + 1;
+        let size = (delay as usize + 4 * max_period + 4).next_power_of_two();
+
+        FormantShifter {
+            history: vec![0.0; size],
+            output: vec![0.0; size],
+            mask: size - 1,
+            time: 0,
+            next_grain: max_period as i64,
+            period: ((sample_rate / DEFAULT_VOICE_HZ) as usize).clamp(min_period, max_period),
+            min_period,
+            max_period,
+            delay,
+            detect_interval: ((sample_rate * DETECT_INTERVAL.as_secs_f32()) as i64).max(1),
+            next_detect: 0,
+            scores: vec![0.0; max_period + 2],
+        }
+    }
+
+    /// Input needed past the center of a grain read at the highest formant ratio
+    fn lookahead(period: usize) -> i64 {
+        (period as f32 * semitones_to_ratio(MAX_FORMANT_SEMITONES)).ceil() as i64 + 2
+    }
+
+    fn at(&self, time: i64) -> f32 {
+        self.history[time as usize & self.mask]
+    }
+
+    /// Takes the first strong autocorrelation peak as the voice period, keeps the
+    /// previous period for silence and unvoiced sounds.
+    fn detect(&mut self) {
+        let window = self.max_period as i64;
+        let energy: f32 = (0..window)
+            .step_by(2)
+            .map(|i| self.at(self.time - i).powi(2))
+            .sum();
+        if energy < 1e-6 * window as f32 {
+            return;
+        }
+
+        let mut best = 0f32;
+        for lag in self.min_period..=self.max_period {
+            let (mut cross, mut lagged) = (0f32, 0f32);
+            for i in (0..window).step_by(2) {
+                let b = self.at(self.time - i - lag as i64);
+                cross += self.at(self.time - i) * b;
+                lagged += b * b;
+            }
+            let score = cross / (energy * lagged).sqrt().max(1e-9);
+            self.scores[lag] = score;
+            best = best.max(score);
+        }
+        if best < 0.5 {
+            return;
+        }
+
+        let is_peak = |lag: usize| {
+            self.scores[lag] >= 0.9 * best
+                && self.scores[lag] >= self.scores[lag - 1]
+                && self.scores[lag] >= self.scores[lag + 1]
+        };
+        if let Some(period) = (self.min_period + 1..self.max_period).find(|&lag| is_peak(lag)) {
+            self.period = period;
+        }
+    }
+
+    fn add_grain(&mut self, ratio: f32) {
+        let center = self.next_grain;
+        let period = self.period as i64;
+
+        for k in -period..period {
+            // Periodic Hann windows at a hop of one period add up to one
+            let weight = 0.5 - 0.5 * (PI * (k + period) as f32 / period as f32).cos();
+            let offset = k as f32 * ratio;
+            let base = offset.floor();
+            let fraction = offset - base;
+            let read = center + base as i64;
+            let (a, b) = (self.at(read), self.at(read + 1));
+            self.output[(center + k) as usize & self.mask] += weight * (a + (b - a) * fraction);
+        }
+        self.next_grain += period;
+    }
+
+    fn process(&mut self, sample: f32, ratio: f32) -> f32 {
+        let time = self.time;
+        self.history[time as usize & self.mask] = sample;
+
+        if time >= self.next_detect {
+            self.detect();
+            self.next_detect = time + self.detect_interval;
+        }
+        while self.next_grain + Self::lookahead(self.period) <= time {
+            self.add_grain(ratio);
+        }
+        self.time += 1;
+
+        let out = time - self.delay;
+        if out < 0 {
+            return 0.0;
+        }
+        std::mem::take(&mut self.output[out as usize & self.mask])
+    }
+}
+
+/// The preset of a voice changer and the processors running it.
+#[derive(Debug)]
+struct VoiceProcessor {
+    preset: Option<VoicePreset>,
+    formant: FormantShifter,
+    pitch: PitchShifter,
+    ring_phase: f32,
+    effects: EffectChain,
+}
+
+impl VoiceProcessor {
+    fn new(preset: Option<VoicePreset>, sample_rate: f32, channels: usize) -> Self {
+        let effects = preset.as_ref().map(|p| p.effects.as_slice());
+        VoiceProcessor {
+            effects: EffectChain::new(effects.unwrap_or_default(), sample_rate, channels),
+            preset,
+            formant: FormantShifter::new(sample_rate),
+            pitch: PitchShifter::new(sample_rate, 1),
+            ring_phase: 0.0,
+        }
+    }
+}
+
+/// Voice changer on the microphone passthrough.
+///
+/// The voice is mixed down to mono, formant and pitch shifted and ring modulated,
+/// then copied to every channel and sent through the effects of the preset.
+/// Without a preset the samples are left untouched.
+pub struct VoiceChanger {
+    control: Arc<VoiceChangerControl>,
+    version: u64,
+    slot: Arc<VoiceSlot>,
+    sample_rate: f32,
+    channels: usize,
+    voice: VoiceProcessor,
+}
+
+impl VoiceChanger {
+    pub fn new(control: Arc<VoiceChangerControl>, sample_rate: u32, channels: u32) -> Self {
+        let sample_rate = sample_rate as f32;
+        let channels = (channels as usize).max(1);
+        let (version, voice, slot) = control.subscribe(sample_rate, channels);
+        VoiceChanger {
+            control,
+            version,
+            slot,
+            sample_rate,
+            channels,
+            voice,
+        }
+    }
+
+    /// Swaps in the state the daemon built for a changed preset, trying again on the next
+    /// call while it is still building it.
+    fn pick_up_preset(&mut self) {
+        if self.control.version.load(Ordering::Acquire) == self.version {
+            return;
+        }
+        if let Ok(mut pending) = self.slot.pending.try_lock()
+            && pending.0 != self.version
+        {
+            std::mem::swap(&mut self.voice, &mut pending.1);
+            self.version = pending.0;
+        }
+    }
+
+    /// Processes interleaved samples in place, returns false when no preset is active and
+    /// the samples were left untouched.
+    pub fn process(&mut self, samples: &mut [f32]) -> bool {
+        self.pick_up_preset();
+        let voice = &mut self.voice;
+        let Some(preset) = &voice.preset else {
+            return false;
+        };
+
+        let formant = semitones_to_ratio(
+            preset
+                .formant
+                .clamp(-MAX_FORMANT_SEMITONES, MAX_FORMANT_SEMITONES),
+        );
+        let pitch = preset
+            .pitch
+            .clamp(-MAX_PITCH_SEMITONES, MAX_PITCH_SEMITONES);
+        let ring_hz = preset.ring_mod_hz.max(0.0);
+        let ring_mix = preset.ring_mod_mix.clamp(0.0, 1.0);
+
+        for frame in samples.chunks_exact_mut(self.channels) {
+            let mut sample = [frame.iter().sum::<f32>() / self.channels as f32];
+            if formant != 1.0 {
+                sample[0] = voice.formant.process(sample[0], formant);
+            }
+            voice.pitch.process(&mut sample, pitch);
+            if ring_hz > 0.0 {
+                let carrier = (voice.ring_phase * TAU).sin();
+                sample[0] *= 1.0 - ring_mix + ring_mix * carrier;
+                voice.ring_phase = (voice.ring_phase + ring_hz / self.sample_rate).fract();
+            }
+
+            frame.fill(sample[0]);
+            voice.effects.process(frame);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (i as f32 * frequency * TAU / 8000.0).sin())
+            .collect()
+    }
+
+    fn correlation(a: &[f32], b: &[f32]) -> f32 {
+        let cross: f32 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+        let energy: f32 =
+            a.iter().map(|a| a * a).sum::<f32>() * b.iter().map(|b| b * b).sum::<f32>();
+        cross / energy.sqrt()
+    }
+
+    fn run(preset: Option<VoicePreset>, samples: &mut [f32], channels: u32) {
+        let control = Arc::new(VoiceChangerControl::new(preset));
+        VoiceChanger::new(control, 8000, channels).process(samples);
+    }
+
+    #[test]
+    fn test_no_preset_passes_through() {
+        let input: Vec<f32> = (0..200).map(|i| i as f32 / 200.0).collect();
+        let mut output = input.clone();
+        run(None, &mut output, 2);
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn test_formant_shifter_without_shift_only_delays() {
+        let input = sine(200.0, 4000);
+        let mut shifter = FormantShifter::new(8000.0);
+        let output: Vec<f32> = input.iter().map(|&s| shifter.process(s, 1.0)).collect();
+
+        let delay = shifter.delay as usize;
+        for t in delay + 1000..input.len() {
+            assert!((output[t] - input[t - delay]).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_formant_shift_keeps_pitch() {
+        let input = sine(200.0, 4000);
+        let mut shifter = FormantShifter::new(8000.0);
+        let output: Vec<f32> = input
+            .iter()
+            .map(|&s| shifter.process(s, semitones_to_ratio(7.0)))
+            .collect();
+
+        // Still periodic at 40 samples (200 Hz), but no longer the same waveform
+        let settled = &output[2000..];
+        assert!(correlation(&settled[..1000], &settled[40..1040]) > 0.95);
+
+        let delay = shifter.delay as usize;
+        let delayed = &input[2000 - delay..4000 - delay];
+        assert!(
+            settled
+                .iter()
+                .zip(delayed)
+                .any(|(a, b)| (a - b).abs() > 0.1)
+        );
+    }
+
+    #[test]
+    fn test_ring_modulation() {
+        let mut samples = vec![0.5; 800];
+        let preset = VoicePreset {
+            ring_mod_hz: 1000.0,
+            ..Default::default()
+        };
+        run(Some(preset), &mut samples, 1);
+
+        let peak = samples.iter().fold(0f32, |peak, s| peak.max(s.abs()));
+        let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+        assert!((peak - 0.5).abs() < 0.01);
+        assert!(mean.abs() < 0.01);
+    }
+
+    #[test]
+    fn test_preset_change_reaches_running_changer() {
+        let control = Arc::new(VoiceChangerControl::new(None));
+        let mut changer = VoiceChanger::new(control.clone(), 8000, 1);
+        assert!(!changer.process(&mut [0.5; 8]));
+
+        control.set_preset(Some(VoicePreset {
+            ring_mod_hz: 1000.0,
+            ..Default::default()
+        }));
+        assert!(control.is_active());
+        let mut samples = vec![0.5; 800];
+        assert!(changer.process(&mut samples));
+        assert!(samples.iter().any(|s| *s < 0.0));
+
+        // The replaced state is dropped by the next change, not by the audio thread
+        assert!(changer.slot.pending.lock().unwrap().1.preset.is_none());
+        control.set_preset(None);
+        assert!(!changer.process(&mut [0.5; 8]));
+    }
+
+    #[test]
+    fn test_active_preset() {
+        let mut settings = VoiceChangerSettings {
+            preset: Some("robot".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(settings.active(), None);
+
+        settings.enabled = true;
+        assert_eq!(settings.active().unwrap().ring_mod_hz, 50.0);
+
+        settings.preset = Some("missing".to_string());
+        assert_eq!(settings.active(), None);
+    }
+}
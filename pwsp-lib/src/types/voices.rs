@@ -0,0 +1,118 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// What to do when a new sound would exceed a voice limit.
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VoiceStealing {
+    /// Stop the voice that has been playing the longest
+    #[default]
+    Oldest,
+    /// Stop the voice with the lowest measured level
+    Quietest,
+    /// Keep the playing voices and reject the new sound
+    Refuse,
+}
+
+impl VoiceStealing {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VoiceStealing::Oldest => "oldest",
+            VoiceStealing::Quietest => "quietest",
+            VoiceStealing::Refuse => "refuse",
+        }
+    }
+}
+
+impl FromStr for VoiceStealing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "oldest" => Ok(VoiceStealing::Oldest),
+            "quietest" => Ok(VoiceStealing::Quietest),
+            "refuse" | "none" => Ok(VoiceStealing::Refuse),
+            other => Err(format!("Unknown voice stealing policy: {}", other)),
+        }
+    }
+}
+
+/// A playing voice as seen by the voice allocator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Voice {
+    pub id: u32,
+    /// Current RMS level of the voice
+    pub loudness: f32,
+}
+
+/// Picks the voices that have to stop so one more can start under `limit`.
+///
+/// Track ids only grow, so the lowest id is the oldest voice.
+/// Returns `None` when the policy refuses to make room.
+pub fn pick_victims(voices: &[Voice], limit: usize, policy: VoiceStealing) -> Option<Vec<u32>> {
+    let limit = limit.max(1);
+    if voices.len() < limit {
+        return Some(vec![]);
+    }
+
+    let excess = voices.len() + 1 - limit;
+    let mut ordered = voices.to_vec();
+    match policy {
+        VoiceStealing::Refuse => return None,
+        VoiceStealing::Oldest => ordered.sort_by_key(|v| v.id),
+        VoiceStealing::Quietest => ordered.sort_by(|a, b| {
+            a.loudness
+                .total_cmp(&b.loudness)
+                .then_with(|| a.id.cmp(&b.id))
+        }),
+    }
+
+    Some(ordered.iter().take(excess).map(|v| v.id).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voices(loudness: &[f32]) -> Vec<Voice> {
+        loudness
+            .iter()
+            .enumerate()
+            .map(|(i, &loudness)| Voice {
+                id: i as u32 + 1,
+                loudness,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_under_limit_steals_nothing() {
+        let v = voices(&[1.0, 1.0]);
+        assert_eq!(pick_victims(&v, 3, VoiceStealing::Refuse), Some(vec![]));
+    }
+
+    #[test]
+    fn test_steal_oldest() {
+        let v = voices(&[0.2, 1.0, 0.5]);
+        assert_eq!(pick_victims(&v, 3, VoiceStealing::Oldest), Some(vec![1]));
+        assert_eq!(pick_victims(&v, 2, VoiceStealing::Oldest), Some(vec![1, 2]));
+    }
+
+    #[test]
+    fn test_steal_quietest() {
+        let v = voices(&[1.0, 0.2, 0.2]);
+        assert_eq!(pick_victims(&v, 3, VoiceStealing::Quietest), Some(vec![2]));
+    }
+
+    #[test]
+    fn test_refuse_when_full() {
+        let v = voices(&[1.0, 1.0]);
+        assert_eq!(pick_victims(&v, 2, VoiceStealing::Refuse), None);
+    }
+
+    #[test]
+    fn test_voice_stealing_from_str() {
+        assert_eq!("Quietest".parse(), Ok(VoiceStealing::Quietest));
+        assert!("loudest".parse::<VoiceStealing>().is_err());
+    }
+}
@@ -0,0 +1,290 @@
+use crate::utils::hash::fnv1a;
+use anyhow::{Result, anyhow};
+use rodio::{Decoder, Source};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Resolution of the stored envelope
+pub const PEAKS_PER_SECOND: u32 = 100;
+/// Envelopes kept on disk, the least recently used go first
+const MAX_CACHED_WAVEFORMS: usize = 1000;
+
+/// Downsampled min/max envelope of a file, mixed down to mono.
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Waveform {
+    /// Duration in seconds
+    pub duration: f32,
+    pub peaks_per_second: u32,
+    /// (min, max) pairs, one per `1 / peaks_per_second` seconds
+    pub peaks: Vec<(f32, f32)>,
+}
+
+impl Waveform {
+    pub fn from_samples(
+        samples: impl Iterator<Item = f32>,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Self {
+        let channels = channels.max(1) as usize;
+        let frames_per_peak = (sample_rate / PEAKS_PER_SECOND).max(1) as usize;
+
+        let mut peaks = vec![];
+        let (mut min, mut max) = (0f32, 0f32);
+        let mut frame_sum = 0f32;
+        let mut frame_samples = 0;
+        let mut frames = 0usize;
+        let mut total_frames = 0usize;
+
+        for sample in samples {
+            frame_sum += sample;
+            frame_samples += 1;
+            if frame_samples < channels {
+                continue;
+            }
+
+            let value = frame_sum / channels as f32;
+            frame_sum = 0.0;
+            frame_samples = 0;
+
+            min = min.min(value);
+            max = max.max(value);
+            frames += 1;
+            total_frames += 1;
+
+            if frames == frames_per_peak {
+                peaks.push((min, max));
+                (min, max) = (0.0, 0.0);
+                frames = 0;
+            }
+        }
+        if frames > 0 {
+            peaks.push((min, max));
+        }
+
+        Waveform {
+            duration: total_frames as f32 / sample_rate.max(1) as f32,
+            peaks_per_second: PEAKS_PER_SECOND,
+            peaks,
+        }
+    }
+
+    /// Decodes the whole file, this is slow for long files so prefer [`Waveform::load`].
+    pub fn compute(path: &Path) -> Result<Self> {
+        let file = fs::File::open(path)?;
+        let decoder = Decoder::try_from(file)?;
+        let sample_rate = u32::from(decoder.sample_rate());
+        let channels = u16::from(decoder.channels());
+        Ok(Self::from_samples(decoder, sample_rate, channels))
+    }
+
+    /// The cached envelope if there is one for the file as it is now, never decodes.
+    pub fn cached(path: &Path) -> Option<Self> {
+        let cache_path = Self::cache_path(path).ok()?;
+        let waveform = serde_json::from_slice::<Waveform>(&fs::read(&cache_path).ok()?).ok()?;
+
+        // Recently used envelopes are the last to be pruned
+        fs::File::options()
+            .write(true)
+            .open(&cache_path)
+            .and_then(|file| file.set_modified(SystemTime::now()))
+            .ok();
+        Some(waveform)
+    }
+
+    /// Returns the cached envelope if the file did not change, computing and caching it otherwise.
+    pub fn load(path: &Path) -> Result<Self> {
+        if let Some(waveform) = Self::cached(path) {
+            return Ok(waveform);
+        }
+
+        let cache_path = Self::cache_path(path)?;
+        let waveform = Self::compute(path)?;
+
+        let Some(dir) = cache_path.parent() else {
+            return Ok(waveform);
+        };
+        fs::create_dir_all(dir)?;
+        fs::write(&cache_path, serde_json::to_vec(&waveform)?)?;
+        prune_cache(dir, &cache_path);
+
+        Ok(waveform)
+    }
+
+    /// Cache file for `path`, named after the path and then its modification time and size
+    /// so older versions of the same file can be found.
+    pub fn cache_path(path: &Path) -> Result<PathBuf> {
+        let metadata = fs::metadata(path)?;
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+
+        // Hashed with FNV-1a, the cache is still found after a toolchain update
+        let path = path.as_os_str().as_encoded_bytes();
+        let path_hash = fnv1a(&[path]);
+        let file_hash = fnv1a(&[path, &mtime.to_le_bytes(), &metadata.len().to_le_bytes()]);
+
+        let cache_dir = dirs::cache_dir()
+            .ok_or_else(|| anyhow!("Failed to get cache directory"))?
+            .join("pwsp")
+            .join("waveforms");
+        Ok(cache_dir.join(format!("{:016x}-{:016x}.json", path_hash, file_hash)))
+    }
+
+    /// Start and end in seconds of the part louder than `threshold` (linear),
+    /// `None` if the whole file is below it.
+    pub fn audible_range(&self, threshold: f32) -> Option<(f32, f32)> {
+        let is_audible = |&(min, max): &(f32, f32)| min.abs().max(max.abs()) > threshold;
+        let first = self.peaks.iter().position(is_audible)?;
+        let last = self.peaks.iter().rposition(is_audible)?;
+
+        let peak_len = 1.0 / self.peaks_per_second.max(1) as f32;
+        Some((
+            first as f32 * peak_len,
+            ((last + 1) as f32 * peak_len).min(self.duration),
+        ))
+    }
+
+    /// Squeezes the envelope into `width` columns, e.g. one per pixel.
+    pub fn columns(&self, width: usize) -> Vec<(f32, f32)> {
+        if width == 0 || self.peaks.is_empty() {
+            return vec![];
+        }
+
+        (0..width)
+            .map(|column| {
+                let start = column * self.peaks.len() / width;
+                let end = ((column + 1) * self.peaks.len() / width).max(start + 1);
+                self.peaks[start..end.min(self.peaks.len())]
+                    .iter()
+                    .fold((0f32, 0f32), |(min, max), &(lo, hi)| {
+                        (min.min(lo), max.max(hi))
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Drops older versions of the file `current` was computed from, then the least recently
+/// used envelopes beyond `MAX_CACHED_WAVEFORMS`.
+fn prune_cache(dir: &Path, current: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let prefix = current
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split_once('-'))
+        .map(|(prefix, _)| format!("{}-", prefix));
+
+    let mut files: Vec<(SystemTime, PathBuf)> = vec![];
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if path == current {
+            continue;
+        }
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        // Left behind by an edited or replaced file, or named before versions were told apart
+        if !name.contains('-')
+            || prefix
+                .as_deref()
+                .is_some_and(|prefix| name.starts_with(prefix))
+        {
+            fs::remove_file(&path).ok();
+            continue;
+        }
+        if let Ok(modified) = entry.metadata().and_then(|m| m.modified()) {
+            files.push((modified, path));
+        }
+    }
+
+    if files.len() < MAX_CACHED_WAVEFORMS {
+        return;
+    }
+    files.sort();
+    for (_, path) in &files[..files.len() + 1 - MAX_CACHED_WAVEFORMS] {
+        fs::remove_file(path).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_samples_mixes_down_and_bins() {
+        // One second of stereo at 1 kHz, left channel a ramp, right channel silent
+        let samples = (0..1000).flat_map(|i| [i as f32 / 1000.0, 0.0]);
+        let waveform = Waveform::from_samples(samples, 1000, 2);
+
+        assert_eq!(waveform.duration, 1.0);
+        assert_eq!(waveform.peaks.len(), PEAKS_PER_SECOND as usize);
+        let (min, max) = waveform.peaks[99];
+        assert_eq!(min, 0.0);
+        assert!((max - 0.4995).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_columns() {
+        let waveform = Waveform {
+            duration: 0.04,
+            peaks_per_second: PEAKS_PER_SECOND,
+            peaks: vec![(-0.1, 0.1), (-0.5, 0.2), (-0.2, 0.9), (0.0, 0.0)],
+        };
+
+        assert_eq!(waveform.columns(2), vec![(-0.5, 0.2), (-0.2, 0.9)]);
+        assert_eq!(waveform.columns(8).len(), 8);
+        assert!(waveform.columns(0).is_empty());
+    }
+
+    #[test]
+    fn test_audible_range() {
+        let waveform = Waveform {
+            duration: 0.05,
+            peaks_per_second: PEAKS_PER_SECOND,
+            peaks: vec![
+                (0.0, 0.001),
+                (-0.5, 0.2),
+                (0.0, 0.0),
+                (-0.1, 0.3),
+                (0.0, 0.002),
+            ],
+        };
+
+        let (start, end) = waveform.audible_range(0.01).unwrap();
+        assert!((start - 0.01).abs() < 1e-6);
+        assert!((end - 0.04).abs() < 1e-6);
+        assert_eq!(waveform.audible_range(0.9), None);
+    }
+
+    #[test]
+    fn test_prune_cache_drops_old_versions() {
+        let dir = std::env::temp_dir().join(format!("pwsp-waveforms-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let current = dir.join("aaaa-0002.json");
+        for name in [
+            "aaaa-0001.json",
+            "aaaa-0002.json",
+            "bbbb-0001.json",
+            "legacy.json",
+        ] {
+            fs::write(dir.join(name), b"{}").unwrap();
+        }
+
+        prune_cache(&dir, &current);
+
+        let mut left: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        left.sort();
+        assert_eq!(left, vec!["aaaa-0002.json", "bbbb-0001.json"]);
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -1,6 +1,47 @@
-use crate::types::{commands::*, config::DaemonConfig, socket::Request};
+use crate::types::{
+    audio_player::PlayOptions,
+    commands::*,
+    config::DaemonConfig,
+    effects::Effect,
+    limiter::LimiterMode,
+    random::{RandomMode, RandomSource},
+    recorder::{RecordFormat, RecordSource, SilenceSplit},
+    socket::Request,
+    tts::TtsEngine,
+    voice_changer::VoicePreset,
+    voices::VoiceStealing,
+};
 
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
+
+fn parse_play_options(request: &Request) -> PlayOptions {
+    PlayOptions {
+        concurrent: request
+            .args
+            .get("concurrent")
+            .and_then(|s| s.parse::<bool>().ok())
+            .unwrap_or(false),
+        slot: request.args.get("slot").cloned(),
+        max_voices: request
+            .args
+            .get("max_voices")
+            .and_then(|s| s.parse::<usize>().ok()),
+        choke_group: request.args.get("choke_group").cloned(),
+        buses: request
+            .args
+            .get("bus")
+            .map(|buses| {
+                buses
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|bus| !bus.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        ..Default::default()
+    }
+}
 
 pub fn parse_command(request: &Request) -> Option<Box<dyn Executable + Send>> {
     let id = request.args.get("id").and_then(|s| s.parse::<u32>().ok());
@@ -44,6 +85,20 @@ pub fn parse_command(request: &Request) -> Option<Box<dyn Executable + Send>> {
                 .ok();
             Some(Box::new(SeekCommand { position, id }))
         }
+        "set_speed" => {
+            let speed = request
+                .args
+                .get("speed")
+                .and_then(|s| s.parse::<f32>().ok());
+            Some(Box::new(SetSpeedCommand { speed, id }))
+        }
+        "set_pitch" => {
+            let pitch = request
+                .args
+                .get("pitch")
+                .and_then(|s| s.parse::<f32>().ok());
+            Some(Box::new(SetPitchCommand { pitch, id }))
+        }
         "get_duration" => Some(Box::new(GetDurationCommand { id })),
         "play" => {
             let file_path = request
@@ -52,15 +107,29 @@ pub fn parse_command(request: &Request) -> Option<Box<dyn Executable + Send>> {
                 .unwrap_or(&String::new())
                 .parse::<PathBuf>()
                 .ok();
-            let concurrent = request
-                .args
-                .get("concurrent")
-                .unwrap_or(&String::new())
-                .parse::<bool>()
-                .ok();
             Some(Box::new(PlayCommand {
                 file_path,
-                concurrent,
+                options: parse_play_options(request),
+            }))
+        }
+        "play_random" => {
+            let source = if let Some(dir) = request.args.get("dir") {
+                Some(RandomSource::Dir(PathBuf::from(dir)))
+            } else if let Some(files) = request.args.get("files") {
+                serde_json::from_str::<Vec<PathBuf>>(files)
+                    .ok()
+                    .map(RandomSource::Files)
+            } else {
+                request.args.get("tag").cloned().map(RandomSource::Tag)
+            };
+            let mode = match request.args.get("mode") {
+                Some(mode) => mode.parse::<RandomMode>().ok(),
+                None => Some(RandomMode::default()),
+            };
+            Some(Box::new(PlayRandomCommand {
+                source,
+                mode,
+                options: parse_play_options(request),
             }))
         }
         "get_tracks" => Some(Box::new(GetTracksCommand {})),
@@ -70,6 +139,21 @@ pub fn parse_command(request: &Request) -> Option<Box<dyn Executable + Send>> {
             let name = Some(request.args.get("input_name").unwrap_or(&String::new())).cloned();
             Some(Box::new(SetCurrentInputCommand { name }))
         }
+        "set_input_channels" => {
+            let channels = request
+                .args
+                .get("channels")
+                .map(|channels| {
+                    channels
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|channel| !channel.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default();
+            Some(Box::new(SetInputChannelsCommand { channels }))
+        }
         "set_loop" => {
             let enabled = request
                 .args
@@ -77,7 +161,11 @@ pub fn parse_command(request: &Request) -> Option<Box<dyn Executable + Send>> {
                 .unwrap_or(&String::new())
                 .parse::<bool>()
                 .ok();
-            Some(Box::new(SetLoopCommand { enabled, id }))
+            let count = request
+                .args
+                .get("count")
+                .and_then(|s| s.parse::<u32>().ok());
+            Some(Box::new(SetLoopCommand { enabled, count, id }))
         }
         "toggle_loop" => Some(Box::new(ToggleLoopCommand { id })),
         "get_daemon_version" => Some(Box::new(GetDaemonVersionCommand {})),
@@ -96,6 +184,14 @@ pub fn parse_command(request: &Request) -> Option<Box<dyn Executable + Send>> {
             let key_chord = request.args.get("key_chord").cloned();
             Some(Box::new(SetHotkeyKeyCommand { slot, key_chord }))
         }
+        "set_hotkey_max_voices" => {
+            let slot = request.args.get("slot").cloned();
+            let max_voices = request
+                .args
+                .get("max_voices")
+                .and_then(|s| s.parse::<usize>().ok());
+            Some(Box::new(SetHotkeyMaxVoicesCommand { slot, max_voices }))
+        }
         "clear_hotkey" => {
             let slot = request.args.get("slot").cloned();
             Some(Box::new(ClearHotkeyCommand { slot }))
@@ -129,6 +225,247 @@ pub fn parse_command(request: &Request) -> Option<Box<dyn Executable + Send>> {
                 key_chord,
             }))
         }
+        "get_sound_settings" => {
+            let file_path = request
+                .args
+                .get("file_path")
+                .and_then(|s| s.parse::<PathBuf>().ok());
+            Some(Box::new(GetSoundSettingsCommand { file_path }))
+        }
+        "set_sound_settings" => {
+            let file_path = request
+                .args
+                .get("file_path")
+                .and_then(|s| s.parse::<PathBuf>().ok());
+            let settings = request
+                .args
+                .get("settings")
+                .and_then(|s| serde_json::from_str::<serde_json::Map<_, _>>(s).ok());
+            Some(Box::new(SetSoundSettingsCommand {
+                file_path,
+                settings,
+            }))
+        }
+        "set_voice_limit" => {
+            let max_voices = request.args.get("max_voices").map(|s| match s.as_str() {
+                "none" => Some(0),
+                s => s.parse::<usize>().ok(),
+            });
+            let policy = request
+                .args
+                .get("policy")
+                .and_then(|s| s.parse::<VoiceStealing>().ok());
+            Some(Box::new(SetVoiceLimitCommand { max_voices, policy }))
+        }
+        "get_levels" => Some(Box::new(GetLevelsCommand {})),
+        "get_audio_info" => Some(Box::new(GetAudioInfoCommand {})),
+        "get_pw_nodes" => Some(Box::new(GetPwNodesCommand {})),
+        "get_pw_ports" => Some(Box::new(GetPwPortsCommand {})),
+        "get_pw_links" => Some(Box::new(GetPwLinksCommand {})),
+        "get_app_streams" => Some(Box::new(GetAppStreamsCommand {})),
+        "set_route_targets" => {
+            let targets = request
+                .args
+                .get("targets")
+                .map(|targets| {
+                    targets
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|target| !target.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default();
+            Some(Box::new(SetRouteTargetsCommand { targets }))
+        }
+        "wait_device_events" => {
+            let since = request.args.get("since").and_then(|s| s.parse().ok());
+            let timeout = request
+                .args
+                .get("timeout")
+                .and_then(|s| s.parse().ok())
+                .map(Duration::from_secs);
+            Some(Box::new(WaitDeviceEventsCommand { since, timeout }))
+        }
+        "start_recording" => {
+            let source = match request.args.get("source") {
+                Some(source) => source.parse::<RecordSource>().ok(),
+                None => Some(RecordSource::default()),
+            };
+            let format = match request.args.get("format") {
+                Some(format) => format.parse::<RecordFormat>().ok(),
+                None => Some(RecordFormat::default()),
+            };
+            let split = match request.args.get("split") {
+                Some(split) => serde_json::from_str::<SilenceSplit>(split).ok().map(Some),
+                None => Some(None),
+            };
+            Some(Box::new(StartRecordingCommand {
+                source,
+                bus: request.args.get("bus").cloned(),
+                format,
+                split,
+            }))
+        }
+        "stop_recording" => Some(Box::new(StopRecordingCommand {})),
+        "say" => Some(Box::new(SayCommand {
+            text: request.args.get("text").cloned(),
+            options: parse_play_options(request),
+        })),
+        "set_tts" => {
+            let engine = request
+                .args
+                .get("engine")
+                .and_then(|s| s.parse::<TtsEngine>().ok());
+            let voice = request.args.get("voice").cloned();
+            let rate = request.args.get("rate").and_then(|s| s.parse::<f32>().ok());
+            let pitch = request
+                .args
+                .get("pitch")
+                .and_then(|s| s.parse::<f32>().ok());
+            Some(Box::new(SetTtsCommand {
+                engine,
+                voice,
+                rate,
+                pitch,
+            }))
+        }
+        "save_replay" => {
+            let seconds = request
+                .args
+                .get("seconds")
+                .and_then(|s| s.parse::<f32>().ok());
+            let slot = request.args.get("slot").cloned();
+            Some(Box::new(SaveReplayCommand { seconds, slot }))
+        }
+        "set_replay" => {
+            let enabled = request
+                .args
+                .get("enabled")
+                .and_then(|s| s.parse::<bool>().ok());
+            let seconds = request
+                .args
+                .get("seconds")
+                .and_then(|s| s.parse::<u32>().ok());
+            let source = request.args.get("source").cloned();
+            Some(Box::new(SetReplayCommand {
+                enabled,
+                seconds,
+                source,
+            }))
+        }
+        "get_sample_cache" => Some(Box::new(GetSampleCacheCommand {})),
+        "set_sample_cache" => {
+            let memory_budget_mb = request
+                .args
+                .get("memory_budget_mb")
+                .and_then(|s| s.parse::<usize>().ok());
+            Some(Box::new(SetSampleCacheCommand { memory_budget_mb }))
+        }
+        "get_buses" => Some(Box::new(GetBusesCommand {})),
+        "set_bus_volume" => {
+            let bus = request.args.get("bus").cloned();
+            let volume = request
+                .args
+                .get("volume")
+                .and_then(|s| s.parse::<f32>().ok());
+            Some(Box::new(SetBusVolumeCommand { bus, volume }))
+        }
+        "set_bus_mic" => {
+            let bus = request.args.get("bus").cloned();
+            let enabled = request
+                .args
+                .get("enabled")
+                .and_then(|s| s.parse::<bool>().ok());
+            Some(Box::new(SetBusMicCommand { bus, enabled }))
+        }
+        "get_limiter" => Some(Box::new(GetLimiterCommand {})),
+        "set_limiter" => {
+            let enabled = request
+                .args
+                .get("enabled")
+                .and_then(|s| s.parse::<bool>().ok());
+            let mode = request
+                .args
+                .get("mode")
+                .and_then(|s| s.parse::<LimiterMode>().ok());
+            let ceiling_db = request
+                .args
+                .get("ceiling_db")
+                .and_then(|s| s.parse::<f32>().ok());
+            let release_ms = request
+                .args
+                .get("release_ms")
+                .and_then(|s| s.parse::<f32>().ok());
+            Some(Box::new(SetLimiterCommand {
+                enabled,
+                mode,
+                ceiling_db,
+                release_ms,
+            }))
+        }
+        "set_silence_trim" => {
+            let enabled = request
+                .args
+                .get("enabled")
+                .and_then(|s| s.parse::<bool>().ok());
+            let threshold_db = request
+                .args
+                .get("threshold_db")
+                .and_then(|s| s.parse::<f32>().ok());
+            Some(Box::new(SetSilenceTrimCommand {
+                enabled,
+                threshold_db,
+            }))
+        }
+        "get_effects" => Some(Box::new(GetEffectsCommand { id })),
+        "set_effects" => {
+            let effects = request
+                .args
+                .get("effects")
+                .and_then(|s| serde_json::from_str::<Vec<Effect>>(s).ok());
+            let preset = request.args.get("preset").cloned();
+            Some(Box::new(SetEffectsCommand {
+                id,
+                effects,
+                preset,
+            }))
+        }
+        "get_effect_presets" => Some(Box::new(GetEffectPresetsCommand {})),
+        "save_effect_preset" => {
+            let name = request.args.get("name").cloned();
+            let effects = request
+                .args
+                .get("effects")
+                .and_then(|s| serde_json::from_str::<Vec<Effect>>(s).ok());
+            Some(Box::new(SaveEffectPresetCommand { name, effects }))
+        }
+        "delete_effect_preset" => {
+            let name = request.args.get("name").cloned();
+            Some(Box::new(DeleteEffectPresetCommand { name }))
+        }
+        "get_voice_changer" => Some(Box::new(GetVoiceChangerCommand {})),
+        "set_voice_changer" => {
+            let enabled = request
+                .args
+                .get("enabled")
+                .and_then(|s| s.parse::<bool>().ok());
+            let preset = request.args.get("preset").cloned();
+            Some(Box::new(SetVoiceChangerCommand { enabled, preset }))
+        }
+        "toggle_voice_changer" => Some(Box::new(ToggleVoiceChangerCommand {})),
+        "save_voice_preset" => {
+            let name = request.args.get("name").cloned();
+            let preset = request
+                .args
+                .get("preset")
+                .and_then(|s| serde_json::from_str::<VoicePreset>(s).ok());
+            Some(Box::new(SaveVoicePresetCommand { name, preset }))
+        }
+        "delete_voice_preset" => {
+            let name = request.args.get("name").cloned();
+            Some(Box::new(DeleteVoicePresetCommand { name }))
+        }
         "get_daemon_config" => Some(Box::new(GetDaemonConfigCommand {})),
         "save_daemon_config" => Some(Box::new(SaveDaemonConfigCommand {})),
         "update_daemon_config" => {
@@ -227,4 +564,20 @@ mod tests {
         let cmd = parse_command(&request);
         assert!(cmd.is_some());
     }
+
+    #[test]
+    fn test_parse_play_buses() {
+        let mut request = Request::play("/sounds/horn.mp3", true);
+        request
+            .args
+            .insert("bus".to_string(), "discord, obs,".to_string());
+
+        let options = parse_play_options(&request);
+        assert_eq!(options.buses, vec!["discord", "obs"]);
+        assert!(
+            parse_play_options(&Request::play("/sounds/horn.mp3", true))
+                .buses
+                .is_empty()
+        );
+    }
 }
@@ -1,6 +1,7 @@
 use crate::types::{
     audio_player::AudioPlayer,
     config::DaemonConfig,
+    pipewire::{AudioDevice, DeviceEvent, DeviceEventKind, DeviceEventLog, DeviceType},
     socket::{MAX_MESSAGE_SIZE, Request, Response},
 };
 
@@ -16,12 +17,26 @@ use std::{
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::UnixStream,
-    sync::{Mutex as AsyncMutex, OnceCell},
-    time::{Duration, sleep},
+    sync::{Mutex as AsyncMutex, OnceCell, watch},
+    time::{Duration, sleep, timeout},
 };
 
 static AUDIO_PLAYER: OnceCell<AsyncMutex<AudioPlayer>> = OnceCell::const_new();
 static DAEMON_CONFIG: OnceLock<Arc<Mutex<DaemonConfig>>> = OnceLock::new();
+static DEVICE_EVENTS: OnceLock<DeviceEvents> = OnceLock::new();
+
+/// Device events waiting for clients, with the latest sequence number to wake them up.
+struct DeviceEvents {
+    log: Mutex<DeviceEventLog>,
+    last_seq: watch::Sender<u64>,
+}
+
+fn get_device_events() -> &'static DeviceEvents {
+    DEVICE_EVENTS.get_or_init(|| DeviceEvents {
+        log: Mutex::new(DeviceEventLog::default()),
+        last_seq: watch::Sender::new(0),
+    })
+}
 
 pub async fn get_audio_player() -> Result<&'static AsyncMutex<AudioPlayer>, String> {
     AUDIO_PLAYER
@@ -53,6 +68,34 @@ pub fn with_daemon_config<R>(f: impl FnOnce(&mut DaemonConfig) -> R) -> R {
     f(&mut guard)
 }
 
+/// Tells waiting clients about an audio source, app streams are left out.
+pub fn publish_device_event(kind: DeviceEventKind, device: &AudioDevice) {
+    if device.device_type != DeviceType::Input {
+        return;
+    }
+    let events = get_device_events();
+    let event = events.log.lock().unwrap().push(kind, device);
+    events.last_seq.send_replace(event.seq);
+}
+
+/// Device events newer than `since`, or than the latest one when unset. Waits up to
+/// `wait` for one to happen and returns none on timeout.
+pub async fn wait_device_events(since: Option<u64>, wait: Duration) -> Vec<DeviceEvent> {
+    let events = get_device_events();
+    let mut last_seq = events.last_seq.subscribe();
+    // Clients may still count from before a daemon restart
+    let latest = *last_seq.borrow();
+    let since = since.map_or(latest, |seq| seq.min(latest));
+
+    if timeout(wait, last_seq.wait_for(|seq| *seq > since))
+        .await
+        .is_err()
+    {
+        return vec![];
+    }
+    events.log.lock().unwrap().since(since)
+}
+
 fn get_current_uid() -> u32 {
     rustix::process::geteuid().as_raw()
 }
@@ -155,7 +155,7 @@ async fn handle_device_events(mut stream: EventStream) {
 
                     let slots = config.slots_for_chord(&chord);
                     for slot in slots {
-                        if let Some(cmd) = parse_command(&slot.action) {
+                        if let Some(cmd) = parse_command(&slot.playback_action()) {
                             cmd.execute().await;
                         }
                     }
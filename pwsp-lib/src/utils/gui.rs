@@ -3,6 +3,7 @@ use crate::{
         audio_player::FullState,
         config::{DaemonConfig, GuiConfig, HotkeyConfig},
         gui::AudioPlayerState,
+        meter::Levels,
         socket::{Request, Response},
     },
     utils::daemon::{is_daemon_running, make_request},
@@ -117,6 +118,8 @@ pub fn start_app_state_thread(audio_player_state_shared: Arc<Mutex<AudioPlayerSt
                 guard.tracks = full_state.tracks;
                 guard.volume = full_state.volume;
                 guard.volume_multiplier = full_state.volume_multiplier;
+                guard.recording = full_state.recording;
+                guard.clips_saved = full_state.clips_saved;
                 guard.current_input = full_state
                     .current_input
                     .split(" - ")
@@ -138,6 +141,18 @@ pub fn start_app_state_thread(audio_player_state_shared: Arc<Mutex<AudioPlayerSt
                 guard.is_daemon_running = true;
             }
 
+            let levels_res = make_request(Request::get_levels())
+                .await
+                .unwrap_or_default();
+            if levels_res.status
+                && let Ok(levels) = serde_json::from_str::<Levels>(&levels_res.message)
+            {
+                let mut guard = audio_player_state_shared
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner());
+                guard.levels = levels;
+            }
+
             // Poll hotkey config at a lower frequency (~every 2 seconds)
             if last_hotkey_poll.elapsed() >= Duration::from_secs(2) {
                 let hotkey_res = make_request(Request::get_hotkeys())
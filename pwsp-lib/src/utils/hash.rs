@@ -0,0 +1,12 @@
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// 64-bit FNV-1a over `parts`, for file names that have to stay the same across builds.
+/// Every part is followed by a zero byte, so moving bytes between parts changes the hash.
+pub fn fnv1a(parts: &[&[u8]]) -> u64 {
+    parts.iter().fold(FNV_OFFSET, |hash, part| {
+        part.iter().chain(&[0]).fold(hash, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+        })
+    })
+}
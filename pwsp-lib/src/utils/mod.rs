@@ -3,4 +3,5 @@ pub mod config;
 pub mod daemon;
 pub mod global_hotkeys;
 pub mod gui;
+pub mod hash;
 pub mod pipewire;
@@ -1,25 +1,123 @@
-use crate::types::pipewire::{AudioDevice, DeviceType, Port};
+use crate::types::{
+    buses::BusSettings,
+    pipewire::{
+        AudioDevice, AudioSettings, ChannelLayout, DeviceEventKind, DeviceSelector, DeviceType,
+        Port, PwGraph, PwLink, PwNode, StreamFormat, StreamInfo, map_ports,
+    },
+};
 use anyhow::{Result, anyhow};
 use pipewire::{
-    context::ContextRc, link::Link, main_loop::MainLoopRc, properties::properties,
-    registry::GlobalObject, spa::utils::dict::DictRef,
+    context::ContextRc,
+    core::CoreRc,
+    link::Link,
+    loop_::TimerSource,
+    main_loop::MainLoopRc,
+    properties::properties,
+    registry::{GlobalObject, RegistryRc},
+    spa::{
+        self,
+        param::{
+            ParamType,
+            audio::{AudioFormat, AudioInfoRaw},
+        },
+        pod::{Pod, serialize::PodSerializer},
+        utils::{Direction, SpaTypes, dict::DictRef},
+    },
+    stream::{StreamFlags, StreamRc},
+    types::ObjectType,
 };
-use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::OnceLock, thread};
-use tokio::sync::oneshot;
+use std::{
+    any::Any,
+    cell::{OnceCell, RefCell},
+    collections::HashMap,
+    io::Cursor,
+    rc::Rc,
+    sync::{Arc, OnceLock},
+    thread,
+    time::Duration,
+};
+use tokio::sync::{broadcast, oneshot, watch};
+
+/// Prefix of the node names the daemon creates
+const NODE_PREFIX: &str = "pwsp-";
+/// Node name of the virtual microphone other apps record from
+pub const VIRTUAL_MIC_NODE: &str = "pwsp-virtual-mic";
+/// Node name of the stream the sounds are played through
+pub const PLAYER_NODE: &str = "pwsp-player";
+/// Node name of the daemon stream capturing the microphone for the buses
+pub const PASSTHROUGH_NODE: &str = "pwsp-mic-passthrough";
+/// Node name of the daemon stream capturing the replay source when it is not the microphone
+pub const REPLAY_NODE: &str = "pwsp-replay";
+/// Node name of the daemon stream capturing a virtual mic while its mix is recorded
+pub const RECORD_NODE: &str = "pwsp-record";
+/// `node.group` of every daemon stream. Grouped nodes share a driver, so the queues between
+/// the player, bus and capture streams can't drift apart.
+const STREAM_GROUP: &str = "pwsp";
+/// Node names of the bus streams, followed by the bus name
+const BUS_STREAM_PREFIX: &str = "pwsp-bus-";
+
+/// `node.name` of the stream feeding the virtual mic of a bus
+pub fn bus_stream_name(bus: &str) -> String {
+    format!("{}{}", BUS_STREAM_PREFIX, bus)
+}
+
+/// Whether a node is one of the daemon's own: its streams, the virtual mics and the buses
+/// configured with another `node_name`.
+pub fn is_daemon_node(name: &str, buses: &[BusSettings]) -> bool {
+    name.starts_with(NODE_PREFIX) || buses.iter().any(|bus| bus.node_name() == name)
+}
+
+/// First retry after losing the PipeWire server, doubled on every failed attempt
+const MIN_RECONNECT_DELAY: Duration = Duration::from_millis(250);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(10);
+/// How long the first request waits for the registry, later ones are answered once connected
+const READY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Reply to requests made while the server is away
+const NOT_CONNECTED: &str = "Not connected to PipeWire";
+const PW_ID_CORE: u32 = 0;
+const EPIPE: i32 = 32;
+
+/// Receives interleaved f32 samples and the channel count from a capture stream.
+pub type SampleCallback = Box<dyn FnMut(&[f32], u32) + Send>;
+/// Writes interleaved f32 samples for a playback stream, the buffer starts out silent.
+pub type FillCallback = Box<dyn FnMut(&mut [f32], u32) + Send>;
 
 pub enum PwCommand {
     GetDevices {
         resp: oneshot::Sender<(Vec<AudioDevice>, Vec<AudioDevice>)>,
     },
+    GetCaptureStreams {
+        resp: oneshot::Sender<Vec<AudioDevice>>,
+    },
+    GetGraph {
+        resp: oneshot::Sender<PwGraph>,
+    },
     CreateVirtualMic {
+        props: VirtualMicProps,
+        settings: AudioSettings,
         resp: oneshot::Sender<Result<u32, String>>,
     },
-    CreateLink {
-        output_fl: Port,
-        output_fr: Port,
-        input_fl: Port,
-        input_fr: Port,
-        resp: oneshot::Sender<Result<(u32, u32), String>>,
+    CreateLinks {
+        /// Output and input port of each link
+        pairs: Vec<(Port, Port)>,
+        resp: oneshot::Sender<Result<Vec<u32>, String>>,
+    },
+    CreateCaptureStream {
+        name: String,
+        /// Node to autoconnect to, the caller links the stream itself when unset
+        target: Option<String>,
+        /// Passive streams don't keep the source running on their own
+        passive: bool,
+        format: StreamFormat,
+        info: Arc<StreamInfo>,
+        on_samples: SampleCallback,
+        resp: oneshot::Sender<Result<u32, String>>,
+    },
+    CreatePlaybackStream {
+        props: PlaybackProps,
+        info: Arc<StreamInfo>,
+        fill: FillCallback,
+        resp: oneshot::Sender<Result<u32, String>>,
     },
     DestroyObject {
         id: u32,
@@ -27,16 +125,97 @@ pub enum PwCommand {
 }
 
 struct AppState {
+    /// Core of the current connection, the streams hold on to it as well
+    core: Option<CoreRc>,
+    session: Option<Session>,
+    reconnect_delay: Duration,
     input_devices: HashMap<u32, AudioDevice>,
     output_devices: HashMap<u32, AudioDevice>,
+    capture_streams: HashMap<u32, AudioDevice>,
     ports: HashMap<u32, Port>,
+    links: HashMap<u32, PwLink>,
     proxies: HashMap<u32, Box<dyn std::any::Any>>,
     proxy_id_counter: u32,
     ready_tx: Option<std::sync::mpsc::Sender<()>>,
 }
 
+/// Registry and listeners of one connection to the PipeWire server, dropped when it is lost.
+struct Session {
+    _registry_listener: pipewire::registry::Listener,
+    _core_listener: pipewire::core::Listener,
+    _registry: RegistryRc,
+}
+
+impl AppState {
+    fn new(ready_tx: Option<std::sync::mpsc::Sender<()>>) -> Self {
+        AppState {
+            core: None,
+            session: None,
+            reconnect_delay: MIN_RECONNECT_DELAY,
+            input_devices: HashMap::new(),
+            output_devices: HashMap::new(),
+            capture_streams: HashMap::new(),
+            ports: HashMap::new(),
+            links: HashMap::new(),
+            proxies: HashMap::new(),
+            proxy_id_counter: 10000,
+            ready_tx,
+        }
+    }
+
+    fn node_mut(&mut self, node_id: u32) -> Option<&mut AudioDevice> {
+        if let Some(d) = self.input_devices.get_mut(&node_id) {
+            Some(d)
+        } else if let Some(d) = self.output_devices.get_mut(&node_id) {
+            Some(d)
+        } else {
+            self.capture_streams.get_mut(&node_id)
+        }
+    }
+
+    /// Tracks a port by its global id and hands it to its node, `port.id` repeats across nodes.
+    fn add_port(&mut self, port: Port) {
+        self.ports.insert(port.id, port.clone());
+        if let Some(d) = self.node_mut(port.node_id) {
+            d.add_port(port);
+        }
+    }
+
+    /// Forgets the port with the global `id`, also on its node, or the ports of the node with it.
+    fn remove_ports(&mut self, id: u32) {
+        self.ports.retain(|_, port| port.node_id != id);
+        if let Some(port) = self.ports.remove(&id)
+            && let Some(d) = self.node_mut(port.node_id)
+        {
+            d.remove_port(id);
+        }
+    }
+
+    /// Forgets everything that belonged to the lost connection. The proxies are dead
+    /// already, the sources they tracked are reported as removed.
+    fn disconnect(&mut self, devices: &broadcast::Sender<DeviceChange>) {
+        self.session = None;
+        self.core = None;
+        self.proxies.clear();
+        for (_, device) in self.input_devices.drain() {
+            let _ = devices.send((DeviceEventKind::Removed, device));
+        }
+        self.output_devices.clear();
+        self.capture_streams.clear();
+        self.ports.clear();
+        self.links.clear();
+    }
+}
+
+/// An audio source or app capture stream added to or removed from the registry, without its
+/// ports yet when added.
+pub type DeviceChange = (DeviceEventKind, AudioDevice);
+
 pub struct PipewireManager {
     pub sender: pipewire::channel::Sender<PwCommand>,
+    devices: broadcast::Sender<DeviceChange>,
+    /// Bumped every time a connection is up and its registry is synced
+    connection: watch::Sender<u64>,
 }
 
 static MANAGER: OnceLock<PipewireManager> = OnceLock::new();
@@ -45,84 +224,80 @@ pub fn get_manager() -> &'static PipewireManager {
     MANAGER.get_or_init(|| {
         let (pw_sender, pw_receiver) = pipewire::channel::channel::<PwCommand>();
         let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+        let (devices, _) = broadcast::channel(64);
+        let connection = watch::Sender::new(0);
+        let devices_for_thread = devices.clone();
+        let connection_for_thread = connection.clone();
 
         thread::spawn(move || {
-            let (main_loop, context) = setup_pipewire_context().expect("Failed to setup pipewire");
+            let devices = devices_for_thread;
+            let connection = connection_for_thread;
+            let (main_loop, context) = {
+                let mut delay = MIN_RECONNECT_DELAY;
+                loop {
+                    match setup_pipewire_context() {
+                        Ok(setup) => break setup,
+                        Err(err) => {
+                            eprintln!(
+                                "Failed to set up PipeWire: {}, retrying in {:?}",
+                                err, delay
+                            );
+                            thread::sleep(delay);
+                            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                        }
+                    }
+                }
+            };
 
             // Leak main_loop and context so their borrows can be 'static
-            let main_loop = Box::leak(Box::new(main_loop));
-            let context = Box::leak(Box::new(context));
-
-            // Leak to fix lifetime issues since this thread lives forever
-            let core = Box::leak(Box::new(
-                context
-                    .connect(None)
-                    .expect("Failed to connect to pipewire"),
-            ));
-            let registry = Box::leak(Box::new(
-                core.get_registry().expect("Failed to get registry"),
-            ));
-
-            let state = Rc::new(RefCell::new(AppState {
-                input_devices: HashMap::new(),
-                output_devices: HashMap::new(),
-                ports: HashMap::new(),
-                proxies: HashMap::new(),
-                proxy_id_counter: 10000,
-                ready_tx: Some(ready_tx),
-            }));
-
-            let state_for_registry_add = state.clone();
-            let state_for_registry_remove = state.clone();
-
-            let _listener = registry
-                .add_listener_local()
-                .global(move |global| {
-                    let (device, port) = parse_global_object(global);
-                    let mut s = state_for_registry_add.borrow_mut();
-                    if let Some(device) = device {
-                        match device.device_type {
-                            DeviceType::Input => {
-                                s.input_devices.insert(device.id, device);
-                            }
-                            DeviceType::Output => {
-                                s.output_devices.insert(device.id, device);
-                            }
-                        }
-                    } else if let Some(port) = port {
-                        let node_id = port.node_id;
-                        s.ports.insert(port.port_id, port.clone());
-                        if let Some(d) = s.input_devices.get_mut(&node_id) {
-                            d.add_port(port.clone());
-                        } else if let Some(d) = s.output_devices.get_mut(&node_id) {
-                            d.add_port(port);
-                        }
+            let main_loop: &'static _ = Box::leak(Box::new(main_loop));
+            let context: &'static _ = Box::leak(Box::new(context));
+
+            let state = Rc::new(RefCell::new(AppState::new(Some(ready_tx))));
+
+            // Connects, and reconnects with a growing delay while the server is away
+            let timer_cell: Rc<OnceCell<TimerSource<'static>>> = Rc::new(OnceCell::new());
+            let arm_timer = {
+                let timer_cell = timer_cell.clone();
+                Rc::new(move |delay: Duration| {
+                    if let Some(timer) = timer_cell.get() {
+                        let _ = timer.update_timer(Some(delay), None);
                     }
                 })
-                .global_remove(move |id| {
-                    let mut s = state_for_registry_remove.borrow_mut();
-                    s.input_devices.remove(&id);
-                    s.output_devices.remove(&id);
-                    s.ports.retain(|_, port| port.node_id != id);
-                    s.ports.remove(&id);
-                })
-                .register();
-
-            // sync to signal ready
-            let state_for_sync = state.clone();
-            let _core_listener = core
-                .add_listener_local()
-                .done(move |id, _seq| {
-                    if id == 0 {
-                        let mut s = state_for_sync.borrow_mut();
-                        if let Some(tx) = s.ready_tx.take() {
-                            let _ = tx.send(());
-                        }
+            };
+            let timer = main_loop.loop_().add_timer({
+                let state = state.clone();
+                let arm_timer = arm_timer.clone();
+                move |_| {
+                    if state.borrow().session.is_some() {
+                        eprintln!("Lost connection to PipeWire, reconnecting");
+                        state.borrow_mut().disconnect(&devices);
                     }
-                })
-                .register();
 
-            let _pending = core.sync(0).expect("sync failed");
+                    let on_lost = {
+                        let arm_timer = arm_timer.clone();
+                        Rc::new(move || arm_timer(MIN_RECONNECT_DELAY)) as Rc<dyn Fn()>
+                    };
+                    match connect(context, &state, &devices, &connection, on_lost) {
+                        Ok(()) => state.borrow_mut().reconnect_delay = MIN_RECONNECT_DELAY,
+                        Err(err) => {
+                            let delay = {
+                                let mut s = state.borrow_mut();
+                                let delay = s.reconnect_delay;
+                                s.reconnect_delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                                delay
+                            };
+                            eprintln!(
+                                "Failed to connect to PipeWire: {}, retrying in {:?}",
+                                err, delay
+                            );
+                            arm_timer(delay);
+                        }
+                    }
+                }
+            });
+            let _ = timer_cell.set(timer);
+            arm_timer(Duration::from_millis(1));
 
             let state_for_cmd = state.clone();
             let _receiver = pw_receiver.attach(main_loop.loop_(), move |cmd| {
@@ -137,16 +312,52 @@ pub fn get_manager() -> &'static PipewireManager {
                         outputs.sort_by_key(|a| a.id);
                         let _ = resp.send((inputs, outputs));
                     }
-                    PwCommand::CreateVirtualMic { resp } => {
-                        let props = properties!(
+                    PwCommand::GetGraph { resp } => {
+                        let mut graph = PwGraph {
+                            nodes: s
+                                .input_devices
+                                .values()
+                                .chain(s.output_devices.values())
+                                .chain(s.capture_streams.values())
+                                .map(PwNode::from)
+                                .collect(),
+                            ports: s.ports.values().cloned().collect(),
+                            links: s.links.values().cloned().collect(),
+                        };
+                        graph.nodes.sort_by_key(|node| node.id);
+                        graph.ports.sort_by_key(|port| (port.node_id, port.port_id));
+                        graph.links.sort_by_key(|link| link.id);
+                        let _ = resp.send(graph);
+                    }
+                    PwCommand::GetCaptureStreams { resp } => {
+                        let mut streams: Vec<AudioDevice> =
+                            s.capture_streams.values().cloned().collect();
+                        streams.sort_by_key(|a| a.id);
+                        let _ = resp.send(streams);
+                    }
+                    PwCommand::CreateVirtualMic {
+                        props: mic,
+                        settings,
+                        resp,
+                    } => {
+                        let Some(core) = s.core.clone() else {
+                            let _ = resp.send(Err(NOT_CONNECTED.to_string()));
+                            return;
+                        };
+                        let mut props = properties!(
                             "factory.name" => "support.null-audio-sink",
-                            "node.name" => "pwsp-virtual-mic",
-                            "node.description" => "PWSP Virtual Mic",
+                            "node.name" => mic.node_name.as_str(),
+                            "node.description" => mic.description.as_str(),
                             "media.class" => "Audio/Source/Virtual",
-                            "audio.position" => "[ FL FR ]",
-                            "audio.channels" => "2",
-                            "object.linger" => "false",
+                            "audio.position" => mic.channels.positions(),
+                            "audio.channels" => mic.channels.channels().to_string().as_str(),
+                            "audio.rate" => settings.sample_rate().to_string().as_str(),
+                            "node.latency" => settings.latency().as_str(),
+                            "object.linger" => mic.linger.to_string().as_str(),
                         );
+                        if let Some(icon) = &mic.icon {
+                            props.insert("device.icon-name", icon.as_str());
+                        }
                         match core.create_object::<pipewire::node::Node>("adapter", &props) {
                             Ok(node) => {
                                 s.proxy_id_counter += 1;
@@ -159,50 +370,93 @@ pub fn get_manager() -> &'static PipewireManager {
                             }
                         }
                     }
-                    PwCommand::CreateLink {
-                        output_fl,
-                        output_fr,
-                        input_fl,
-                        input_fr,
+                    PwCommand::CreateLinks { pairs, resp } => {
+                        let Some(core) = s.core.clone() else {
+                            let _ = resp.send(Err(NOT_CONNECTED.to_string()));
+                            return;
+                        };
+                        let mut ids = vec![];
+                        for (output, input) in pairs {
+                            let props = properties! {
+                                "link.output.node" => format!("{}", output.node_id).as_str(),
+                                "link.output.port" => format!("{}", output.id).as_str(),
+                                "link.input.node"  => format!("{}", input.node_id).as_str(),
+                                "link.input.port"  => format!("{}", input.id).as_str(),
+                            };
+
+                            match core.create_object::<Link>("link-factory", &props) {
+                                Ok(link) => {
+                                    s.proxy_id_counter += 1;
+                                    let id = s.proxy_id_counter;
+                                    s.proxies.insert(id, Box::new(link));
+                                    ids.push(id);
+                                }
+                                Err(e) => {
+                                    // No half-linked streams
+                                    for id in ids {
+                                        s.proxies.remove(&id);
+                                    }
+                                    let _ = resp.send(Err(e.to_string()));
+                                    return;
+                                }
+                            }
+                        }
+                        let _ = resp.send(Ok(ids));
+                    }
+                    PwCommand::CreateCaptureStream {
+                        name,
+                        target,
+                        passive,
+                        format,
+                        info,
+                        on_samples,
                         resp,
                     } => {
-                        let props_fl = properties! {
-                            "link.output.node" => format!("{}", output_fl.node_id).as_str(),
-                            "link.output.port" => format!("{}", output_fl.port_id).as_str(),
-                            "link.input.node"  => format!("{}", input_fl.node_id).as_str(),
-                            "link.input.port"  => format!("{}", input_fl.port_id).as_str(),
+                        let Some(core) = s.core.clone() else {
+                            let _ = resp.send(Err(NOT_CONNECTED.to_string()));
+                            return;
                         };
-                        let props_fr = properties! {
-                            "link.output.node" => format!("{}", output_fr.node_id).as_str(),
-                            "link.output.port" => format!("{}", output_fr.port_id).as_str(),
-                            "link.input.node"  => format!("{}", input_fr.node_id).as_str(),
-                            "link.input.port"  => format!("{}", input_fr.port_id).as_str(),
-                        };
-
-                        let link_fl = match core.create_object::<Link>("link-factory", &props_fl) {
-                            Ok(link) => link,
+                        match build_capture_stream(
+                            &core,
+                            &name,
+                            target.as_deref(),
+                            passive,
+                            format,
+                            info,
+                            on_samples,
+                        ) {
+                            Ok(stream) => {
+                                s.proxy_id_counter += 1;
+                                let id = s.proxy_id_counter;
+                                s.proxies.insert(id, stream);
+                                let _ = resp.send(Ok(id));
+                            }
                             Err(e) => {
                                 let _ = resp.send(Err(e.to_string()));
-                                return;
                             }
+                        }
+                    }
+                    PwCommand::CreatePlaybackStream {
+                        props,
+                        info,
+                        fill,
+                        resp,
+                    } => {
+                        let Some(core) = s.core.clone() else {
+                            let _ = resp.send(Err(NOT_CONNECTED.to_string()));
+                            return;
                         };
-                        let link_fr = match core.create_object::<Link>("link-factory", &props_fr) {
-                            Ok(link) => link,
+                        match build_playback_stream(&core, &props, info, fill) {
+                            Ok(stream) => {
+                                s.proxy_id_counter += 1;
+                                let id = s.proxy_id_counter;
+                                s.proxies.insert(id, stream);
+                                let _ = resp.send(Ok(id));
+                            }
                             Err(e) => {
                                 let _ = resp.send(Err(e.to_string()));
-                                return;
                             }
-                        };
-
-                        s.proxy_id_counter += 1;
-                        let id_fl = s.proxy_id_counter;
-                        s.proxies.insert(id_fl, Box::new(link_fl));
-
-                        s.proxy_id_counter += 1;
-                        let id_fr = s.proxy_id_counter;
-                        s.proxies.insert(id_fr, Box::new(link_fr));
-
-                        let _ = resp.send(Ok((id_fl, id_fr)));
+                        }
                     }
                     PwCommand::DestroyObject { id } => {
                         s.proxies.remove(&id);
@@ -213,13 +467,130 @@ pub fn get_manager() -> &'static PipewireManager {
             main_loop.run();
         });
 
-        // Wait for the pipewire thread to be fully up and processed initial events
-        let _ = ready_rx.recv();
+        // Wait for the pipewire thread to be connected and processed initial events. Without a
+        // server it keeps retrying in the background, requests are refused until it is back.
+        if ready_rx.recv_timeout(READY_TIMEOUT).is_err() {
+            eprintln!("PipeWire is not ready yet, connecting in the background");
+        }
 
-        PipewireManager { sender: pw_sender }
+        PipewireManager {
+            sender: pw_sender,
+            devices,
+            connection,
+        }
     })
 }
 
+/// Connects to the PipeWire server and tracks its registry. `on_lost` is called from the
+/// loop once the connection breaks.
+fn connect(
+    context: &'static ContextRc,
+    state: &Rc<RefCell<AppState>>,
+    devices: &broadcast::Sender<DeviceChange>,
+    connection: &watch::Sender<u64>,
+    on_lost: Rc<dyn Fn()>,
+) -> Result<(), String> {
+    let core = context.connect_rc(None).map_err(|e| e.to_string())?;
+    let registry = core.get_registry_rc().map_err(|e| e.to_string())?;
+
+    let state_for_registry_add = state.clone();
+    let state_for_registry_remove = state.clone();
+    let devices_for_add = devices.clone();
+    let devices_for_remove = devices.clone();
+
+    let registry_listener = registry
+        .add_listener_local()
+        .global(move |global| {
+            let mut s = state_for_registry_add.borrow_mut();
+            if let Some(link) = parse_link(global) {
+                s.links.insert(link.id, link);
+                return;
+            }
+
+            let (device, port) = parse_global_object(global);
+            if let Some(device) = device {
+                match device.device_type {
+                    DeviceType::Input => {
+                        // Nobody listening is fine
+                        let _ = devices_for_add.send((DeviceEventKind::Added, device.clone()));
+                        s.input_devices.insert(device.id, device);
+                    }
+                    DeviceType::Output => {
+                        s.output_devices.insert(device.id, device);
+                    }
+                    DeviceType::Capture => {
+                        // The daemon's own streams come and go with its links, not with devices
+                        if !device.name.starts_with(NODE_PREFIX) {
+                            let _ = devices_for_add.send((DeviceEventKind::Added, device.clone()));
+                        }
+                        s.capture_streams.insert(device.id, device);
+                    }
+                }
+            } else if let Some(port) = port {
+                s.add_port(port);
+            }
+        })
+        .global_remove(move |id| {
+            let mut s = state_for_registry_remove.borrow_mut();
+            if let Some(device) = s.input_devices.remove(&id) {
+                let _ = devices_for_remove.send((DeviceEventKind::Removed, device));
+            }
+            s.output_devices.remove(&id);
+            if let Some(stream) = s.capture_streams.remove(&id)
+                && !stream.name.starts_with(NODE_PREFIX)
+            {
+                let _ = devices_for_remove.send((DeviceEventKind::Removed, stream));
+            }
+            s.remove_ports(id);
+            s.links.remove(&id);
+        })
+        .register();
+
+    // The initial sync tells when the registry is complete
+    let state_for_sync = state.clone();
+    let connection = connection.clone();
+    let core_listener = core
+        .add_listener_local()
+        .done(move |id, _seq| {
+            if id == PW_ID_CORE {
+                if let Some(tx) = state_for_sync.borrow_mut().ready_tx.take() {
+                    let _ = tx.send(());
+                }
+                connection.send_modify(|generation| *generation += 1);
+            }
+        })
+        .error(move |id, _seq, res, message| {
+            // The server closing the socket shows up as EPIPE on the core
+            if id == PW_ID_CORE && res == -EPIPE {
+                eprintln!("PipeWire connection error: {}", message);
+                on_lost();
+            }
+        })
+        .register();
+
+    core.sync(0).map_err(|e| e.to_string())?;
+
+    let mut s = state.borrow_mut();
+    s.core = Some(core);
+    s.session = Some(Session {
+        _registry_listener: registry_listener,
+        _core_listener: core_listener,
+        _registry: registry,
+    });
+    Ok(())
+}
+
+/// Connection generations, bumped after every (re)connect once the registry is synced.
+/// The streams, links and virtual mics of an older generation are gone.
+pub fn subscribe_connection() -> watch::Receiver<u64> {
+    get_manager().connection.subscribe()
+}
+
+/// Audio sources and app capture streams coming and going, as the registry reports them.
+pub fn subscribe_devices() -> broadcast::Receiver<DeviceChange> {
+    get_manager().devices.subscribe()
+}
+
 pub fn setup_pipewire_context() -> Result<(MainLoopRc, ContextRc), String> {
     pipewire::init();
     let main_loop = MainLoopRc::new(None).map_err(|e| e.to_string())?;
@@ -227,6 +598,257 @@ pub fn setup_pipewire_context() -> Result<(MainLoopRc, ContextRc), String> {
     Ok((main_loop, context))
 }
 
+/// Serialized `EnumFormat` param asking for interleaved f32 samples.
+fn f32_format_param(rate: Option<u32>, channels: Option<u32>) -> Result<Vec<u8>, String> {
+    let mut info = AudioInfoRaw::new();
+    info.set_format(AudioFormat::F32LE);
+    if let Some(rate) = rate {
+        info.set_rate(rate);
+    }
+    if let Some(channels) = channels {
+        info.set_channels(channels);
+    }
+
+    let object = spa::pod::Object {
+        type_: SpaTypes::ObjectParamFormat.as_raw(),
+        id: ParamType::EnumFormat.as_raw(),
+        properties: info.into(),
+    };
+    PodSerializer::serialize(Cursor::new(Vec::new()), &spa::pod::Value::Object(object))
+        .map(|(cursor, _)| cursor.into_inner())
+        .map_err(|e| format!("Failed to serialize audio format: {:?}", e))
+}
+
+struct CaptureData {
+    channels: u32,
+    info: Arc<StreamInfo>,
+    samples: Vec<f32>,
+    on_samples: SampleCallback,
+}
+
+/// Records the negotiated format of a stream from its `Format` param.
+fn parse_format(id: u32, param: Option<&Pod>, info: &StreamInfo) -> Option<u32> {
+    let param = param?;
+    if id != ParamType::Format.as_raw() {
+        return None;
+    }
+    let mut raw = AudioInfoRaw::new();
+    raw.parse(param).ok()?;
+    let channels = raw.channels().max(1);
+    info.set_format(raw.rate(), channels);
+    Some(channels)
+}
+
+/// Creates a stream reading from the node named `target`, or an unlinked one without it.
+///
+/// The returned box keeps the stream and its listener alive, dropping it disconnects.
+fn build_capture_stream(
+    core: &CoreRc,
+    name: &str,
+    target: Option<&str>,
+    passive: bool,
+    format: StreamFormat,
+    info: Arc<StreamInfo>,
+    on_samples: SampleCallback,
+) -> Result<Box<dyn Any>, String> {
+    let mut props = properties! {
+        "media.type" => "Audio",
+        "media.category" => "Capture",
+        "media.role" => "Production",
+        "node.name" => name,
+        "node.passive" => if passive { "true" } else { "false" },
+        "node.dont-reconnect" => "true",
+        "node.latency" => format.latency().as_str(),
+        "node.group" => STREAM_GROUP,
+    };
+    let mut flags = StreamFlags::MAP_BUFFERS | StreamFlags::RT_PROCESS;
+    if let Some(target) = target {
+        props.insert("target.object", target);
+        flags |= StreamFlags::AUTOCONNECT;
+    }
+    let stream = StreamRc::new(core.clone(), name, props).map_err(|e| e.to_string())?;
+
+    let listener = stream
+        .add_local_listener_with_user_data(CaptureData {
+            channels: format.channels,
+            info,
+            samples: vec![],
+            on_samples,
+        })
+        .param_changed(|_, data, id, param| {
+            if let Some(channels) = parse_format(id, param, &data.info) {
+                data.channels = channels;
+            }
+        })
+        .process(|stream, data| {
+            let Some(mut buffer) = stream.dequeue_buffer() else {
+                return;
+            };
+            let datas = buffer.datas_mut();
+            let Some(first) = datas.first_mut() else {
+                return;
+            };
+            let size = first.chunk().size() as usize;
+            if let Some(bytes) = first.data() {
+                let bytes = &bytes[..size.min(bytes.len())];
+                data.samples.clear();
+                data.samples.extend(
+                    bytes
+                        .chunks_exact(4)
+                        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                );
+                data.info
+                    .set_quantum(data.samples.len() as u32 / data.channels);
+                (data.on_samples)(&data.samples, data.channels);
+            }
+        })
+        .register()
+        .map_err(|e| e.to_string())?;
+
+    let format = f32_format_param(Some(format.rate), Some(format.channels))?;
+    let mut params = [Pod::from_bytes(&format).ok_or("Invalid audio format")?];
+    stream
+        .connect(Direction::Input, None, flags, &mut params)
+        .map_err(|e| e.to_string())?;
+
+    // Listener first so it never outlives the stream it points to
+    Ok(Box::new((listener, stream)))
+}
+
+struct PlaybackData {
+    channels: u32,
+    info: Arc<StreamInfo>,
+    samples: Vec<f32>,
+    fill: FillCallback,
+}
+
+/// Properties of a virtual mic node.
+#[derive(Debug, Clone)]
+pub struct VirtualMicProps {
+    pub node_name: String,
+    pub description: String,
+    pub channels: ChannelLayout,
+    /// `device.icon-name`
+    pub icon: Option<String>,
+    /// Keep the node after the daemon disconnects
+    pub linger: bool,
+}
+
+/// Properties of a daemon playback stream.
+#[derive(Debug, Clone)]
+pub struct PlaybackProps {
+    pub node_name: String,
+    pub media_role: String,
+    /// Requested format, also sets `node.latency`
+    pub format: StreamFormat,
+    /// Let the session manager link the stream, to `target` or the default sink
+    pub autoconnect: bool,
+    /// `node.name` of the node to autoconnect to
+    pub target: Option<String>,
+}
+
+/// Creates an output stream with the given properties.
+///
+/// The returned box keeps the stream and its listener alive, dropping it disconnects.
+fn build_playback_stream(
+    core: &CoreRc,
+    playback: &PlaybackProps,
+    info: Arc<StreamInfo>,
+    fill: FillCallback,
+) -> Result<Box<dyn Any>, String> {
+    let name = playback.node_name.as_str();
+    let mut props = properties! {
+        "media.type" => "Audio",
+        "media.category" => "Playback",
+        "media.role" => playback.media_role.as_str(),
+        "node.name" => name,
+        "node.autoconnect" => if playback.autoconnect { "true" } else { "false" },
+        "node.dont-reconnect" => "true",
+        "node.latency" => playback.format.latency().as_str(),
+        "node.group" => STREAM_GROUP,
+    };
+    if let Some(target) = &playback.target {
+        props.insert("target.object", target.as_str());
+    }
+    let stream = StreamRc::new(core.clone(), name, props).map_err(|e| e.to_string())?;
+
+    let listener = stream
+        .add_local_listener_with_user_data(PlaybackData {
+            channels: playback.format.channels,
+            info,
+            samples: vec![],
+            fill,
+        })
+        .param_changed(|_, data, id, param| {
+            if let Some(channels) = parse_format(id, param, &data.info) {
+                data.channels = channels;
+            }
+        })
+        .process(|stream, data| {
+            let Some(mut buffer) = stream.dequeue_buffer() else {
+                return;
+            };
+            let requested = buffer.requested() as usize;
+            let datas = buffer.datas_mut();
+            let Some(first) = datas.first_mut() else {
+                return;
+            };
+
+            let stride = 4 * data.channels as usize;
+            let frames = if let Some(bytes) = first.data() {
+                let mut frames = bytes.len() / stride;
+                if requested > 0 {
+                    frames = frames.min(requested);
+                }
+
+                data.info.set_quantum(frames as u32);
+                data.samples.clear();
+                data.samples.resize(frames * data.channels as usize, 0.0);
+                (data.fill)(&mut data.samples, data.channels);
+                for (bytes, sample) in bytes.chunks_exact_mut(4).zip(&data.samples) {
+                    bytes.copy_from_slice(&sample.to_le_bytes());
+                }
+                frames
+            } else {
+                0
+            };
+
+            let chunk = first.chunk_mut();
+            *chunk.offset_mut() = 0;
+            *chunk.stride_mut() = stride as i32;
+            *chunk.size_mut() = (frames * stride) as u32;
+        })
+        .register()
+        .map_err(|e| e.to_string())?;
+
+    let mut flags = StreamFlags::MAP_BUFFERS | StreamFlags::RT_PROCESS;
+    if playback.autoconnect {
+        flags |= StreamFlags::AUTOCONNECT;
+    }
+    let format = f32_format_param(Some(playback.format.rate), Some(playback.format.channels))?;
+    let mut params = [Pod::from_bytes(&format).ok_or("Invalid audio format")?];
+    stream
+        .connect(Direction::Output, None, flags, &mut params)
+        .map_err(|e| e.to_string())?;
+
+    Ok(Box::new((listener, stream)))
+}
+
+fn parse_link(global_object: &GlobalObject<&DictRef>) -> Option<PwLink> {
+    if global_object.type_ != ObjectType::Link {
+        return None;
+    }
+    let props = global_object.props?;
+    let get = |key: &str| props.get(key).and_then(|value| value.parse::<u32>().ok());
+    Some(PwLink {
+        id: global_object.id,
+        output_node: get("link.output.node")?,
+        output_port: get("link.output.port")?,
+        input_node: get("link.input.node")?,
+        input_port: get("link.input.port")?,
+    })
+}
+
 fn parse_global_object(
     global_object: &GlobalObject<&DictRef>,
 ) -> (Option<AudioDevice>, Option<Port>) {
@@ -241,40 +863,40 @@ fn parse_global_object(
         let node_name = props.get("node.name");
         let node_description = props.get("node.description");
 
-        if media_class.starts_with("Audio/Source") {
-            let input_device = AudioDevice::new(
-                node_id,
-                node_nick,
-                node_description,
-                node_name,
-                DeviceType::Input,
-            );
-            return (Some(input_device), None);
+        let device_type = if media_class.starts_with("Audio/Source") {
+            DeviceType::Input
         } else if media_class.starts_with("Stream/Output/Audio") {
-            let output_device = AudioDevice::new(
-                node_id,
-                node_nick,
-                node_description,
-                node_name,
-                DeviceType::Output,
-            );
-            return (Some(output_device), None);
-        }
-        return (None, None);
+            DeviceType::Output
+        } else if media_class.starts_with("Stream/Input/Audio") {
+            DeviceType::Capture
+        } else {
+            return (None, None);
+        };
+
+        let mut device =
+            AudioDevice::new(node_id, node_nick, node_description, node_name, device_type);
+        device.serial = props.get("object.serial").map(str::to_string);
+        device.bus_path = props.get("device.bus-path").map(str::to_string);
+        device.app_name = props.get("application.name").map(str::to_string);
+        device.app_binary = props.get("application.process.binary").map(str::to_string);
+        return (Some(device), None);
     }
 
-    if props.get("port.direction").is_some()
+    if let Some(direction) = props.get("port.direction")
         && let (Some(node_id), Some(port_id), Some(port_name)) = (
             props.get("node.id").and_then(|id| id.parse::<u32>().ok()),
             props.get("port.id").and_then(|id| id.parse::<u32>().ok()),
             props.get("port.name"),
         )
     {
-        let port = Port {
+        let port = Port::new(
+            global_object.id,
             node_id,
             port_id,
-            name: port_name.to_string(),
-        };
+            port_name,
+            props.get("audio.channel"),
+            Some(direction),
+        );
         return (None, Some(port));
     }
 
@@ -294,25 +916,62 @@ pub async fn get_all_devices() -> Result<(Vec<AudioDevice>, Vec<AudioDevice>)> {
     Ok(res)
 }
 
-pub async fn get_device(device_name: &str) -> Result<AudioDevice> {
-    let (input_devices, output_devices) = get_all_devices().await?;
+pub async fn get_capture_streams() -> Result<Vec<AudioDevice>> {
+    let (tx, rx) = oneshot::channel();
+    let manager = get_manager();
+    manager
+        .sender
+        .send(PwCommand::GetCaptureStreams { resp: tx })
+        .map_err(|_| anyhow!("Failed to send GetCaptureStreams to manager"))?;
+    rx.await
+        .map_err(|e| anyhow!("Failed to receive response: {}", e))
+}
 
-    input_devices
+/// Capture streams of other applications, with their input ports.
+pub async fn get_app_streams() -> Result<Vec<AudioDevice>> {
+    Ok(get_capture_streams()
+        .await?
         .into_iter()
-        .chain(output_devices)
-        .find(|device| {
-            device.name == device_name
-                || device.nick == device_name
-                || device.name.contains(device_name)
-                || device.nick.contains(device_name)
-        })
-        .ok_or_else(|| anyhow!("Device not found"))
+        .filter(|stream| !stream.name.starts_with(NODE_PREFIX))
+        .collect())
+}
+
+pub async fn get_pw_graph() -> Result<PwGraph> {
+    let (tx, rx) = oneshot::channel();
+    let manager = get_manager();
+    manager
+        .sender
+        .send(PwCommand::GetGraph { resp: tx })
+        .map_err(|_| anyhow!("Failed to send GetGraph to manager"))?;
+    rx.await
+        .map_err(|e| anyhow!("Failed to receive response: {}", e))
+}
+
+/// Finds the device picked by a [`DeviceSelector`] string, exact `node.name` unless prefixed.
+pub async fn get_device(selector: &str) -> Result<AudioDevice> {
+    let (input_devices, output_devices) = get_all_devices().await?;
+
+    DeviceSelector::parse(selector)
+        .select(input_devices.into_iter().chain(output_devices))
+        .map_err(|e| anyhow!(e))
 }
 
 pub struct PwTerminator {
     ids: Vec<u32>,
 }
 
+impl PwTerminator {
+    /// One terminator for several objects, destroyed in the given order
+    fn merge(terminators: impl IntoIterator<Item = PwTerminator>) -> Self {
+        PwTerminator {
+            ids: terminators
+                .into_iter()
+                .flat_map(|mut t| std::mem::take(&mut t.ids))
+                .collect(),
+        }
+    }
+}
+
 impl Drop for PwTerminator {
     fn drop(&mut self) {
         let manager = get_manager();
@@ -322,12 +981,41 @@ impl Drop for PwTerminator {
     }
 }
 
-pub async fn create_virtual_mic() -> Result<PwTerminator> {
+/// Creates the virtual mic node of a bus, removed again when dropped unless it lingers.
+///
+/// A lingering mic left behind by an earlier daemon is reused as it is.
+pub async fn create_virtual_mic(
+    bus: &BusSettings,
+    settings: AudioSettings,
+) -> Result<PwTerminator> {
+    let props = VirtualMicProps {
+        node_name: bus.node_name(),
+        description: bus.description.clone(),
+        channels: bus.layout(&settings),
+        icon: bus.icon.clone(),
+        linger: bus.linger,
+    };
+
+    if props.linger {
+        let (input_devices, _) = get_all_devices().await?;
+        if input_devices
+            .iter()
+            .any(|device| device.name == props.node_name)
+        {
+            println!("Reusing lingering virtual mic {}", props.node_name);
+            return Ok(PwTerminator { ids: vec![] });
+        }
+    }
+
     let (tx, rx) = oneshot::channel();
     let manager = get_manager();
     manager
         .sender
-        .send(PwCommand::CreateVirtualMic { resp: tx })
+        .send(PwCommand::CreateVirtualMic {
+            props,
+            settings,
+            resp: tx,
+        })
         .map_err(|_| anyhow!("Failed to send CreateVirtualMic to manager"))?;
 
     let res = rx
@@ -338,70 +1026,276 @@ pub async fn create_virtual_mic() -> Result<PwTerminator> {
     Ok(PwTerminator { ids: vec![id] })
 }
 
-pub async fn link_player_to_virtual_mic() -> Result<PwTerminator> {
-    let pwsp_daemon_output = match get_device("pwsp-daemon").await {
-        Ok(device) => device,
-        Err(_) => {
-            return Err(anyhow!(
-                "Could not find alsa_playback.pwsp-daemon device, skipping device linking"
-            ));
-        }
-    };
+/// Starts capturing from the node with the given `node.name`; samples stop when dropped.
+pub async fn create_capture_stream(
+    name: &str,
+    target: &str,
+    passive: bool,
+    format: StreamFormat,
+    info: Arc<StreamInfo>,
+    on_samples: SampleCallback,
+) -> Result<PwTerminator> {
+    let (tx, rx) = oneshot::channel();
+    let manager = get_manager();
+    manager
+        .sender
+        .send(PwCommand::CreateCaptureStream {
+            name: name.to_string(),
+            target: Some(target.to_string()),
+            passive,
+            format,
+            info,
+            on_samples,
+            resp: tx,
+        })
+        .map_err(|_| anyhow!("Failed to send CreateCaptureStream to manager"))?;
 
-    let pwsp_daemon_input = match get_device("pwsp-virtual-mic").await {
-        Ok(device) => device,
-        Err(_) => {
-            return Err(anyhow!(
-                "Could not find pwsp-virtual-mic device, skipping device linking"
-            ));
-        }
-    };
+    let res = rx
+        .await
+        .map_err(|e| anyhow!("Failed to receive response: {}", e))?;
 
-    let output_fl = match pwsp_daemon_output.output_fl {
-        Some(port) => port,
-        None => return Err(anyhow!("Failed to get pwsp-daemon output_fl")),
-    };
-    let output_fr = match pwsp_daemon_output.output_fr {
-        Some(port) => port,
-        None => return Err(anyhow!("Failed to get pwsp-daemon output_fr")),
-    };
-    let input_fl = match pwsp_daemon_input.input_fl {
-        Some(port) => port,
-        None => return Err(anyhow!("Failed to get pwsp-virtual-mic input_fl")),
-    };
-    let input_fr = match pwsp_daemon_input.input_fr {
-        Some(port) => port,
-        None => return Err(anyhow!("Failed to get pwsp-virtual-mic input_fr")),
-    };
+    let id = res.map_err(|e| anyhow!(e))?;
+    Ok(PwTerminator { ids: vec![id] })
+}
 
-    create_link(output_fl, output_fr, input_fl, input_fr).await
+/// A daemon playback stream, stopped when dropped.
+pub struct PlaybackStream {
+    /// Output ports in channel order
+    pub outputs: Vec<Port>,
+    pub info: Arc<StreamInfo>,
+    _terminator: PwTerminator,
 }
 
-pub async fn create_link(
-    output_fl: Port,
-    output_fr: Port,
-    input_fl: Port,
-    input_fr: Port,
-) -> Result<PwTerminator> {
+/// Starts an output stream that plays whatever `fill` writes and waits for its ports,
+/// so they can be linked right away.
+pub async fn create_playback_stream(
+    props: PlaybackProps,
+    fill: FillCallback,
+) -> Result<PlaybackStream> {
+    // A stream of the same name may still be on its way out
+    let stale = output_stream_ids(&props.node_name).await?;
+    let name = props.node_name.clone();
+    let channels = props.format.channels as usize;
+    let info = Arc::new(StreamInfo::default());
+
     let (tx, rx) = oneshot::channel();
     let manager = get_manager();
     manager
         .sender
-        .send(PwCommand::CreateLink {
-            output_fl,
-            output_fr,
-            input_fl,
-            input_fr,
+        .send(PwCommand::CreatePlaybackStream {
+            props,
+            info: info.clone(),
+            fill,
             resp: tx,
         })
-        .map_err(|_| anyhow!("Failed to send CreateLink to manager"))?;
+        .map_err(|_| anyhow!("Failed to send CreatePlaybackStream to manager"))?;
 
     let res = rx
         .await
         .map_err(|e| anyhow!("Failed to receive response: {}", e))?;
 
-    let (id_fl, id_fr) = res.map_err(|e| anyhow!(e))?;
-    Ok(PwTerminator {
-        ids: vec![id_fl, id_fr],
+    let id = res.map_err(|e| anyhow!(e))?;
+    let terminator = PwTerminator { ids: vec![id] };
+
+    let outputs = wait_for_output_ports(&name, &stale, channels).await?;
+    Ok(PlaybackStream {
+        outputs,
+        info,
+        _terminator: terminator,
     })
 }
+
+/// Ids of the output streams currently registered under exactly `name`.
+async fn output_stream_ids(name: &str) -> Result<Vec<u32>> {
+    let (_, outputs) = get_all_devices().await?;
+    Ok(outputs
+        .into_iter()
+        .filter(|device| device.name == name)
+        .map(|device| device.id)
+        .collect())
+}
+
+/// Waits for a freshly created stream to show up in the registry with all `channels` of
+/// its output ports, ignoring the `stale` nodes of the same name that are still being torn
+/// down.
+async fn wait_for_output_ports(name: &str, stale: &[u32], channels: usize) -> Result<Vec<Port>> {
+    for _ in 0..40 {
+        let (_, outputs) = get_all_devices().await?;
+        if let Some(ports) = outputs
+            .into_iter()
+            .filter(|device| device.name == name && !stale.contains(&device.id))
+            .map(|device| device.outputs)
+            .find(|ports| ports.len() >= channels.max(1))
+        {
+            return Ok(ports);
+        }
+        tokio::time::sleep(Duration::from_millis(25)).await;
+    }
+    Err(anyhow!("Timed out waiting for {} ports", name))
+}
+
+/// Same as [`wait_for_output_ports`] for the input ports of a capture stream.
+async fn wait_for_capture_ports(name: &str, stale: &[u32], channels: usize) -> Result<Vec<Port>> {
+    for _ in 0..40 {
+        if let Some(ports) = get_capture_streams()
+            .await?
+            .into_iter()
+            .filter(|stream| stream.name == name && !stale.contains(&stream.id))
+            .map(|stream| stream.inputs)
+            .find(|ports| ports.len() >= channels.max(1))
+        {
+            return Ok(ports);
+        }
+        tokio::time::sleep(Duration::from_millis(25)).await;
+    }
+    Err(anyhow!("Timed out waiting for {} ports", name))
+}
+
+/// Captures the given output ports of a device, in order, instead of letting PipeWire mix
+/// every channel of it down. Stops when dropped.
+pub async fn create_channel_capture(
+    name: &str,
+    ports: Vec<Port>,
+    format: StreamFormat,
+    info: Arc<StreamInfo>,
+    on_samples: SampleCallback,
+) -> Result<PwTerminator> {
+    if ports.is_empty() {
+        return Err(anyhow!("No ports to capture"));
+    }
+    let stale: Vec<u32> = get_capture_streams()
+        .await?
+        .into_iter()
+        .filter(|stream| stream.name == name)
+        .map(|stream| stream.id)
+        .collect();
+
+    let (tx, rx) = oneshot::channel();
+    let manager = get_manager();
+    manager
+        .sender
+        .send(PwCommand::CreateCaptureStream {
+            name: name.to_string(),
+            target: None,
+            passive: false,
+            format,
+            info,
+            on_samples,
+            resp: tx,
+        })
+        .map_err(|_| anyhow!("Failed to send CreateCaptureStream to manager"))?;
+
+    let res = rx
+        .await
+        .map_err(|e| anyhow!("Failed to receive response: {}", e))?;
+    let capture = PwTerminator {
+        ids: vec![res.map_err(|e| anyhow!(e))?],
+    };
+
+    let inputs = wait_for_capture_ports(name, &stale, format.channels as usize).await?;
+    let links = create_link(map_ports(&ports, &inputs)).await?;
+    Ok(PwTerminator::merge([links, capture]))
+}
+
+/// The player stream, heard on the default output and linked into the main bus. It renders
+/// the other buses as well.
+pub async fn create_player_stream(
+    format: StreamFormat,
+    fill: FillCallback,
+) -> Result<PlaybackStream> {
+    create_playback_stream(
+        PlaybackProps {
+            node_name: PLAYER_NODE.to_string(),
+            media_role: "Production".to_string(),
+            format,
+            autoconnect: true,
+            target: None,
+        },
+        fill,
+    )
+    .await
+}
+
+/// Links the output ports of a daemon stream to the input ports of the node named `target`.
+pub async fn link_stream_to(stream: &PlaybackStream, target: &str) -> Result<PwTerminator> {
+    link_ports_to(&stream.outputs, target).await
+}
+
+/// Links output ports, e.g. those of a microphone, to the input ports of the node named
+/// `target`.
+pub async fn link_ports_to(outputs: &[Port], target: &str) -> Result<PwTerminator> {
+    let (input_devices, _) = get_all_devices().await?;
+    let target_device = input_devices
+        .into_iter()
+        .find(|device| device.name == target)
+        .ok_or_else(|| anyhow!("Could not find {} device", target))?;
+    if target_device.inputs.is_empty() {
+        return Err(anyhow!("Failed to get {} input ports", target));
+    }
+
+    create_link(map_ports(outputs, &target_device.inputs)).await
+}
+
+/// The stream of a bus, linked to its virtual mic by the caller.
+pub async fn create_bus_stream(
+    bus: &str,
+    format: StreamFormat,
+    fill: FillCallback,
+) -> Result<PlaybackStream> {
+    create_playback_stream(
+        PlaybackProps {
+            node_name: bus_stream_name(bus),
+            media_role: "Communication".to_string(),
+            format,
+            autoconnect: false,
+            target: None,
+        },
+        fill,
+    )
+    .await
+}
+
+/// Links each output port to its input port, all or nothing.
+pub async fn create_link(pairs: Vec<(Port, Port)>) -> Result<PwTerminator> {
+    let (tx, rx) = oneshot::channel();
+    let manager = get_manager();
+    manager
+        .sender
+        .send(PwCommand::CreateLinks { pairs, resp: tx })
+        .map_err(|_| anyhow!("Failed to send CreateLinks to manager"))?;
+
+    let res = rx
+        .await
+        .map_err(|e| anyhow!("Failed to receive response: {}", e))?;
+
+    let ids = res.map_err(|e| anyhow!(e))?;
+    Ok(PwTerminator { ids })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ports_are_keyed_by_global_id() {
+        let mut state = AppState::new(None);
+        let mic = AudioDevice::new(1, None, None, Some("mic"), DeviceType::Input);
+        let stream = AudioDevice::new(2, None, None, Some("stream"), DeviceType::Capture);
+        state.input_devices.insert(mic.id, mic);
+        state.capture_streams.insert(stream.id, stream);
+
+        // Both nodes number their ports from 0
+        state.add_port(Port::new(10, 1, 0, "capture_MONO", None, None));
+        state.add_port(Port::new(20, 2, 0, "input_MONO", None, None));
+        assert_eq!(state.ports.len(), 2);
+        assert_eq!(state.input_devices[&1].outputs[0].id, 10);
+        assert_eq!(state.capture_streams[&2].inputs[0].id, 20);
+
+        state.remove_ports(10);
+        assert_eq!(state.ports.keys().collect::<Vec<_>>(), [&20]);
+        assert!(state.input_devices[&1].outputs.is_empty());
+        assert_eq!(state.capture_streams[&2].inputs.len(), 1);
+        state.remove_ports(2);
+        assert!(state.ports.is_empty());
+    }
+}